@@ -0,0 +1 @@
+ALTER TABLE players DROP COLUMN rcode_check_expiry;
@@ -0,0 +1 @@
+ALTER TABLE players ADD COLUMN rcode_check_expiry TIMESTAMP;
@@ -0,0 +1 @@
+ALTER TABLE players ADD COLUMN private BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,13 @@
+-- One row per leaderboard entry per day. board is 'all', 'legend' or 'char' (per character, see char_id).
+-- No reference to players, leaderboards include players we haven't seen a replay from yet.
+CREATE TABLE leaderboard_snapshots (
+    snapshot_date DATE NOT NULL,
+    board TEXT NOT NULL,
+    player_id BIGINT NOT NULL,
+    char_id SMALLINT NOT NULL,
+    rank INT NOT NULL,
+    name TEXT NOT NULL,
+    rating BIGINT NOT NULL,
+    PRIMARY KEY (snapshot_date, board, player_id, char_id)
+);
+CREATE INDEX leaderboard_snapshots_player ON leaderboard_snapshots(player_id, char_id);
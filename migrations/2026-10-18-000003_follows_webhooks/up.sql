@@ -0,0 +1,23 @@
+CREATE TABLE follows (
+    player_id BIGINT NOT NULL REFERENCES players(id),
+    followed_id BIGINT NOT NULL,
+    PRIMARY KEY (player_id, followed_id)
+);
+CREATE INDEX follows_followed_id ON follows(followed_id);
+
+CREATE TABLE webhooks (
+    player_id BIGINT NOT NULL PRIMARY KEY REFERENCES players(id),
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL
+);
+
+-- Pending deliveries, removed once delivered or out of attempts
+CREATE TABLE webhook_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    player_id BIGINT NOT NULL REFERENCES webhooks(player_id) ON DELETE CASCADE,
+    payload TEXT NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt TIMESTAMP NOT NULL,
+    last_error TEXT
+);
+CREATE INDEX webhook_deliveries_next_attempt ON webhook_deliveries(next_attempt);
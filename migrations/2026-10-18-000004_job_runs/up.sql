@@ -0,0 +1,10 @@
+-- One row per scheduled job run, success and finished stay null while it runs
+CREATE TABLE job_runs (
+    id BIGSERIAL PRIMARY KEY,
+    job TEXT NOT NULL,
+    started TIMESTAMP NOT NULL,
+    finished TIMESTAMP,
+    success BOOLEAN,
+    error TEXT
+);
+CREATE INDEX job_runs_job_started ON job_runs(job, started DESC);
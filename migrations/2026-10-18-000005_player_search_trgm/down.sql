@@ -0,0 +1,2 @@
+DROP INDEX player_names_name_trgm;
+DROP INDEX players_name_trgm;
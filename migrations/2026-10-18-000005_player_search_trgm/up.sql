@@ -0,0 +1,4 @@
+-- Fuzzy player search over current and previous names
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX players_name_trgm ON players USING gin (name gin_trgm_ops);
+CREATE INDEX player_names_name_trgm ON player_names USING gin (name gin_trgm_ops);
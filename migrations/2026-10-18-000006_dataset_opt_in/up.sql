@@ -0,0 +1 @@
+ALTER TABLE players ADD COLUMN dataset_opt_in BOOLEAN NOT NULL DEFAULT FALSE;
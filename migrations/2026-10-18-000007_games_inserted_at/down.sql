@@ -0,0 +1,2 @@
+DROP INDEX games_inserted_at;
+ALTER TABLE games DROP COLUMN inserted_at;
@@ -0,0 +1,7 @@
+-- When the row was written, unlike timestamp (the replay time) it only grows, so the
+-- incremental dump can pick up games backfilled long after they were played
+ALTER TABLE games ADD COLUMN inserted_at TIMESTAMP;
+UPDATE games SET inserted_at = timestamp;
+ALTER TABLE games ALTER COLUMN inserted_at SET DEFAULT (now() AT TIME ZONE 'UTC');
+ALTER TABLE games ALTER COLUMN inserted_at SET NOT NULL;
+CREATE INDEX games_inserted_at ON games(inserted_at);
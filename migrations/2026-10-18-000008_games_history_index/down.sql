@@ -0,0 +1,4 @@
+DROP INDEX games_id_char_time_a;
+DROP INDEX games_id_char_time_b;
+CREATE INDEX games_id_char_a ON games(id_a, char_a);
+CREATE INDEX games_id_char_b ON games(id_b, char_b);
@@ -0,0 +1,5 @@
+-- Player's games in time order, for history pages and the opponents' next games after them
+DROP INDEX games_id_char_a;
+DROP INDEX games_id_char_b;
+CREATE INDEX games_id_char_time_a ON games(id_a, char_a, (COALESCE(real_timestamp, timestamp)));
+CREATE INDEX games_id_char_time_b ON games(id_b, char_b, (COALESCE(real_timestamp, timestamp)));
@@ -0,0 +1,47 @@
+-- Before/after rating pairs from consecutive ranked games of the same player + character.
+-- Used to check src/rating.rs against what the game actually did.
+-- psql "$DATABASE_URL" -f scripts/mine_rating_pairs.sql
+-- Reads the week before the newest ranked game, so a given copy of the database always gives the same pairs.
+-- The pair column is a PAIRS line for src/rating.rs.
+WITH newest AS (
+    SELECT max(timestamp) AS timestamp
+    FROM games
+    WHERE game_floor = 0
+),
+sides AS (
+    SELECT timestamp, id_a AS id, char_a AS char_id, value_a AS value, value_b AS opponent_value, winner = 1 AS won
+    FROM games
+    WHERE game_floor = 0
+    UNION ALL
+    SELECT timestamp, id_b AS id, char_b AS char_id, value_b AS value, value_a AS opponent_value, winner = 2 AS won
+    FROM games
+    WHERE game_floor = 0
+),
+pairs AS (
+    SELECT
+        value AS before,
+        opponent_value,
+        won,
+        LEAD(value) OVER (PARTITION BY id, char_id ORDER BY timestamp) AS after,
+        LEAD(timestamp) OVER (PARTITION BY id, char_id ORDER BY timestamp) - timestamp AS gap,
+        timestamp,
+        id
+    FROM sides
+    WHERE timestamp > (SELECT timestamp FROM newest) - interval '1 week'
+)
+SELECT
+    before,
+    opponent_value,
+    won,
+    after,
+    format('(%s.0, %s.0, %s, %s.0),', before, opponent_value, won::text, after) AS pair
+FROM pairs
+WHERE after IS NOT NULL
+AND gap < interval '10 minutes'
+AND before > 0
+AND opponent_value > 0
+-- Skip promotions/demotions between LP and MR
+AND (before >= 10000000) = (after >= 10000000)
+AND (before >= 10000000) = (opponent_value >= 10000000)
+ORDER BY timestamp, id
+LIMIT 50;
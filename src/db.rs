@@ -1,5 +1,6 @@
 use std::collections::{HashMap, HashSet};
 
+use crate::error::Error;
 use crate::models::{self, Player, PlayerRating};
 use crate::pull::Matchup;
 use crate::{schema, CHAR_NAMES};
@@ -7,12 +8,20 @@ use diesel::sql_types::{BigInt, Integer, Timestamp};
 use diesel::{prelude::*, update};
 use diesel_async::RunQueryDsl;
 
+/// `not_found` when the query had no row, otherwise what went wrong in Postgres
+fn query_error(e: diesel::result::Error, not_found: &str) -> Error {
+    match e {
+        diesel::result::Error::NotFound => Error::NotFound(not_found.to_string()),
+        e => Error::from(e),
+    }
+}
+
 pub async fn set_player_rating(
     id: i64,
     char_id: i16,
     value: i64,
     db: &mut crate::Connection<'_>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     match diesel::update(schema::player_ratings::table)
         .filter(schema::player_ratings::id.eq(id))
         .filter(schema::player_ratings::char_id.eq(char_id))
@@ -21,25 +30,24 @@ pub async fn set_player_rating(
         .await
     {
         Ok(_) => Ok(()),
-        Err(e) => Err(format!("Error setting player rating: {}", e)),
+        Err(e) => Err(Error::from(e).context("Error setting player rating")),
     }
 }
 
 async fn get_player_char_and_rating(
     id: i64,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<(Player, PlayerRating)>, String> {
+) -> Result<Vec<(Player, PlayerRating)>, Error> {
     let player_char: Vec<(Player, PlayerRating)> = schema::players::table
         .inner_join(schema::player_ratings::table)
         .filter(schema::players::id.eq(id))
         .select((Player::as_select(), PlayerRating::as_select()))
         .order(schema::player_ratings::value.desc())
         .load(db)
-        .await
-        .expect("Error loading player");
+        .await?;
 
     if player_char.len() == 0 {
-        return Err("Player not found".to_string());
+        return Err(Error::NotFound("Player not found".to_string()));
     }
 
     Ok(player_char.clone())
@@ -50,7 +58,7 @@ async fn get_match_count(
     id: i64,
     char_id: i16,
     db: &mut crate::Connection<'_>,
-) -> Result<i64, String> {
+) -> Result<i64, Error> {
     match schema::games::table
         .filter(
             schema::games::id_a
@@ -67,7 +75,7 @@ async fn get_match_count(
         .await
     {
         Ok(count) => Ok(count),
-        Err(_) => Err("Match count not found".to_string()),
+        Err(e) => Err(query_error(e, "Match count not found")),
     }
 }
 
@@ -84,7 +92,7 @@ async fn get_top_defeated(
         i16,
         i64,
     )>,
-    String,
+    Error,
 > {
     match schema::games::table
         .select((
@@ -133,7 +141,7 @@ async fn get_top_defeated(
         .await
     {
         Ok(top_defeated) => Ok(top_defeated),
-        Err(_) => Err("Top defeated not found".to_string()),
+        Err(e) => Err(query_error(e, "Top defeated not found")),
     }
 }
 
@@ -141,7 +149,7 @@ async fn get_top_rating(
     id: i64,
     char_id: i16,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<(chrono::NaiveDateTime, i64)>, String> {
+) -> Result<Vec<(chrono::NaiveDateTime, i64)>, Error> {
     match schema::games::table
         .select((
             schema::games::timestamp,
@@ -175,14 +183,14 @@ async fn get_top_rating(
         .await
     {
         Ok(top_rating) => Ok(top_rating),
-        Err(_) => Err("Top rating not found".to_string()),
+        Err(e) => Err(query_error(e, "Top rating not found")),
     }
 }
 
 async fn get_tags(
     id: i64,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<(String, String)>, String> {
+) -> Result<Vec<(String, String)>, Error> {
     match schema::tags::table
         .select((schema::tags::tag, schema::tags::style))
         .filter(schema::tags::player_id.eq(id))
@@ -190,14 +198,14 @@ async fn get_tags(
         .await
     {
         Ok(tags) => Ok(tags),
-        Err(_) => Err("Tags not found".to_string()),
+        Err(e) => Err(query_error(e, "Tags not found")),
     }
 }
 
 pub async fn get_tags_from_player_list(
     ids: HashSet<i64>,
     db: &mut crate::Connection<'_>,
-) -> Result<HashMap<i64, Vec<(String, String)>>, String> {
+) -> Result<HashMap<i64, Vec<(String, String)>>, Error> {
     let tags = match schema::tags::table
         .select((
             schema::tags::player_id,
@@ -209,7 +217,7 @@ pub async fn get_tags_from_player_list(
         .await
     {
         Ok(tags) => tags,
-        Err(_) => return Err("Tags not found".to_string()),
+        Err(e) => return Err(query_error(e, "Tags not found")),
     };
 
     let mut result = HashMap::new();
@@ -236,13 +244,11 @@ pub async fn get_player_response_data(
         i32,
         Vec<(String, String)>,
     ),
-    String,
+    Error,
 > {
     let player_char = match get_player_char_and_rating(id, db).await {
         Ok(player_char) => player_char,
-        Err(_) => {
-            return Err("Player not found".to_string());
-        }
+        Err(e) => return Err(e),
     };
 
     let mut match_counts = HashMap::new();
@@ -336,73 +342,239 @@ pub async fn get_player_response_data(
     ))
 }
 
+/// `id`'s performance on each character over ranked games since `since`
+pub async fn get_performance(
+    id: i64,
+    since: chrono::NaiveDateTime,
+    db: &mut crate::Connection<'_>,
+) -> Result<HashMap<i16, crate::handlers::performance::Performance>, Error> {
+    use schema::games::dsl::*;
+
+    let rows = match games
+        .select((id_a, char_a, char_b, value_a, value_b, winner))
+        .filter(id_a.eq(id).or(id_b.eq(id)))
+        .filter(timestamp.gt(since))
+        .filter(game_floor.eq(0)) // Only ranked matches
+        .load::<(i64, i16, i16, i64, i64, i16)>(db)
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => return Err(query_error(e, "Games not found")),
+    };
+
+    let mut performance: HashMap<i16, crate::handlers::performance::Performance> = HashMap::new();
+    for (player_a, char_id_a, char_id_b, rating_a, rating_b, won_by) in rows {
+        if player_a == id {
+            performance.entry(char_id_a).or_default().add(rating_a, rating_b, won_by == 1);
+        } else {
+            performance.entry(char_id_b).or_default().add(rating_b, rating_a, won_by == 2);
+        }
+    }
+
+    Ok(performance)
+}
+
+/// A game from `get_games` with the ratings both sides had going into their next game on the same
+/// character, their current rating if there is none yet
+#[derive(QueryableByName, Clone)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct HistoryGame {
+    #[diesel(embed)]
+    pub game: models::Game,
+    #[diesel(sql_type = diesel::sql_types::Nullable<BigInt>)]
+    pub own_rating_after: Option<i64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<BigInt>)]
+    pub opponent_rating_after: Option<i64>,
+}
+
+impl std::borrow::Borrow<models::Game> for HistoryGame {
+    fn borrow(&self) -> &models::Game {
+        &self.game
+    }
+}
+
+/// `id`'s games on `char_id`, newest first. The rating after each game is the one on the next game,
+/// LEAD over the page and the game after it, and the opponent's on their next game on the same
+/// character, LEAD over the opponents' games since the oldest game on the page.
 pub async fn get_games(
     id: i64,
     char_id: i16,
     count: i64,
     offset: i64,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<models::Game>, String> {
-    match schema::games::table
-        .filter(
-            (schema::games::id_a
-                .eq(id)
-                .and(schema::games::char_a.eq(char_id)))
-            .or(schema::games::id_b
-                .eq(id)
-                .and(schema::games::char_b.eq(char_id))),
-        )
-        .select(models::Game::as_select())
-        .order(
-            crate::pull::coalesce(schema::games::real_timestamp, schema::games::timestamp).desc(),
+) -> Result<Vec<HistoryGame>, Error> {
+    get_history_games(id, char_id, None, count, offset, true, db).await
+}
+
+/// Up to `count` of `id`'s games on `char_id` since `since`, newest first, like `get_games` but
+/// without `opponent_rating_after`, which reads the opponents' games
+pub async fn get_games_since(
+    id: i64,
+    char_id: i16,
+    since: chrono::NaiveDateTime,
+    count: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<HistoryGame>, Error> {
+    get_history_games(id, char_id, Some(since), count, 0, false, db).await
+}
+
+async fn get_history_games(
+    id: i64,
+    char_id: i16,
+    since: Option<chrono::NaiveDateTime>,
+    count: i64,
+    offset: i64,
+    opponent_after: bool,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<HistoryGame>, Error> {
+    match diesel::sql_query(
+        "
+        WITH history AS (
+            -- The page and, past the first page, the game after it
+            SELECT g.*,
+                CASE WHEN g.id_a = $1 AND g.char_a = $2 THEN g.id_b ELSE g.id_a END opponent_id,
+                CASE WHEN g.id_a = $1 AND g.char_a = $2 THEN g.char_b ELSE g.char_a END opponent_char,
+                CASE WHEN g.id_a = $1 AND g.char_a = $2 THEN g.value_a ELSE g.value_b END own_value
+            FROM games g
+            WHERE ((g.id_a = $1 AND g.char_a = $2) OR (g.id_b = $1 AND g.char_b = $2))
+            AND ($5::timestamp IS NULL OR COALESCE(g.real_timestamp, g.timestamp) >= $5)
+            ORDER BY COALESCE(g.real_timestamp, g.timestamp) DESC
+            LIMIT $3 + LEAST($4, 1)
+            OFFSET GREATEST($4 - 1, 0)
+        ),
+        page AS (
+            SELECT *, LEAD(own_value) OVER (ORDER BY COALESCE(real_timestamp, timestamp)) own_rating_after
+            FROM history
+            ORDER BY COALESCE(real_timestamp, timestamp) DESC
+            OFFSET LEAST($4, 1)
+        ),
+        opponents AS (
+            SELECT DISTINCT opponent_id, opponent_char FROM page WHERE $6
+        ),
+        opponent_games AS (
+            SELECT n.timestamp, n.id_a, n.id_b, o.opponent_id, o.opponent_char,
+                LEAD(CASE WHEN n.id_a = o.opponent_id AND n.char_a = o.opponent_char THEN n.value_a ELSE n.value_b END)
+                    OVER (PARTITION BY o.opponent_id, o.opponent_char ORDER BY COALESCE(n.real_timestamp, n.timestamp))
+                    rating_after
+            FROM opponents o
+            JOIN games n ON (n.id_a = o.opponent_id AND n.char_a = o.opponent_char)
+                OR (n.id_b = o.opponent_id AND n.char_b = o.opponent_char)
+            WHERE COALESCE(n.real_timestamp, n.timestamp) >= (SELECT MIN(COALESCE(real_timestamp, timestamp)) FROM page)
         )
-        .limit(count)
-        .offset(offset)
-        .load(db)
-        .await
+        SELECT h.timestamp, h.real_timestamp, h.id_a, h.name_a, h.char_a, h.platform_a,
+            h.id_b, h.name_b, h.char_b, h.platform_b, h.winner, h.game_floor, h.value_a, h.value_b,
+            COALESCE(
+                h.own_rating_after,
+                (SELECT value FROM player_ratings WHERE id = $1 AND char_id = $2)
+            ) own_rating_after,
+            CASE WHEN $6 THEN COALESCE(n.rating_after, r.value) END opponent_rating_after
+        FROM page h
+        LEFT JOIN opponent_games n ON n.timestamp = h.timestamp AND n.id_a = h.id_a AND n.id_b = h.id_b
+            AND n.opponent_id = h.opponent_id AND n.opponent_char = h.opponent_char
+        LEFT JOIN player_ratings r ON r.id = h.opponent_id AND r.char_id = h.opponent_char
+        ORDER BY COALESCE(h.real_timestamp, h.timestamp) DESC;
+        ",
+    )
+    .bind::<BigInt, _>(id)
+    .bind::<diesel::sql_types::SmallInt, _>(char_id)
+    .bind::<BigInt, _>(count)
+    .bind::<BigInt, _>(offset)
+    .bind::<diesel::sql_types::Nullable<Timestamp>, _>(since)
+    .bind::<diesel::sql_types::Bool, _>(opponent_after)
+    .load::<HistoryGame>(db)
+    .await
     {
         Ok(games) => Ok(games),
-        Err(_) => Err("Games not found".to_string()),
+        Err(e) => Err(query_error(e, "Games not found")),
     }
 }
 
+#[derive(QueryableByName)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SearchResult {
+    #[diesel(sql_type = BigInt)]
+    pub id: i64,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub name: String,
+    /// The current or previous name that matched
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub matched_name: String,
+    #[diesel(sql_type = diesel::sql_types::SmallInt)]
+    pub char_id: i16,
+    #[diesel(sql_type = BigInt)]
+    pub value: i64,
+}
+
+/// Players with a current or previous name containing the search string, or close to it by
+/// trigram similarity unless `exact`, best match first, then highest rated
 pub async fn find_player(
-    search_params: crate::handlers::search::SearchParams,
+    filters: &crate::handlers::search::SearchFilters,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<(Player, PlayerRating)>, String> {
-    let count = 100;
-    let offset = 0;
-
-    let exact_like = if search_params.exact.unwrap_or(false) {
-        format!("{}", search_params.search_string)
+) -> Result<Vec<SearchResult>, Error> {
+    let pattern = if filters.exact {
+        filters.search.clone()
     } else {
-        format!("%{}%", search_params.search_string)
+        format!("%{}%", filters.search)
     };
 
-    match schema::players::table
-        .inner_join(
-            schema::player_ratings::table.on(schema::player_ratings::id.eq(schema::players::id)),
+    match diesel::sql_query(
+        "
+        WITH matches AS (
+            SELECT DISTINCT ON (id) id, name, similarity(name, $1) + (name ILIKE $2)::int AS score
+            FROM (
+                SELECT id, name FROM players WHERE name ILIKE $2 OR (NOT $3 AND name % $1)
+                UNION
+                SELECT id, name FROM player_names WHERE name ILIKE $2 OR (NOT $3 AND name % $1)
+            ) names
+            ORDER BY id, score DESC
         )
-        .select((Player::as_select(), PlayerRating::as_select()))
-        .filter(schema::players::name.ilike(exact_like))
-        .order(schema::player_ratings::value.desc())
-        .limit(count)
-        .offset(offset)
-        .load(db)
-        .await
+        SELECT p.id, p.name, m.name matched_name, r.char_id, r.value
+        FROM matches m
+        JOIN players p ON p.id = m.id
+        JOIN player_ratings r ON r.id = p.id
+        WHERE NOT p.private
+        AND ($4::smallint IS NULL OR r.char_id = $4)
+        AND ($5::smallint IS NULL OR p.platform = $5)
+        AND ($6::bigint IS NULL OR r.value >= $6)
+        AND ($7::bigint IS NULL OR r.value <= $7)
+        ORDER BY m.score DESC, r.value DESC, p.id, r.char_id
+        LIMIT $8
+        OFFSET $9;
+        ",
+    )
+    .bind::<diesel::sql_types::Text, _>(&filters.search)
+    .bind::<diesel::sql_types::Text, _>(pattern)
+    .bind::<diesel::sql_types::Bool, _>(filters.exact)
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::SmallInt>, _>(filters.char_id)
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::SmallInt>, _>(filters.platform)
+    .bind::<diesel::sql_types::Nullable<BigInt>, _>(filters.min_rating)
+    .bind::<diesel::sql_types::Nullable<BigInt>, _>(filters.max_rating)
+    .bind::<BigInt, _>(filters.count)
+    .bind::<BigInt, _>(filters.offset)
+    .load::<SearchResult>(db)
+    .await
     {
-        Ok(player) => Ok(player),
-        Err(_) => Err("Player not found".to_string()),
+        Ok(results) => Ok(results),
+        Err(e) => Err(query_error(e, "Player not found")),
     }
 }
 
+/// Leaves a code that hasn't expired yet alone, false if there was one or the player doesn't exist
 pub async fn set_claim_code(
     id: i64,
     code: &str,
+    expiry: chrono::NaiveDateTime,
     db: &mut crate::Connection<'_>,
-) -> Result<bool, String> {
-    match update(schema::players::table.filter(schema::players::id.eq(id)))
-        .set(schema::players::rcode_check_code.eq(code))
+) -> Result<bool, Error> {
+    let expired = schema::players::rcode_check_expiry
+        .is_null()
+        .or(schema::players::rcode_check_expiry.le(chrono::Utc::now().naive_utc()));
+
+    match update(schema::players::table.filter(schema::players::id.eq(id)).filter(expired))
+        .set((
+            schema::players::rcode_check_code.eq(code),
+            schema::players::rcode_check_expiry.eq(expiry),
+        ))
         .execute(db)
         .await
     {
@@ -413,29 +585,63 @@ pub async fn set_claim_code(
                 Ok(false)
             }
         }
-        Err(_) => Err("Error setting claim code".to_string()),
+        Err(e) => Err(query_error(e, "Error setting claim code")),
     }
 }
 
-pub async fn get_claim_code(id: i64, db: &mut crate::Connection<'_>) -> Result<String, String> {
+pub async fn get_claim_code(id: i64, db: &mut crate::Connection<'_>) -> Result<String, Error> {
     match schema::players::table
-        .select(schema::players::rcode_check_code)
+        .select((
+            schema::players::rcode_check_code,
+            schema::players::rcode_check_expiry,
+        ))
         .filter(schema::players::id.eq(id))
-        .first::<Option<String>>(db)
+        .first::<(Option<String>, Option<chrono::NaiveDateTime>)>(db)
         .await
     {
-        Ok(code) => {
-            if let Some(code) = code {
+        Ok((Some(code), Some(expiry))) => {
+            if expiry > chrono::Utc::now().naive_utc() {
                 Ok(code)
             } else {
-                Err("Claim code not found".to_string())
+                Err(Error::NotFound("Claim code expired".to_string()))
             }
         }
-        Err(_) => Err("Error getting claim code".to_string()),
+        Ok(_) => Err(Error::NotFound("Claim code not found".to_string())),
+        Err(e) => Err(query_error(e, "Error getting claim code")),
     }
 }
 
-pub async fn get_player_api_key(id: i64, db: &mut crate::Connection<'_>) -> Result<String, String> {
+/// Expiry of the player's claim code if one hasn't expired yet
+pub async fn get_claim_code_expiry(
+    id: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Option<chrono::NaiveDateTime>, Error> {
+    match schema::players::table
+        .select(schema::players::rcode_check_expiry)
+        .filter(schema::players::id.eq(id))
+        .first::<Option<chrono::NaiveDateTime>>(db)
+        .await
+    {
+        Ok(expiry) => Ok(expiry.filter(|expiry| *expiry > chrono::Utc::now().naive_utc())),
+        Err(e) => Err(query_error(e, "Player not found")),
+    }
+}
+
+pub async fn clear_claim_code(id: i64, db: &mut crate::Connection<'_>) -> Result<(), Error> {
+    match update(schema::players::table.filter(schema::players::id.eq(id)))
+        .set((
+            schema::players::rcode_check_code.eq(None::<String>),
+            schema::players::rcode_check_expiry.eq(None::<chrono::NaiveDateTime>),
+        ))
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(query_error(e, "Error clearing claim code")),
+    }
+}
+
+pub async fn get_player_api_key(id: i64, db: &mut crate::Connection<'_>) -> Result<String, Error> {
     Ok(match schema::players::table
         .select(schema::players::api_key)
         .filter(schema::players::id.eq(id))
@@ -453,16 +659,16 @@ pub async fn get_player_api_key(id: i64, db: &mut crate::Connection<'_>) -> Resu
                         .await
                     {
                         Ok(updated_row_count) => updated_row_count,
-                        Err(_) => panic!("Error updating player"),
+                        Err(e) => return Err(e.into()),
                     };
 
                 if updated_row_count == 0 {
-                    return Err("Player not found".to_string());
+                    return Err(Error::NotFound("Player not found".to_string()));
                 }
                 Some(key)
             }
         },
-        Err(_) => panic!("Error updating player"),
+        Err(e) => return Err(query_error(e, "Player not found")),
     }
     .unwrap())
 }
@@ -470,21 +676,152 @@ pub async fn get_player_api_key(id: i64, db: &mut crate::Connection<'_>) -> Resu
 pub async fn get_player_id_and_name_using_key(
     key: String,
     db: &mut crate::Connection<'_>,
-) -> Result<(i64, String), String> {
+) -> Result<(i64, String, bool), Error> {
     Ok(
         match schema::players::table
-            .select((schema::players::id, schema::players::name))
+            .select((
+                schema::players::id,
+                schema::players::name,
+                schema::players::private,
+            ))
             .filter(schema::players::api_key.eq(key.clone()))
-            .first::<(i64, String)>(db)
+            .first::<(i64, String, bool)>(db)
             .await
         {
             Ok(id_name) => id_name,
-            Err(_) => return Err("Player not found".to_string()),
+            Err(e) => return Err(query_error(e, "Player not found")),
         },
     )
 }
 
-pub async fn get_aliases(id: i64, db: &mut crate::Connection<'_>) -> Result<Vec<String>, String> {
+pub async fn toggle_private(key: String, db: &mut crate::Connection<'_>) -> Result<bool, Error> {
+    match update(schema::players::table.filter(schema::players::api_key.eq(key)))
+        .set(schema::players::private.eq(diesel::dsl::not(schema::players::private)))
+        .returning(schema::players::private)
+        .get_result::<bool>(db)
+        .await
+    {
+        Ok(private) => Ok(private),
+        Err(e) => Err(query_error(e, "Player not found")),
+    }
+}
+
+pub async fn toggle_dataset_opt_in(key: String, db: &mut crate::Connection<'_>) -> Result<bool, Error> {
+    match update(schema::players::table.filter(schema::players::api_key.eq(key)))
+        .set(schema::players::dataset_opt_in.eq(diesel::dsl::not(schema::players::dataset_opt_in)))
+        .returning(schema::players::dataset_opt_in)
+        .get_result::<bool>(db)
+        .await
+    {
+        Ok(opt_in) => Ok(opt_in),
+        Err(e) => Err(query_error(e, "Player not found")),
+    }
+}
+
+pub async fn is_dataset_opt_in(id: i64, db: &mut crate::Connection<'_>) -> Result<bool, Error> {
+    match schema::players::table
+        .select(schema::players::dataset_opt_in)
+        .filter(schema::players::id.eq(id))
+        .first::<bool>(db)
+        .await
+    {
+        Ok(opt_in) => Ok(opt_in),
+        Err(e) => Err(query_error(e, "Player not found")),
+    }
+}
+
+/// Players whose id and names may go in the public dump: opted in and not private
+pub async fn get_dataset_players(db: &mut crate::Connection<'_>) -> Result<HashSet<i64>, Error> {
+    match schema::players::table
+        .select(schema::players::id)
+        .filter(schema::players::dataset_opt_in.eq(true))
+        .filter(schema::players::private.eq(false))
+        .load::<i64>(db)
+        .await
+    {
+        Ok(ids) => Ok(ids.into_iter().collect()),
+        Err(e) => Err(query_error(e, "Players not found")),
+    }
+}
+
+/// Every player who set their profile private
+pub async fn get_all_private_players(db: &mut crate::Connection<'_>) -> Result<HashSet<i64>, Error> {
+    match schema::players::table
+        .select(schema::players::id)
+        .filter(schema::players::private.eq(true))
+        .load::<i64>(db)
+        .await
+    {
+        Ok(ids) => Ok(ids.into_iter().collect()),
+        Err(e) => Err(query_error(e, "Players not found")),
+    }
+}
+
+/// Up to `count` (id, name, platform) after `after_id`, by id
+pub async fn get_players_after(
+    after_id: i64,
+    count: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<(i64, String, i16)>, Error> {
+    match schema::players::table
+        .select((schema::players::id, schema::players::name, schema::players::platform))
+        .filter(schema::players::id.gt(after_id))
+        .order(schema::players::id.asc())
+        .limit(count)
+        .load(db)
+        .await
+    {
+        Ok(players) => Ok(players),
+        Err(e) => Err(query_error(e, "Players not found")),
+    }
+}
+
+/// Every (id, name) in `player_names` for `ids`
+pub async fn get_names_of_players(
+    ids: &[i64],
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<(i64, String)>, Error> {
+    match schema::player_names::table
+        .select((schema::player_names::id, schema::player_names::name))
+        .filter(schema::player_names::id.eq_any(ids))
+        .order((schema::player_names::id.asc(), schema::player_names::name.asc()))
+        .load(db)
+        .await
+    {
+        Ok(names) => Ok(names),
+        Err(e) => Err(query_error(e, "Player names not found")),
+    }
+}
+
+pub async fn is_player_private(id: i64, db: &mut crate::Connection<'_>) -> Result<bool, Error> {
+    match schema::players::table
+        .select(schema::players::private)
+        .filter(schema::players::id.eq(id))
+        .first::<bool>(db)
+        .await
+    {
+        Ok(private) => Ok(private),
+        Err(e) => Err(query_error(e, "Player not found")),
+    }
+}
+
+pub async fn get_private_players(
+    ids: HashSet<i64>,
+    db: &mut crate::Connection<'_>,
+) -> Result<HashSet<i64>, Error> {
+    match schema::players::table
+        .select(schema::players::id)
+        .filter(schema::players::id.eq_any(ids))
+        .filter(schema::players::private.eq(true))
+        .load::<i64>(db)
+        .await
+    {
+        Ok(ids) => Ok(ids.into_iter().collect()),
+        Err(e) => Err(query_error(e, "Private players not found")),
+    }
+}
+
+pub async fn get_aliases(id: i64, db: &mut crate::Connection<'_>) -> Result<Vec<String>, Error> {
     match schema::player_names::table
         .select(schema::player_names::name)
         .filter(schema::player_names::id.eq(id))
@@ -493,7 +830,7 @@ pub async fn get_aliases(id: i64, db: &mut crate::Connection<'_>) -> Result<Vec<
         .await
     {
         Ok(aliases) => Ok(aliases),
-        Err(_) => Err("Aliases not found".to_string()),
+        Err(e) => Err(query_error(e, "Aliases not found")),
     }
 }
 
@@ -511,7 +848,7 @@ pub async fn get_ratings(
     duration: i32,
     pre_vanquisher: bool,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<RatingResult>, String> {
+) -> Result<Vec<RatingResult>, Error> {
     //TODO when positional_order_by + limit is released, change this to ORM query.
 
     // Check if they're Vanq, if they are, only return DR
@@ -583,7 +920,7 @@ pub async fn get_ratings(
         .await
     {
         Ok(results) => Ok(results),
-        Err(_) => return Err("Ratings not found".to_string()),
+        Err(e) => return Err(query_error(e, "Ratings not found")),
     }
 }
 
@@ -592,7 +929,7 @@ pub async fn get_matchups(
     char_id: i16,
     duration: i32,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<Matchup>, String> {
+) -> Result<Vec<Matchup>, Error> {
     let results = diesel::sql_query(
         "
     SELECT 
@@ -634,11 +971,11 @@ pub async fn get_matchups(
         .await
     {
         Ok(results) => Ok(results),
-        Err(_) => return Err("Matchups not found".to_string()),
+        Err(e) => return Err(query_error(e, "Matchups not found")),
     }
 }
 
-pub async fn get_supporters(db: &mut crate::Connection<'_>) -> Result<Vec<(i64, String)>, String> {
+pub async fn get_supporters(db: &mut crate::Connection<'_>) -> Result<Vec<(i64, String)>, Error> {
     match schema::tags::table
         .inner_join(schema::players::table.on(schema::tags::player_id.eq(schema::players::id)))
         .select((schema::tags::player_id, schema::players::name))
@@ -647,11 +984,11 @@ pub async fn get_supporters(db: &mut crate::Connection<'_>) -> Result<Vec<(i64,
         .await
     {
         Ok(supporters) => Ok(supporters),
-        Err(_) => Err("Supporters not found".to_string()),
+        Err(e) => Err(query_error(e, "Supporters not found")),
     }
 }
 
-pub async fn player_exists(db: &mut crate::Connection<'_>, player_id: i64) -> Result<bool, String> {
+pub async fn player_exists(db: &mut crate::Connection<'_>, player_id: i64) -> Result<bool, Error> {
     let exists = match schema::players::table
         .filter(schema::players::id.eq(player_id))
         .count()
@@ -659,8 +996,598 @@ pub async fn player_exists(db: &mut crate::Connection<'_>, player_id: i64) -> Re
         .await
     {
         Ok(count) => Ok(count > 0),
-        Err(_) => Err("Player not found".to_string()),
+        Err(e) => Err(query_error(e, "Player not found")),
     };
 
     exists
 }
+
+/// For the health check
+pub async fn ping(db: &mut crate::Connection<'_>) -> Result<(), Error> {
+    diesel::sql_query("SELECT 1")
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(Error::from)
+}
+
+pub async fn get_rank_history(
+    player_id: i64,
+    char_id: i16,
+    since: chrono::NaiveDate,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::LeaderboardSnapshot>, Error> {
+    match schema::leaderboard_snapshots::table
+        .filter(schema::leaderboard_snapshots::player_id.eq(player_id))
+        .filter(schema::leaderboard_snapshots::char_id.eq(char_id))
+        .filter(schema::leaderboard_snapshots::board.eq("char"))
+        .filter(schema::leaderboard_snapshots::snapshot_date.ge(since))
+        .order(schema::leaderboard_snapshots::snapshot_date.asc())
+        .load::<models::LeaderboardSnapshot>(db)
+        .await
+    {
+        Ok(history) => Ok(history),
+        Err(e) => Err(query_error(e, "Rank history not found")),
+    }
+}
+
+/// The two most recent snapshot dates of a leaderboard, newest first.
+/// `char_id` is only used for the per character board.
+pub async fn get_latest_snapshot_dates(
+    board: &str,
+    char_id: i16,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<chrono::NaiveDate>, Error> {
+    let mut query = schema::leaderboard_snapshots::table
+        .select(schema::leaderboard_snapshots::snapshot_date)
+        .filter(schema::leaderboard_snapshots::board.eq(board))
+        .distinct()
+        .order(schema::leaderboard_snapshots::snapshot_date.desc())
+        .limit(2)
+        .into_boxed();
+
+    if board == "char" {
+        query = query.filter(schema::leaderboard_snapshots::char_id.eq(char_id));
+    }
+
+    match query.load::<chrono::NaiveDate>(db).await {
+        Ok(dates) => Ok(dates),
+        Err(e) => Err(query_error(e, "Leaderboard snapshots not found")),
+    }
+}
+
+pub async fn get_leaderboard_snapshot(
+    board: &str,
+    char_id: i16,
+    snapshot_date: chrono::NaiveDate,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::LeaderboardSnapshot>, Error> {
+    let mut query = schema::leaderboard_snapshots::table
+        .filter(schema::leaderboard_snapshots::board.eq(board))
+        .filter(schema::leaderboard_snapshots::snapshot_date.eq(snapshot_date))
+        .order(schema::leaderboard_snapshots::rank.asc())
+        .into_boxed();
+
+    if board == "char" {
+        query = query.filter(schema::leaderboard_snapshots::char_id.eq(char_id));
+    }
+
+    match query.load::<models::LeaderboardSnapshot>(db).await {
+        Ok(snapshot) => Ok(snapshot),
+        Err(e) => Err(query_error(e, "Leaderboard snapshot not found")),
+    }
+}
+
+/// Ranks on the snapshot before the latest one, keyed by (player id, char id).
+/// The latest snapshot is what's currently in Redis.
+pub async fn get_previous_ranks(
+    board: &str,
+    char_id: i16,
+    db: &mut crate::Connection<'_>,
+) -> Result<HashMap<(i64, i64), i64>, Error> {
+    let dates = get_latest_snapshot_dates(board, char_id, db).await?;
+
+    let previous_date = match dates.get(1) {
+        Some(date) => *date,
+        None => return Ok(HashMap::new()),
+    };
+
+    Ok(get_leaderboard_snapshot(board, char_id, previous_date, db)
+        .await?
+        .into_iter()
+        .map(|s| ((s.player_id, s.char_id as i64), s.rank as i64))
+        .collect())
+}
+
+pub async fn get_follows(
+    player_id: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<(i64, String, bool)>, Error> {
+    match schema::follows::table
+        .inner_join(schema::players::table.on(schema::follows::followed_id.eq(schema::players::id)))
+        .select((schema::players::id, schema::players::name, schema::players::private))
+        .filter(schema::follows::player_id.eq(player_id))
+        .order(schema::players::name.asc())
+        .load::<(i64, String, bool)>(db)
+        .await
+    {
+        Ok(follows) => Ok(follows),
+        Err(e) => Err(query_error(e, "Follows not found")),
+    }
+}
+
+pub async fn add_follow(
+    player_id: i64,
+    followed_id: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<(), Error> {
+    let count = match schema::follows::table
+        .filter(schema::follows::player_id.eq(player_id))
+        .count()
+        .get_result::<i64>(db)
+        .await
+    {
+        Ok(count) => count,
+        Err(e) => return Err(query_error(e, "Follows not found")),
+    };
+
+    if count >= crate::handlers::webhook::MAX_FOLLOWS {
+        return Err(Error::BadInput(format!(
+            "Can't follow more than {} players",
+            crate::handlers::webhook::MAX_FOLLOWS
+        )));
+    }
+
+    match diesel::insert_into(schema::follows::table)
+        .values(&models::Follow {
+            player_id,
+            followed_id,
+        })
+        .on_conflict_do_nothing()
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(query_error(e, "Failed to follow player")),
+    }
+}
+
+pub async fn remove_follow(
+    player_id: i64,
+    followed_id: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<bool, Error> {
+    match diesel::delete(schema::follows::table)
+        .filter(schema::follows::player_id.eq(player_id))
+        .filter(schema::follows::followed_id.eq(followed_id))
+        .execute(db)
+        .await
+    {
+        Ok(deleted) => Ok(deleted > 0),
+        Err(e) => Err(query_error(e, "Failed to unfollow player")),
+    }
+}
+
+pub async fn get_webhook(
+    player_id: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Option<models::Webhook>, Error> {
+    match schema::webhooks::table
+        .filter(schema::webhooks::player_id.eq(player_id))
+        .first::<models::Webhook>(db)
+        .await
+        .optional()
+    {
+        Ok(webhook) => Ok(webhook),
+        Err(e) => Err(query_error(e, "Webhook not found")),
+    }
+}
+
+/// Replaces the url and secret if the player already has a webhook
+pub async fn set_webhook(webhook: models::Webhook, db: &mut crate::Connection<'_>) -> Result<(), Error> {
+    match diesel::insert_into(schema::webhooks::table)
+        .values(&webhook)
+        .on_conflict(schema::webhooks::player_id)
+        .do_update()
+        .set((
+            schema::webhooks::url.eq(&webhook.url),
+            schema::webhooks::secret.eq(&webhook.secret),
+        ))
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(query_error(e, "Failed to set webhook")),
+    }
+}
+
+/// Pending deliveries go with it
+pub async fn delete_webhook(player_id: i64, db: &mut crate::Connection<'_>) -> Result<bool, Error> {
+    match diesel::delete(schema::webhooks::table)
+        .filter(schema::webhooks::player_id.eq(player_id))
+        .execute(db)
+        .await
+    {
+        Ok(deleted) => Ok(deleted > 0),
+        Err(e) => Err(query_error(e, "Failed to delete webhook")),
+    }
+}
+
+/// (follower, followed) for followers of `followed_ids` that have a webhook set
+pub async fn get_webhook_followers(
+    followed_ids: HashSet<i64>,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<(i64, i64)>, Error> {
+    match schema::follows::table
+        .inner_join(schema::webhooks::table.on(schema::follows::player_id.eq(schema::webhooks::player_id)))
+        .select((schema::follows::player_id, schema::follows::followed_id))
+        .filter(schema::follows::followed_id.eq_any(followed_ids))
+        .load::<(i64, i64)>(db)
+        .await
+    {
+        Ok(followers) => Ok(followers),
+        Err(e) => Err(query_error(e, "Followers not found")),
+    }
+}
+
+pub async fn enqueue_webhook_deliveries(
+    deliveries: Vec<models::NewWebhookDelivery>,
+    db: &mut crate::Connection<'_>,
+) -> Result<(), Error> {
+    for chunk in deliveries.chunks(1000) {
+        if let Err(e) = diesel::insert_into(schema::webhook_deliveries::table)
+            .values(chunk)
+            .execute(db)
+            .await
+        {
+            return Err(Error::from(e).context("Failed to enqueue webhook deliveries"));
+        }
+    }
+    Ok(())
+}
+
+#[derive(QueryableByName)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct ClaimedDelivery {
+    #[diesel(embed)]
+    delivery: models::WebhookDelivery,
+    #[diesel(embed)]
+    webhook: models::Webhook,
+}
+
+/// Claims up to `limit` deliveries due at `now` by moving their next attempt to `lease_until`, so
+/// other instances skip them. One that isn't delivered, rescheduled or dropped by then is due again.
+pub async fn claim_due_webhook_deliveries(
+    now: chrono::NaiveDateTime,
+    lease_until: chrono::NaiveDateTime,
+    limit: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<(models::WebhookDelivery, models::Webhook)>, Error> {
+    match diesel::sql_query(
+        "
+        WITH claimed AS (
+            UPDATE webhook_deliveries SET next_attempt = $2
+            WHERE id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE next_attempt <= $1
+                ORDER BY next_attempt
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+        )
+        SELECT c.id, c.player_id, c.payload, c.attempts, c.next_attempt, c.last_error, w.url, w.secret
+        FROM claimed c
+        JOIN webhooks w ON w.player_id = c.player_id
+        ORDER BY c.id;
+        ",
+    )
+    .bind::<Timestamp, _>(now)
+    .bind::<Timestamp, _>(lease_until)
+    .bind::<BigInt, _>(limit)
+    .load::<ClaimedDelivery>(db)
+    .await
+    {
+        Ok(claimed) => Ok(claimed.into_iter().map(|c| (c.delivery, c.webhook)).collect()),
+        Err(e) => Err(query_error(e, "Webhook deliveries not found")),
+    }
+}
+
+pub async fn delete_webhook_delivery(id: i64, db: &mut crate::Connection<'_>) -> Result<(), Error> {
+    match diesel::delete(schema::webhook_deliveries::table)
+        .filter(schema::webhook_deliveries::id.eq(id))
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(query_error(e, "Failed to delete webhook delivery")),
+    }
+}
+
+pub async fn reschedule_webhook_delivery(
+    id: i64,
+    attempts: i32,
+    next_attempt: chrono::NaiveDateTime,
+    error: String,
+    db: &mut crate::Connection<'_>,
+) -> Result<(), Error> {
+    match update(schema::webhook_deliveries::table)
+        .filter(schema::webhook_deliveries::id.eq(id))
+        .set((
+            schema::webhook_deliveries::attempts.eq(attempts),
+            schema::webhook_deliveries::next_attempt.eq(next_attempt),
+            schema::webhook_deliveries::last_error.eq(error),
+        ))
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(query_error(e, "Failed to reschedule webhook delivery")),
+    }
+}
+
+/// Records that `job` started, returns the run id for `finish_job_run`
+pub async fn start_job_run(
+    job: &str,
+    started: chrono::NaiveDateTime,
+    db: &mut crate::Connection<'_>,
+) -> Result<i64, Error> {
+    diesel::insert_into(schema::job_runs::table)
+        .values(models::NewJobRun { job, started })
+        .returning(schema::job_runs::id)
+        .get_result::<i64>(db)
+        .await
+        .map_err(|e| Error::from(e).context("Failed to record job run"))
+}
+
+pub async fn finish_job_run(
+    id: i64,
+    finished: chrono::NaiveDateTime,
+    error: Option<String>,
+    db: &mut crate::Connection<'_>,
+) -> Result<(), Error> {
+    match update(schema::job_runs::table)
+        .filter(schema::job_runs::id.eq(id))
+        .set((
+            schema::job_runs::finished.eq(finished),
+            schema::job_runs::success.eq(error.is_none()),
+            schema::job_runs::error.eq(error),
+        ))
+        .execute(db)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(query_error(e, "Failed to finish job run")),
+    }
+}
+
+/// The latest run of every job that ever ran, or the latest successful one
+pub async fn get_latest_job_runs(
+    successful_only: bool,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::JobRun>, Error> {
+    let mut query = schema::job_runs::table
+        .select(models::JobRun::as_select())
+        .distinct_on(schema::job_runs::job)
+        .order((schema::job_runs::job, schema::job_runs::started.desc()))
+        .into_boxed();
+
+    if successful_only {
+        query = query.filter(schema::job_runs::success.eq(true));
+    }
+
+    match query.load::<models::JobRun>(db).await {
+        Ok(runs) => Ok(runs),
+        Err(e) => Err(query_error(e, "Job runs not found")),
+    }
+}
+
+/// All games between two players, on any characters, newest first
+pub async fn get_h2h_games(
+    player_a: i64,
+    player_b: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::Game>, Error> {
+    match schema::games::table
+        .filter(
+            (schema::games::id_a
+                .eq(player_a)
+                .and(schema::games::id_b.eq(player_b)))
+            .or(schema::games::id_a
+                .eq(player_b)
+                .and(schema::games::id_b.eq(player_a))),
+        )
+        .select(models::Game::as_select())
+        .order(
+            crate::pull::coalesce(schema::games::real_timestamp, schema::games::timestamp).desc(),
+        )
+        .load(db)
+        .await
+    {
+        Ok(games) => Ok(games),
+        Err(e) => Err(query_error(e, "Games not found")),
+    }
+}
+
+/// Up to `count` of `id`'s games on every character, oldest first by primary key, after `after`
+/// (timestamp, id_a, id_b) when it's given, so an export can read them a batch at a time.
+pub async fn get_games_after(
+    id: i64,
+    after: Option<(chrono::NaiveDateTime, i64, i64)>,
+    count: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::Game>, Error> {
+    use schema::games::dsl::*;
+
+    let mut query = games
+        .select(models::Game::as_select())
+        .filter(id_a.eq(id).or(id_b.eq(id)))
+        .into_boxed();
+
+    if let Some((after_timestamp, after_a, after_b)) = after {
+        query = query.filter(
+            timestamp
+                .gt(after_timestamp)
+                .or(timestamp.eq(after_timestamp).and(id_a.gt(after_a)))
+                .or(timestamp.eq(after_timestamp).and(id_a.eq(after_a)).and(id_b.gt(after_b))),
+        );
+    }
+
+    match query
+        .order((timestamp.asc(), id_a.asc(), id_b.asc()))
+        .limit(count)
+        .load(db)
+        .await
+    {
+        Ok(rows) => Ok(rows),
+        Err(e) => Err(query_error(e, "Games not found")),
+    }
+}
+
+/// Up to `count` games with `since < timestamp <= until`, by primary key, after `after` (timestamp, id_a, id_b)
+pub async fn get_all_games_after(
+    after: Option<(chrono::NaiveDateTime, i64, i64)>,
+    since: Option<chrono::NaiveDateTime>,
+    until: chrono::NaiveDateTime,
+    count: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<models::Game>, Error> {
+    use schema::games::dsl::*;
+
+    let mut query = games
+        .select(models::Game::as_select())
+        .filter(timestamp.le(until))
+        .into_boxed();
+
+    if let Some(since) = since {
+        query = query.filter(timestamp.gt(since));
+    }
+
+    if let Some((after_timestamp, after_a, after_b)) = after {
+        query = query.filter(
+            timestamp
+                .gt(after_timestamp)
+                .or(timestamp.eq(after_timestamp).and(id_a.gt(after_a)))
+                .or(timestamp.eq(after_timestamp).and(id_a.eq(after_a)).and(id_b.gt(after_b))),
+        );
+    }
+
+    match query
+        .order((timestamp.asc(), id_a.asc(), id_b.asc()))
+        .limit(count)
+        .load(db)
+        .await
+    {
+        Ok(rows) => Ok(rows),
+        Err(e) => Err(query_error(e, "Games not found")),
+    }
+}
+
+/// Up to `count` (inserted_at, game) with `since < inserted_at <= until`, after `after`
+/// (inserted_at, timestamp, id_a, id_b). By insertion time, so backfilled games aren't missed.
+pub async fn get_games_inserted_after(
+    after: Option<(chrono::NaiveDateTime, chrono::NaiveDateTime, i64, i64)>,
+    since: Option<chrono::NaiveDateTime>,
+    until: chrono::NaiveDateTime,
+    count: i64,
+    db: &mut crate::Connection<'_>,
+) -> Result<Vec<(chrono::NaiveDateTime, models::Game)>, Error> {
+    use schema::games::dsl::*;
+
+    let mut query = games
+        .select((inserted_at, models::Game::as_select()))
+        .filter(inserted_at.le(until))
+        .into_boxed();
+
+    if let Some(since) = since {
+        query = query.filter(inserted_at.gt(since));
+    }
+
+    if let Some((after_inserted, after_timestamp, after_a, after_b)) = after {
+        query = query.filter(
+            inserted_at
+                .gt(after_inserted)
+                .or(inserted_at.eq(after_inserted).and(timestamp.gt(after_timestamp)))
+                .or(inserted_at.eq(after_inserted).and(timestamp.eq(after_timestamp)).and(id_a.gt(after_a)))
+                .or(inserted_at
+                    .eq(after_inserted)
+                    .and(timestamp.eq(after_timestamp))
+                    .and(id_a.eq(after_a))
+                    .and(id_b.gt(after_b))),
+        );
+    }
+
+    match query
+        .order((inserted_at.asc(), timestamp.asc(), id_a.asc(), id_b.asc()))
+        .limit(count)
+        .load(db)
+        .await
+    {
+        Ok(rows) => Ok(rows),
+        Err(e) => Err(query_error(e, "Games not found")),
+    }
+}
+
+/// (id, name, private) of the players that exist
+pub async fn get_player_names(
+    ids: HashSet<i64>,
+    db: &mut crate::Connection<'_>,
+) -> Result<HashMap<i64, (String, bool)>, Error> {
+    match schema::players::table
+        .select((schema::players::id, schema::players::name, schema::players::private))
+        .filter(schema::players::id.eq_any(ids))
+        .load::<(i64, String, bool)>(db)
+        .await
+    {
+        Ok(players) => Ok(players.into_iter().map(|(id, name, private)| (id, (name, private))).collect()),
+        Err(e) => Err(query_error(e, "Players not found")),
+    }
+}
+
+/// Sets in `offset..offset + count` (in sets, newest first) and the rating going into the game right after them:
+/// the current rating on the first page. Games are grouped with `group_sets`, reading more rows until
+/// the last set on the page is complete.
+pub async fn get_game_sets(
+    id: i64,
+    char_id: i16,
+    count: usize,
+    offset: usize,
+    db: &mut crate::Connection<'_>,
+) -> Result<(Vec<Vec<HistoryGame>>, Option<i64>), Error> {
+    //Most sets are 2 or 3 games
+    let mut limit = ((offset + count + 1) * 3) as i64;
+
+    let (sets, rating_after_page) = loop {
+        //Sets take their ratings from the games themselves, not the after-ratings
+        let games = get_history_games(id, char_id, None, limit, 0, false, db).await?;
+        let exhausted = (games.len() as i64) < limit;
+        let sets = crate::handlers::player_history::group_sets(id, &games);
+
+        if exhausted || sets.len() > offset + count {
+            let next_rating = offset
+                .checked_sub(1)
+                .and_then(|i| sets.get(i))
+                .and_then(|set| set.last())
+                .map(|history| &history.game)
+                .map(|game| if game.id_a == id { game.value_a } else { game.value_b });
+            let page: Vec<Vec<HistoryGame>> = sets.into_iter().skip(offset).take(count).map(|s| s.to_vec()).collect();
+            break (page, next_rating);
+        }
+
+        limit *= 2;
+    };
+
+    if offset > 0 {
+        return Ok((sets, rating_after_page));
+    }
+
+    let current_rating = schema::player_ratings::table
+        .select(schema::player_ratings::value)
+        .filter(schema::player_ratings::id.eq(id))
+        .filter(schema::player_ratings::char_id.eq(char_id))
+        .first::<i64>(db)
+        .await
+        .ok();
+
+    Ok((sets, current_rating))
+}
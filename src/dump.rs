@@ -0,0 +1,512 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDateTime, Timelike};
+use flate2::{write::GzEncoder, Compression};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::error::Error;
+use crate::{db, CHAR_NAMES};
+
+/// Rows per file before starting the next one
+const CHUNK_ROWS: usize = 100_000;
+/// Rows read per query
+const BATCH_SIZE: i64 = 10_000;
+/// Games inserted this recently may still be in an open transaction, the next dump picks them up
+const SETTLE_HOURS: i64 = 2;
+/// Player ids are public, a guessable key would let anyone map every `anon-` id back by hashing them
+const MIN_KEY_BYTES: usize = 32;
+const MANIFEST: &str = "manifest.json";
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub created: String,
+    /// Only has the games since the previous dump, players, names and leaderboards are always complete
+    pub incremental: bool,
+    /// Games inserted with `games_since < inserted_at <= games_until` are in this dump, however old
+    /// their `timestamp`, so backfilled games still reach the next incremental dump
+    pub games_since: Option<String>,
+    pub games_until: String,
+    /// Tells dumps anonymised with different keys apart, an incremental dump needs the same one
+    pub key_id: String,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub table: String,
+    /// Relative to the dump root
+    pub path: String,
+    pub rows: usize,
+    /// Of the compressed file
+    pub sha256: String,
+}
+
+/// Player ids through HMAC-SHA256 with `DUMP_KEY`, unless the player opted in to the dataset.
+/// Names only for those who did. Private players are left out of the dump altogether, a stable
+/// pseudonym would still publish their linkable history.
+struct Anonymiser {
+    key: Vec<u8>,
+    opted_in: HashSet<i64>,
+    private: HashSet<i64>,
+}
+
+impl Anonymiser {
+    fn hash(&self, data: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(data.as_bytes());
+        hex::encode(&mac.finalize().into_bytes()[..8])
+    }
+
+    fn key_id(&self) -> String {
+        self.hash("key_id")
+    }
+
+    fn id(&self, id: i64) -> String {
+        if self.opted_in.contains(&id) {
+            id.to_string()
+        } else {
+            format!("anon-{}", self.hash(&id.to_string()))
+        }
+    }
+
+    fn name(&self, id: i64, name: &str) -> Option<String> {
+        self.opted_in.contains(&id).then(|| name.to_string())
+    }
+
+    fn excluded(&self, id: i64) -> bool {
+        self.private.contains(&id)
+    }
+}
+
+#[derive(Serialize)]
+struct GameRow {
+    timestamp: String,
+    real_timestamp: Option<String>,
+    player_a: String,
+    name_a: Option<String>,
+    char_a: &'static str,
+    platform_a: i16,
+    rating_a: i64,
+    player_b: String,
+    name_b: Option<String>,
+    char_b: &'static str,
+    platform_b: i16,
+    rating_b: i64,
+    winner: i16,
+    floor: i16,
+}
+
+#[derive(Serialize)]
+struct PlayerRow {
+    id: String,
+    name: Option<String>,
+    platform: i16,
+}
+
+#[derive(Serialize)]
+struct PlayerNameRow {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct LeaderboardRow {
+    board: String,
+    rank: i64,
+    player: String,
+    name: Option<String>,
+    char: &'static str,
+    rating: i64,
+}
+
+/// Hashes what goes through it, so a file's checksum is known once it's written
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+type ChunkFile = GzEncoder<HashingWriter<BufWriter<File>>>;
+
+/// Writes one table as `<table>-00000.ndjson.gz`, `<table>-00001.ndjson.gz`... of `CHUNK_ROWS` rows each
+struct ChunkWriter {
+    root: PathBuf,
+    dir: String,
+    table: &'static str,
+    current: Option<(ChunkFile, String, usize)>,
+    files: Vec<ManifestFile>,
+}
+
+impl ChunkWriter {
+    fn new(root: &Path, dir: &str, table: &'static str) -> ChunkWriter {
+        ChunkWriter {
+            root: root.to_path_buf(),
+            dir: dir.to_string(),
+            table,
+            current: None,
+            files: vec![],
+        }
+    }
+
+    fn write<T: Serialize>(&mut self, row: &T) -> Result<(), Error> {
+        if self.current.is_none() {
+            let path = format!("{}/{}-{:05}.ndjson.gz", self.dir, self.table, self.files.len());
+            let file = File::create(self.root.join(&path))?;
+            let writer = HashingWriter {
+                inner: BufWriter::new(file),
+                hasher: Sha256::new(),
+            };
+            self.current = Some((GzEncoder::new(writer, Compression::default()), path, 0));
+        }
+
+        let (encoder, _, rows) = self.current.as_mut().unwrap();
+        serde_json::to_writer(&mut *encoder, row)?;
+        encoder.write_all(b"\n")?;
+        *rows += 1;
+
+        if *rows == CHUNK_ROWS {
+            self.close_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn close_chunk(&mut self) -> Result<(), Error> {
+        if let Some((encoder, path, rows)) = self.current.take() {
+            let mut writer = encoder.finish()?;
+            writer.flush()?;
+            self.files.push(ManifestFile {
+                table: self.table.to_string(),
+                path,
+                rows,
+                sha256: hex::encode(writer.hasher.finalize()),
+            });
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<ManifestFile>, Error> {
+        self.close_chunk()?;
+        Ok(self.files)
+    }
+}
+
+/// The manifest of the newest finished dump in `root`. A dump's manifest is written last,
+/// so one that stopped halfway isn't picked up.
+pub fn last_manifest(root: &Path) -> Result<Option<Manifest>, Error> {
+    if !root.exists() {
+        return Ok(None);
+    }
+
+    let mut dirs: Vec<PathBuf> = fs::read_dir(root)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.join(MANIFEST).is_file())
+        .collect();
+    dirs.sort();
+
+    match dirs.last() {
+        Some(dir) => Ok(Some(serde_json::from_str(&fs::read_to_string(dir.join(MANIFEST))?)?)),
+        None => Ok(None),
+    }
+}
+
+async fn dump_games(
+    state: &crate::AppState,
+    anonymiser: &Anonymiser,
+    since: Option<NaiveDateTime>,
+    until: NaiveDateTime,
+    mut writer: ChunkWriter,
+) -> Result<Vec<ManifestFile>, Error> {
+    let mut db = state.db_pool.get().await?;
+    let mut after = None;
+
+    loop {
+        let games = db::get_games_inserted_after(after, since, until, BATCH_SIZE, &mut db).await?;
+        for (_, game) in games.iter().filter(|(_, g)| !anonymiser.excluded(g.id_a) && !anonymiser.excluded(g.id_b)) {
+            writer.write(&GameRow {
+                timestamp: game.timestamp.to_string(),
+                real_timestamp: game.real_timestamp.map(|t| t.to_string()),
+                player_a: anonymiser.id(game.id_a),
+                name_a: anonymiser.name(game.id_a, &game.name_a),
+                char_a: CHAR_NAMES[game.char_a as usize].0,
+                platform_a: game.platform_a,
+                rating_a: game.value_a,
+                player_b: anonymiser.id(game.id_b),
+                name_b: anonymiser.name(game.id_b, &game.name_b),
+                char_b: CHAR_NAMES[game.char_b as usize].0,
+                platform_b: game.platform_b,
+                rating_b: game.value_b,
+                winner: game.winner,
+                floor: game.game_floor,
+            })?;
+        }
+
+        match games.last() {
+            Some((inserted_at, last)) if games.len() as i64 == BATCH_SIZE => {
+                after = Some((*inserted_at, last.timestamp, last.id_a, last.id_b))
+            }
+            _ => break,
+        }
+    }
+
+    writer.finish()
+}
+
+async fn dump_players(
+    state: &crate::AppState,
+    anonymiser: &Anonymiser,
+    mut writer: ChunkWriter,
+) -> Result<Vec<ManifestFile>, Error> {
+    let mut db = state.db_pool.get().await?;
+    let mut after = i64::MIN;
+
+    loop {
+        let players = db::get_players_after(after, BATCH_SIZE, &mut db).await?;
+        for (id, name, platform) in players.iter().filter(|(id, _, _)| !anonymiser.excluded(*id)) {
+            writer.write(&PlayerRow {
+                id: anonymiser.id(*id),
+                name: anonymiser.name(*id, name),
+                platform: *platform,
+            })?;
+        }
+
+        match players.last() {
+            Some((id, _, _)) if players.len() as i64 == BATCH_SIZE => after = *id,
+            _ => break,
+        }
+    }
+
+    writer.finish()
+}
+
+/// Only opted in players' names, the others would give them away
+async fn dump_player_names(
+    state: &crate::AppState,
+    anonymiser: &Anonymiser,
+    mut writer: ChunkWriter,
+) -> Result<Vec<ManifestFile>, Error> {
+    let mut db = state.db_pool.get().await?;
+    let mut ids: Vec<i64> = anonymiser.opted_in.iter().copied().collect();
+    ids.sort();
+
+    for batch in ids.chunks(BATCH_SIZE as usize) {
+        for (id, name) in db::get_names_of_players(batch, &mut db).await? {
+            writer.write(&PlayerNameRow { id: anonymiser.id(id), name })?;
+        }
+    }
+
+    writer.finish()
+}
+
+/// What's in Redis now, the boards that haven't been built yet are left out
+async fn dump_leaderboards(
+    state: &crate::AppState,
+    anonymiser: &Anonymiser,
+    mut writer: ChunkWriter,
+) -> Result<Vec<ManifestFile>, Error> {
+    let mut redis = state.redis_pool.get().await?;
+    let mut boards = vec!["leaderboard_all".to_string(), "leaderboard_legend".to_string()];
+    boards.extend((0..CHAR_NAMES.len()).map(|c| format!("leaderboard_char_{}", c)));
+
+    for board in boards {
+        let Ok(entries) = crate::read_leaderboard(&mut redis, &board).await else {
+            continue;
+        };
+        for entry in entries {
+            let Ok(id) = entry.player_id.parse::<i64>() else {
+                continue;
+            };
+            if anonymiser.excluded(id) {
+                continue;
+            }
+            writer.write(&LeaderboardRow {
+                board: board.trim_start_matches("leaderboard_").to_string(),
+                rank: entry.rank,
+                player: anonymiser.id(id),
+                name: anonymiser.name(id, &entry.player_name),
+                char: CHAR_NAMES.get(entry.char_id as usize).map(|c| c.0).unwrap_or("??"),
+                rating: entry.rating,
+            })?;
+        }
+    }
+
+    writer.finish()
+}
+
+fn check_key(key: &str) -> Result<(), Error> {
+    if key.len() < MIN_KEY_BYTES {
+        return Err(Error::BadInput(format!("DUMP_KEY must be at least {} bytes", MIN_KEY_BYTES)));
+    }
+    Ok(())
+}
+
+/// Dumps into a new `<root>/<date>_<time>` directory. Games since the last dump's `games_until` unless
+/// `full` or there's no dump yet, everything else in full.
+pub async fn dump(state: &crate::AppState, root: &Path, key: &str, full: bool) -> Result<Manifest, Error> {
+    check_key(key)?;
+
+    let (opted_in, private) = {
+        let mut db = state.db_pool.get().await?;
+        (db::get_dataset_players(&mut db).await?, db::get_all_private_players(&mut db).await?)
+    };
+    let anonymiser = Anonymiser {
+        key: key.as_bytes().to_vec(),
+        opted_in,
+        private,
+    };
+
+    let previous = if full { None } else { last_manifest(root)? };
+    if let Some(previous) = &previous
+        && previous.key_id != anonymiser.key_id()
+    {
+        return Err(Error::BadInput(
+            "DUMP_KEY changed since the last dump, ids wouldn't match, run a full dump".to_string(),
+        ));
+    }
+
+    let created = chrono::Utc::now().naive_utc();
+    let games_since = match previous {
+        Some(previous) => Some(
+            NaiveDateTime::parse_from_str(&previous.games_until, TIME_FORMAT)
+                .map_err(|e| Error::Internal(format!("Bad games_until in the last manifest: {}", e)))?,
+        ),
+        None => None,
+    };
+    let games_until = (created - chrono::Duration::hours(SETTLE_HOURS)).with_nanosecond(0).unwrap();
+    let dir = created.format("%Y-%m-%d_%H%M%S").to_string();
+    fs::create_dir_all(root.join(&dir))?;
+
+    info!("Dumping games from {:?} to {} into {}", games_since, games_until, dir);
+    let mut files = dump_games(state, &anonymiser, games_since, games_until, ChunkWriter::new(root, &dir, "games")).await?;
+    files.extend(dump_players(state, &anonymiser, ChunkWriter::new(root, &dir, "players")).await?);
+    files.extend(dump_player_names(state, &anonymiser, ChunkWriter::new(root, &dir, "player_names")).await?);
+    files.extend(dump_leaderboards(state, &anonymiser, ChunkWriter::new(root, &dir, "leaderboards")).await?);
+
+    let manifest = Manifest {
+        created: created.format(TIME_FORMAT).to_string(),
+        incremental: games_since.is_some(),
+        games_since: games_since.map(|t| t.format(TIME_FORMAT).to_string()),
+        games_until: games_until.format(TIME_FORMAT).to_string(),
+        key_id: anonymiser.key_id(),
+        files,
+    };
+    fs::write(root.join(&dir).join(MANIFEST), serde_json::to_string_pretty(&manifest)?)?;
+
+    for file in &manifest.files {
+        info!("{}: {} rows", file.path, file.rows);
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn temp_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("pf-dump-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn anonymiser_keeps_only_opted_in_players() {
+        let anonymiser = Anonymiser {
+            key: b"secret".to_vec(),
+            opted_in: HashSet::from([1]),
+            private: HashSet::from([4]),
+        };
+        let other_key = Anonymiser {
+            key: b"other".to_vec(),
+            opted_in: HashSet::new(),
+            private: HashSet::new(),
+        };
+
+        assert_eq!(anonymiser.id(1), "1");
+        assert_eq!(anonymiser.name(1, "Sol").as_deref(), Some("Sol"));
+
+        assert!(anonymiser.id(2).starts_with("anon-"));
+        assert_eq!(anonymiser.id(2), anonymiser.id(2));
+        assert_ne!(anonymiser.id(2), anonymiser.id(3));
+        assert_ne!(anonymiser.id(2), other_key.id(2));
+        assert_eq!(anonymiser.name(2, "Ky"), None);
+
+        assert!(anonymiser.excluded(4));
+        assert!(!anonymiser.excluded(2));
+    }
+
+    #[test]
+    fn short_keys_are_refused() {
+        assert!(check_key("").is_err());
+        assert!(check_key("secret").is_err());
+        assert!(check_key(&"k".repeat(MIN_KEY_BYTES)).is_ok());
+    }
+
+    #[test]
+    fn chunk_writer_splits_and_checksums_files() {
+        let root = temp_root();
+        fs::create_dir_all(root.join("dump")).unwrap();
+
+        let mut writer = ChunkWriter::new(&root, "dump", "players");
+        for id in 0..CHUNK_ROWS + 1 {
+            writer.write(&PlayerNameRow { id: id.to_string(), name: "Sol".to_string() }).unwrap();
+        }
+        let files = writer.finish().unwrap();
+
+        assert_eq!(files.iter().map(|f| f.rows).collect::<Vec<_>>(), vec![CHUNK_ROWS, 1]);
+        assert_eq!(files[1].path, "dump/players-00001.ndjson.gz");
+
+        let compressed = fs::read(root.join(&files[1].path)).unwrap();
+        assert_eq!(hex::encode(Sha256::digest(&compressed)), files[1].sha256);
+        let mut contents = String::new();
+        GzDecoder::new(&compressed[..]).read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, format!("{{\"id\":\"{}\",\"name\":\"Sol\"}}\n", CHUNK_ROWS));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn last_manifest_skips_unfinished_dumps() {
+        let root = temp_root();
+        let manifest = |until: &str| Manifest {
+            created: until.to_string(),
+            incremental: false,
+            games_since: None,
+            games_until: until.to_string(),
+            key_id: "key".to_string(),
+            files: vec![],
+        };
+
+        assert!(last_manifest(&root).unwrap().is_none());
+
+        for (dir, until) in [("2026-10-17_000000", "2026-10-17 00:00:00"), ("2026-10-18_000000", "2026-10-18 00:00:00")] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join(MANIFEST), serde_json::to_string(&manifest(until)).unwrap()).unwrap();
+        }
+        // Stopped before writing its manifest
+        fs::create_dir_all(root.join("2026-10-19_000000")).unwrap();
+
+        let last = last_manifest(&root).unwrap().unwrap();
+        assert_eq!(last.games_until, "2026-10-18 00:00:00");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use axum::extract::{OriginalUri, Path, State};
+use axum::http::StatusCode;
+use axum::response::Html;
+use axum::{routing::get, Router};
+use tracing::info;
+
+use crate::error::Error;
+use crate::handlers::common::SITE_URL;
+use crate::handlers::embed::{render_default_embed, render_player_embed, PlayerEmbed};
+use crate::CHAR_NAMES;
+
+pub async fn serve(state: crate::AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let app = Router::new()
+        .route("/player/:player_id", get(player))
+        .route("/player/:player_id/*rest", get(player))
+        .fallback(fallback)
+        .with_state(state);
+
+    let addr = std::env::var("EMBED_LISTEN_ADDR").expect("EMBED_LISTEN_ADDR");
+    info!("Embed server listening on {}", addr);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn fallback(OriginalUri(uri): OriginalUri) -> (StatusCode, Html<String>) {
+    (
+        StatusCode::NOT_FOUND,
+        Html(render_default_embed(&SITE_URL, uri.path())),
+    )
+}
+
+// Matches the frontend route: /player/:player_id/:char_short?/:count?/:offset?
+async fn player(
+    State(pools): State<crate::AppState>,
+    OriginalUri(uri): OriginalUri,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<(StatusCode, Html<String>), Error> {
+    let not_found = (
+        StatusCode::NOT_FOUND,
+        Html(render_default_embed(&SITE_URL, uri.path())),
+    );
+
+    let id = match params.get("player_id").and_then(|id| id.parse::<i64>().ok()) {
+        Some(id) => id,
+        None => return Ok(not_found),
+    };
+
+    let char_short = params
+        .get("rest")
+        .and_then(|rest| rest.split('/').next())
+        .map(|c| c.to_string());
+
+    let mut db = pools.db_pool.get().await?;
+
+    let (player_char, match_counts, _, _, _, _, _) =
+        match crate::db::get_player_response_data(id, &mut db).await {
+            Ok(response) => response,
+            Err(Error::NotFound(_)) => return Ok(not_found),
+            Err(e) => return Err(e),
+        };
+
+    if player_char[0].0.private {
+        return Ok(not_found);
+    }
+
+    // The requested character if there is one, otherwise the most played
+    let (player, rating) = match char_short
+        .and_then(|c| player_char.iter().find(|(_, r)| CHAR_NAMES[r.char_id as usize].0 == c))
+    {
+        Some(pc) => pc,
+        None => player_char
+            .iter()
+            .max_by_key(|(_, r)| match_counts.get(&r.char_id).copied().unwrap_or(0))
+            .unwrap(),
+    };
+
+    let mut redis = pools.redis_pool.get().await?;
+    let (global_rank, char_ranks) = crate::read_player_ranks(id, &player_char, &mut redis).await;
+
+    let embed = PlayerEmbed {
+        id,
+        name: player.name.clone(),
+        char_short: CHAR_NAMES[rating.char_id as usize].0.to_string(),
+        character: CHAR_NAMES[rating.char_id as usize].1.to_string(),
+        rating: rating.value,
+        global_rank,
+        char_rank: char_ranks.get(&rating.char_id).copied(),
+    };
+
+    Ok((
+        StatusCode::OK,
+        Html(render_player_embed(&SITE_URL, uri.path(), &embed)),
+    ))
+}
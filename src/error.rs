@@ -0,0 +1,173 @@
+use std::fmt;
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use bb8_redis::redis;
+use serde::Serialize;
+
+/// Everything the db, imdb and ggst_api functions and the handlers can fail with.
+/// The variant picks the status code, so clients can tell a missing player from an outage.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The player, character, game etc. doesn't exist
+    NotFound(String),
+    /// Postgres, Redis or GGST is down or refused the request
+    UpstreamUnavailable(String),
+    RateLimited(String),
+    BadInput(String),
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'static str,
+    message: &'a str,
+}
+
+impl Error {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::UpstreamUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::BadInput(_) => StatusCode::BAD_REQUEST,
+            Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// `error` in the response body
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::NotFound(_) => "not_found",
+            Error::UpstreamUnavailable(_) => "upstream_unavailable",
+            Error::RateLimited(_) => "rate_limited",
+            Error::BadInput(_) => "bad_input",
+            Error::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Error::NotFound(m)
+            | Error::UpstreamUnavailable(m)
+            | Error::RateLimited(m)
+            | Error::BadInput(m)
+            | Error::Internal(m) => m,
+        }
+    }
+
+    /// Same kind, `context` in front of the message
+    pub fn context(self, context: &str) -> Error {
+        let message = format!("{}: {}", context, self.message());
+        match self {
+            Error::NotFound(_) => Error::NotFound(message),
+            Error::UpstreamUnavailable(_) => Error::UpstreamUnavailable(message),
+            Error::RateLimited(_) => Error::RateLimited(message),
+            Error::BadInput(_) => Error::BadInput(message),
+            Error::Internal(_) => Error::Internal(message),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        if let Error::Internal(message) | Error::UpstreamUnavailable(message) = &self {
+            tracing::error!("{}: {}", self.kind(), message);
+        }
+
+        let body = ErrorBody {
+            error: self.kind(),
+            message: self.message(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+/// The database's own message can show queries and schema, it's only logged
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Error {
+        use diesel::result::{DatabaseErrorKind, Error as DieselError};
+        match e {
+            DieselError::NotFound => Error::NotFound("Not found".to_string()),
+            DieselError::DatabaseError(DatabaseErrorKind::ClosedConnection, info) => {
+                tracing::error!("Database connection closed: {}", info.message());
+                Error::UpstreamUnavailable("Database unavailable".to_string())
+            }
+            e => {
+                tracing::error!("Database error: {}", e);
+                Error::Internal("Database error".to_string())
+            }
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> From<bb8::RunError<E>> for Error {
+    fn from(e: bb8::RunError<E>) -> Error {
+        Error::UpstreamUnavailable(format!("No connection available: {}", e))
+    }
+}
+
+impl From<redis::RedisError> for Error {
+    fn from(e: redis::RedisError) -> Error {
+        Error::UpstreamUnavailable(format!("Redis error: {}", e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Internal(format!("Invalid JSON: {}", e))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Internal(format!("IO error: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn into_response_has_status_and_json_body() {
+        let response = Error::NotFound("Player not found".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({"error": "not_found", "message": "Player not found"}));
+    }
+
+    #[test]
+    fn from_diesel_error() {
+        assert_eq!(
+            Error::from(diesel::result::Error::NotFound).status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            Error::from(diesel::result::Error::RollbackTransaction).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn from_diesel_error_hides_the_database_message() {
+        let e = Error::from(diesel::result::Error::DeserializationError("column \"api_key\" is null".into()));
+        assert_eq!(e, Error::Internal("Database error".to_string()));
+    }
+
+    #[test]
+    fn context_keeps_kind() {
+        let e = Error::UpstreamUnavailable("timed out".to_string()).context("Couldn't get replays");
+        assert_eq!(e, Error::UpstreamUnavailable("Couldn't get replays: timed out".to_string()));
+    }
+}
@@ -1,21 +1,30 @@
-use crate::{requests, responses, responses::Response};
+use crate::{error::Error, metrics, requests, responses, responses::Response};
 use aes_gcm::{
     aead::{generic_array::GenericArray, Aead},
     Aes256Gcm, KeyInit,
 };
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use hex;
 use lazy_static::lazy_static;
 use reqwest::header;
-use serde::{Deserialize, Serialize};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use tracing::{debug, error, info, warn};
-use std::{error::Error, ops::Deref};
+use std::{path::PathBuf, sync::Arc};
 use tokio::sync::Mutex;
 
 lazy_static! {
-    pub static ref TOKEN: Mutex<Option<String>> = Mutex::new(None);
-    
-    /// Reusing a single client maintains a connection pool and 
+    static ref AUTH: Mutex<Auth> = Mutex::new(Auth::default());
+    /// Held through a login so concurrent callers wait for its token instead of logging in again
+    static ref LOGIN: Mutex<()> = Mutex::new(());
+    static ref EXPIRED_STATUSES: Vec<i64> = match dotenv::var("GGST_SESSION_EXPIRED_STATUSES") {
+        Ok(statuses) => parse_statuses(&statuses),
+        Err(_) => SESSION_EXPIRED_STATUSES.to_vec(),
+    };
+
+    /// Reusing a single client maintains a connection pool and
     /// ensures stable SNI/TLS handling across requests.
     pub static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder()
         .user_agent("GGST/Steam")
@@ -23,269 +32,535 @@ lazy_static! {
         .expect("Failed to create reqwest client");
 }
 
-pub async fn get_player_stats(player_id: String) -> Result<String, String> {
-    let request_data = requests::generate_player_stats_request(player_id);
-    let request_data = encrypt_data(&request_data);
+const LIVE_API_URL: &str = "https://ggst-game.guiltygear.com/api";
+const KEY: &str = "EEBC1F57487F51921C0465665F8AE6D1658BB26DE6F8A069A3520293A572078F";
+
+/// Replays per `get_replay` page, the most the game asks for
+pub const REPLAYS_PER_PAGE: usize = 127;
+
+/// Header statuses GGST refuses a request with once the session is gone. Any other refusal is about
+/// the request itself (an id it doesn't know, say) and leaves the token alone.
+///
+/// 1 is unconfirmed, no refusal of an expired session has been captured yet. GGST_RECORD_DIR saves
+/// the refused response with the others, add it to the fixtures once there is one.
+/// GGST_SESSION_EXPIRED_STATUSES (comma separated) replaces the list until then.
+const SESSION_EXPIRED_STATUSES: &[i64] = &[1];
+
+const LOGIN_BACKOFF_SECONDS: i64 = 30;
+const MAX_LOGIN_BACKOFF_SECONDS: i64 = 60 * 60;
+
+/// Login state of this process, shared by every `HttpApi`
+#[derive(Default)]
+struct Auth {
+    token: Option<String>,
+    /// Failed logins in a row
+    failures: u32,
+    next_login: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AuthStatus {
+    pub connected: bool,
+    pub failed_logins: u32,
+    pub next_login: Option<String>,
+    pub last_error: Option<String>,
+    /// When token.txt was written, the puller writes it on every login
+    #[serde(default)]
+    pub token_since: Option<String>,
+}
 
-    let response = HTTP_CLIENT
-        .post("https://ggst-game.guiltygear.com/api/statistics/get")
-        .header(header::CACHE_CONTROL, "no-store")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header("x-client-version", "1")
-        .form(&[("data", request_data)])
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
+/// Uses `token` until GGST refuses it, the puller loads the one it saved last run
+pub async fn set_token(token: String) {
+    AUTH.lock().await.token = Some(token);
+}
 
-    let response_bytes = response.bytes().await.map_err(|e| e.to_string())?;
+/// The token in memory, else the one in token.txt (whatever the puller logged in with last), kept
+/// in memory until GGST refuses it
+async fn saved_token() -> Option<String> {
+    let mut auth = AUTH.lock().await;
 
-    if let Ok(r) = decrypt_response::<responses::PlayerStats>(&response_bytes) {
-        Ok(r.body.json)
-    } else {
-        Err("Couldn't get player stats".to_owned())
+    if auth.token.is_none()
+        && let Ok(t) = std::fs::read_to_string("token.txt")
+        && !t.trim().is_empty()
+    {
+        auth.token = Some(t.trim().to_owned());
     }
+
+    auth.token.clone()
 }
 
-pub async fn get_player_comment(player_id: String) -> Result<String, String> {
-    let request_data = requests::generate_player_stats_request(player_id);
-    let request_data = encrypt_data(&request_data);
+/// "1, 7" as [1, 7], skipping anything that isn't a number
+fn parse_statuses(statuses: &str) -> Vec<i64> {
+    statuses.split(',').filter_map(|s| s.trim().parse().ok()).collect()
+}
 
-    let response = HTTP_CLIENT
-        .post("https://ggst-game.guiltygear.com/api/statistics/get")
-        .header(header::CACHE_CONTROL, "no-store")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header("x-client-version", "1")
-        .form(&[("data", request_data)])
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
+/// Wait before the next login, after `failures` failed ones in a row
+pub fn login_backoff(failures: u32) -> chrono::Duration {
+    let seconds = LOGIN_BACKOFF_SECONDS.saturating_mul(1 << failures.saturating_sub(1).min(20));
+    chrono::Duration::seconds(seconds.min(MAX_LOGIN_BACKOFF_SECONDS))
+}
+
+/// The game's API. Every endpoint is a provided method on top of `post`, so the live servers,
+/// recorded fixtures and a stand-in server all go through the same request and response handling.
+#[async_trait]
+pub trait GgstApi: Send + Sync {
+    /// Token for the request header, logs in if there isn't one yet
+    async fn token(&self) -> Result<String, Error>;
 
-    let response_bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    /// False while there is no token, usually because a patch broke the login
+    async fn is_connected(&self) -> bool;
 
-    if let Ok(r) = decrypt_response::<responses::PlayerStats>(&response_bytes) {
-        let parsed: Value = serde_json::from_str(&r.body.json)
-            .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+    /// Drops `token` after GGST refused it, the next request logs in again
+    async fn invalidate_token(&self, _token: &str) {}
+
+    /// Keeps the version GGST asked for after a patch for every later request, once it answered a request
+    /// sent with it
+    async fn set_api_version(&self, version: &str) {
+        requests::set_api_version(version);
+    }
+
+    async fn auth_status(&self) -> AuthStatus {
+        AuthStatus {
+            connected: self.is_connected().await,
+            ..Default::default()
+        }
+    }
+
+    /// Sends encrypted `data` to `endpoint` and returns the response, still encrypted.
+    /// `fixture` is the file name the response is recorded under, see `fixture_name`.
+    async fn post(&self, endpoint: &str, fixture: &str, data: String) -> Result<Vec<u8>, Error>;
+
+    async fn get_player_stats(&self, player_id: String) -> Result<String, Error> {
+        let token = self.token().await?;
+        let request = requests::generate_player_stats_request(player_id, &token);
+
+        match send::<_, _, responses::PlayerStats>(self, "statistics/get", request, &token).await {
+            Ok(r) => Ok(r.body.json),
+            Err(e) => Err(e.context("Couldn't get player stats")),
+        }
+    }
+
+    async fn get_player_comment(&self, player_id: String) -> Result<String, Error> {
+        let json = self.get_player_stats(player_id).await?;
+
+        let parsed: Value = serde_json::from_str(&json)
+            .map_err(|e| Error::from(e).context("Failed to parse player stats"))?;
 
         if let Some(comment) = parsed.get("PublicComment").and_then(|v| v.as_str()) {
             return Ok(comment.to_owned());
         }
-        Err("Comment not found".to_owned())
-    } else {
-        Err("Couldn't get player stats".to_owned())
+        Err(Error::NotFound("Comment not found".to_owned()))
+    }
+
+    async fn get_player_avatar(&self, player_id: String) -> Result<String, Error> {
+        let token = self.token().await?;
+        let request = requests::generate_player_avatar_request(player_id, &token);
+
+        match send::<_, _, responses::PlayerAvatar>(self, "tus/read", request, &token).await {
+            Ok(r) => Ok(r.body.png),
+            Err(e) => Err(e.context("Couldn't get player avatar")),
+        }
+    }
+
+    /// Page `index` of the newest replays matching `query`, newest first. Page 0 is the latest games.
+    async fn get_replay_page(
+        &self,
+        index: usize,
+        query: &requests::ReplayQuery,
+    ) -> Result<Vec<responses::Replay>, Error> {
+        let token = self.token().await?;
+
+        debug!("Grabbing replays (page {index})");
+        let request = requests::generate_replay_request(index, REPLAYS_PER_PAGE, query, &token);
+
+        match send::<_, _, responses::Replays>(self, "catalog/get_replay", request, &token).await {
+            Ok(r) => Ok(r.body.replays),
+            Err(e) => {
+                error!("get_replay (page {}) error: {}", index, e);
+                Err(e.context("Couldn't get replays"))
+            }
+        }
+    }
+
+    async fn get_rank_match_legend(&self) -> Result<Vec<responses::LegendPlayer>, Error> {
+        let token = self.token().await?;
+        let request = requests::generate_rank_match_legend_request(&token);
+
+        match send::<_, _, responses::RankMatchLegend>(self, "ranking/get_rank_match_legend", request, &token).await {
+            Ok(r) => Ok(r.body.players),
+            Err(e) => {
+                error!("get_rank_match_legend error: {}", e);
+                Err(e.context("Couldn't get rank match legend"))
+            }
+        }
+    }
+
+    async fn get_rank_match_mr(
+        &self,
+        page: i64,
+        char_id: i64,
+    ) -> Result<Vec<responses::MrPlayer>, Error> {
+        let token = self.token().await?;
+        let request = requests::generate_rank_match_mr_request(&token, page, char_id);
+
+        match send::<_, _, responses::RankMatchMr>(self, "ranking/rank_match_mr", request, &token).await {
+            Ok(r) => Ok(r.body.players),
+            Err(e) => {
+                error!("get_rank_match_mr error: {}", e);
+                Err(e.context("Couldn't get rank match MR"))
+            }
+        }
+    }
+
+    async fn get_rank_match_lp(
+        &self,
+        page: i64,
+        char_id: i64,
+    ) -> Result<Vec<responses::LpPlayer>, Error> {
+        let token = self.token().await?;
+        let request = requests::generate_rank_match_lp_request(&token, page, char_id);
+
+        match send::<_, _, responses::RankMatchLp>(self, "ranking/rank_match_lp", request, &token).await {
+            Ok(r) => Ok(r.body.players),
+            Err(e) => {
+                error!("get_rank_match_lp error: {}", e);
+                Err(e.context("Couldn't get rank match LP"))
+            }
+        }
     }
 }
 
-pub async fn get_player_avatar(player_id: String) -> Result<String, String> {
-    let request_data = requests::generate_player_avatar_request(player_id);
-    let request_data = encrypt_data(&request_data);
+/// Posts `request` and decrypts the response.
+///
+/// An expired session or a patch doesn't fail the request, GGST answers with its usual header and
+/// a body we can't read. When that header asks for another API version, the request is sent again
+/// with it once, and the version is kept if that one is answered. When its status says the session
+/// expired `token` gets invalidated, so the next request logs in again.
+async fn send<A, T, B>(
+    api: &A,
+    endpoint: &str,
+    mut request: requests::Request<T>,
+    token: &str,
+) -> Result<Response<B>, Error>
+where
+    A: GgstApi + ?Sized,
+    T: Serialize,
+    B: for<'a> Deserialize<'a>,
+{
+    let fixture = fixture_name(endpoint, &body_value(request.body()));
+    let mut retried_with: Option<String> = None;
+
+    loop {
+        let start = std::time::Instant::now();
+        let response_bytes = api.post(endpoint, &fixture, encrypt_data(&request)).await;
+        metrics::record_ggst_latency(endpoint, start.elapsed());
+        let response_bytes = response_bytes.inspect_err(|_| metrics::record_ggst_error(endpoint, "transport"))?;
+
+        let error = match decrypt_response::<B>(&response_bytes).map_err(|e| e.to_string()) {
+            Ok(r) => {
+                if let Some(version) = retried_with {
+                    api.set_api_version(&version).await;
+                }
+                return Ok(r);
+            }
+            Err(e) => e,
+        };
 
-    let response = HTTP_CLIENT
-        .post("https://ggst-game.guiltygear.com/api/tus/read")
-        .header(header::CACHE_CONTROL, "no-store")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header("x-client-version", "1")
-        .form(&[("data", request_data)])
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
+        let header = match decrypt_response::<IgnoredAny>(&response_bytes) {
+            Ok(refused) => refused.header,
+            Err(_) => {
+                metrics::record_ggst_error(endpoint, "unreadable");
+                return Err(Error::UpstreamUnavailable(error));
+            }
+        };
+        metrics::record_ggst_error(endpoint, "refused");
 
-    let response_bytes = response.bytes().await.map_err(|e| e.to_string())?;
+        if retried_with.is_none() && !header.version.is_empty() && header.version != request.version() {
+            warn!("GGST wants API version {} instead of {}, retrying {endpoint}", header.version, request.version());
+            request.set_version(header.version.clone());
+            retried_with = Some(header.version);
+            continue;
+        }
 
-    if let Ok(r) = decrypt_response::<responses::PlayerAvatar>(&response_bytes) {
-        Ok(r.body.png)
-    } else {
-        Err("Couldn't get player avatar".to_owned())
+        if EXPIRED_STATUSES.contains(&header.status) {
+            warn!("GGST refused {endpoint} (status {}), dropping the token", header.status);
+            api.invalidate_token(token).await;
+        } else {
+            warn!("GGST refused {endpoint} (status {})", header.status);
+        }
+        return Err(Error::UpstreamUnavailable(format!("GGST refused the request (status {})", header.status)));
     }
 }
 
-pub async fn get_token() -> Result<String, String> {
-    // Hold the lock for the entire check-and-fetch so concurrent callers
-    // block here rather than racing to open duplicate GGST login requests.
-    let mut token = TOKEN.lock().await;
-
-    if let Some(t) = token.deref() {
-        debug!("Already have a strive token");
-        return Ok(t.to_owned());
-    }
-
-    warn!("Grabbing steam token");
-    let request_data = requests::generate_login_request().await;
-    let request_data = encrypt_data(&request_data);
-
-    let response = HTTP_CLIENT
-        .post("https://ggst-game.guiltygear.com/api/user/login")
-        .header(header::CACHE_CONTROL, "no-store")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header("x-client-version", "1")
-        .form(&[("data", request_data)])
-        .send()
-        .await
-        .unwrap_or_else(|err| {
-            error!("get_token send() error: {}", err);
-            panic!("Critical TLS or Network failure: {}", err);
-        });
+/// The request body the way it reads after a msgpack round trip, which is also how the
+/// stand-in server sees it.
+fn body_value<T: Serialize>(body: &T) -> Value {
+    rmp_serde::from_slice(&rmp_serde::to_vec(body).unwrap()).unwrap()
+}
 
-    let response_bytes = response.bytes().await.unwrap();
+/// File a response is recorded under: the endpoint and a hash of the request body.
+/// The header is left out, so fixtures survive new tokens and versions.
+pub fn fixture_name(endpoint: &str, body: &Value) -> String {
+    let hash = hex::encode(Sha256::digest(body.to_string().as_bytes()));
+    format!("{}-{}.bin", endpoint.replace('/', "_"), &hash[..16])
+}
 
-    if let Ok(r) = decrypt_response::<responses::Login>(&response_bytes) {
-        info!("Got token: {}", r.header.token);
-        *token = Some(r.header.token.to_owned());
-        let _ = std::fs::write("token.txt", r.header.token.clone());
-        Ok(r.header.token)
-    } else {
-        Err("Couldn't get strive token".to_owned())
+/// The live API, or the fixtures in GGST_FIXTURE_DIR to run without it
+pub fn from_env(can_login: bool) -> Arc<dyn GgstApi> {
+    match dotenv::var("GGST_FIXTURE_DIR") {
+        Ok(dir) => {
+            warn!("Using GGST fixtures from {dir}");
+            Arc::new(FixtureApi::new(PathBuf::from(dir)))
+        }
+        Err(_) => Arc::new(HttpApi::live(can_login)),
     }
 }
 
-pub async fn get_replays() -> Result<Vec<responses::Replay>, String> {
-    let token = get_token().await?;
-    let mut replays = Vec::new();
+/// The game's servers, or anything that speaks the same protocol at `base_url`
+pub struct HttpApi {
+    base_url: String,
+    /// Every response is also written here, for `FixtureApi`
+    record_dir: Option<PathBuf>,
+    /// Only processes that pull log in, the web server uses the token the puller saved
+    can_login: bool,
+}
+
+impl HttpApi {
+    /// The live API, GGST_RECORD_DIR records the responses
+    pub fn live(can_login: bool) -> HttpApi {
+        HttpApi::new(LIVE_API_URL, dotenv::var("GGST_RECORD_DIR").ok().map(PathBuf::from), can_login)
+    }
+
+    pub fn new(base_url: &str, record_dir: Option<PathBuf>, can_login: bool) -> HttpApi {
+        HttpApi {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            record_dir,
+            can_login,
+        }
+    }
+
+    /// Logs in, once more with the version GGST asks for if it refuses ours. That version is only
+    /// kept if the second login works.
+    async fn login(&self) -> Result<String, Error> {
+        let mut retried_with: Option<String> = None;
+
+        loop {
+            let response_bytes = self.post_login(retried_with.clone()).await?;
+
+            match decrypt_response::<responses::Login>(&response_bytes).map_err(|e| e.to_string()) {
+                Ok(r) => {
+                    info!("Got token: {}", r.header.token);
+                    if let Some(version) = retried_with {
+                        self.set_api_version(&version).await;
+                    }
+                    return Ok(r.header.token);
+                }
+                Err(e) => {
+                    metrics::record_ggst_error("user/login", "refused");
+                    if retried_with.is_none()
+                        && let Some(refused) = decrypt_response::<IgnoredAny>(&response_bytes).ok()
+                        && !refused.header.version.is_empty()
+                        && refused.header.version != requests::api_version()
+                    {
+                        warn!("GGST wants API version {} for login, retrying", refused.header.version);
+                        retried_with = Some(refused.header.version);
+                        continue;
+                    }
+                    return Err(Error::UpstreamUnavailable(format!("Couldn't get strive token: {e}")));
+                }
+            }
+        }
+    }
 
-    for i in 0..5 {
-        debug!("Grabbing replays (page {i})");
-        let request_data = requests::generate_replay_request(i, 127, &token);
+    /// Posts a login request with a new Steam ticket, with `version` instead of ours if given
+    async fn post_login(&self, version: Option<String>) -> Result<Vec<u8>, Error> {
+        warn!("Grabbing steam token");
+        let mut request_data = tokio::task::spawn_blocking(requests::generate_login_request)
+            .await
+            .map_err(|e| Error::Internal(format!("Steam login panicked: {e}")))?
+            .map_err(Error::UpstreamUnavailable)?;
+        if let Some(version) = version {
+            request_data.set_version(version);
+        }
         let request_data = encrypt_data(&request_data);
 
+        let start = std::time::Instant::now();
         let response = HTTP_CLIENT
-            .post("https://ggst-game.guiltygear.com/api/catalog/get_replay")
+            .post(format!("{}/user/login", self.base_url))
             .header(header::CACHE_CONTROL, "no-store")
             .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
             .header("x-client-version", "1")
             .form(&[("data", request_data)])
             .send()
-            .await
-            .map_err(|err| {
-                error!("get_replay (page {}) send() error: {}", i, err);
-                err.to_string()
-            })?;
-
-        let response_bytes = response.bytes().await.map_err(|e| e.to_string())?;
-
-        match decrypt_response::<responses::Replays>(&response_bytes) {
-            Ok(r) => replays.extend_from_slice(&r.body.replays),
-            Err(err) => {
-                error!("get_replay decrypt_response() error: {}", err);
-                return Err("Failed to decrypt replay data".to_owned());
+            .await;
+        metrics::record_ggst_latency("user/login", start.elapsed());
+        let response = response.map_err(|err| {
+            metrics::record_ggst_error("user/login", "transport");
+            Error::UpstreamUnavailable(format!("Login request failed: {}", err))
+        })?;
+
+        let response_bytes = response.bytes().await.map_err(|e| {
+            metrics::record_ggst_error("user/login", "transport");
+            Error::UpstreamUnavailable(e.to_string())
+        })?;
+
+        Ok(response_bytes.to_vec())
+    }
+}
+
+#[async_trait]
+impl GgstApi for HttpApi {
+    async fn token(&self) -> Result<String, Error> {
+        if let Some(token) = saved_token().await {
+            return Ok(token);
+        }
+
+        if !self.can_login {
+            return Err(Error::UpstreamUnavailable("Not logged in to GGST".to_owned()));
+        }
+
+        // AUTH stays free during the login, for the status and invalidations
+        let _login = LOGIN.lock().await;
+        if let Some(token) = saved_token().await {
+            debug!("Got the token from a concurrent login");
+            return Ok(token);
+        }
+
+        if let Some(next_login) = AUTH.lock().await.next_login
+            && Utc::now() < next_login
+        {
+            return Err(Error::UpstreamUnavailable(format!(
+                "GGST login failed, next attempt at {}",
+                next_login.naive_utc()
+            )));
+        }
+
+        let result = self.login().await;
+        let mut auth = AUTH.lock().await;
+        match result {
+            Ok(token) => {
+                *auth = Auth {
+                    token: Some(token.clone()),
+                    ..Default::default()
+                };
+                let _ = std::fs::write("token.txt", &token);
+                Ok(token)
             }
-        };
+            Err(e) => {
+                auth.failures += 1;
+                let wait = login_backoff(auth.failures);
+                auth.next_login = Some(Utc::now() + wait);
+                auth.last_error = Some(e.to_string());
+                error!("GGST login failed ({} in a row), retrying in {}s: {e}", auth.failures, wait.num_seconds());
+                Err(e)
+            }
+        }
     }
 
-    Ok(replays)
-}
+    async fn is_connected(&self) -> bool {
+        AUTH.lock().await.token.is_some() || std::fs::exists("token.txt").unwrap_or(false)
+    }
 
-pub async fn get_rank_match_legend() -> Result<Vec<responses::LegendPlayer>, String> {
-    let token = get_token().await?;
-    let request_data = requests::generate_rank_match_legend_request(&token);
-    let request_data = encrypt_data(&request_data);
-
-    let response = HTTP_CLIENT
-        .post("https://ggst-game.guiltygear.com/api/ranking/get_rank_match_legend")
-        .header(header::CACHE_CONTROL, "no-store")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header("x-client-version", "1")
-        .form(&[("data", request_data)])
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
-
-    let response_bytes = response.bytes().await.map_err(|e| e.to_string())?;
-
-    match decrypt_response::<responses::RankMatchLegend>(&response_bytes) {
-        Ok(r) => Ok(r.body.players),
-        Err(e) => {
-            error!("get_rank_match_legend decrypt_response() error: {}", e);
-            Err("Couldn't get rank match legend".to_owned())
+    async fn invalidate_token(&self, token: &str) {
+        let mut auth = AUTH.lock().await;
+        if auth.token.as_deref() == Some(token) {
+            auth.token = None;
+        }
+
+        // Until the puller logs in again, so the web server reports GGST as disconnected. The web
+        // server can't log in, it leaves the file be and reads it again on the next request.
+        if self.can_login && std::fs::read_to_string("token.txt").is_ok_and(|t| t.trim() == token) {
+            let _ = std::fs::remove_file("token.txt");
         }
     }
-}
 
-pub async fn get_rank_match_mr(
-    page: i64,
-    char_id: i64,
-) -> Result<Vec<responses::MrPlayer>, String> {
-    let token = get_token().await?;
-    let request_data = requests::generate_rank_match_mr_request(&token, page, char_id);
-    let request_data = encrypt_data(&request_data);
-
-    let response = HTTP_CLIENT
-        .post("https://ggst-game.guiltygear.com/api/ranking/rank_match_mr")
-        .header(header::CACHE_CONTROL, "no-store")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header("x-client-version", "1")
-        .form(&[("data", request_data)])
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
-
-    let response_bytes = response.bytes().await.map_err(|e| e.to_string())?;
-
-    match decrypt_response::<responses::RankMatchMr>(&response_bytes) {
-        Ok(r) => Ok(r.body.players),
-        Err(e) => {
-            error!("get_rank_match_mr decrypt_response() error: {}", e);
-            Err("Couldn't get rank match MR".to_owned())
+    async fn auth_status(&self) -> AuthStatus {
+        let connected = self.is_connected().await;
+        let auth = AUTH.lock().await;
+
+        AuthStatus {
+            connected,
+            failed_logins: auth.failures,
+            next_login: auth.next_login.map(|t| t.naive_utc().to_string()),
+            last_error: auth.last_error.clone(),
+            token_since: std::fs::metadata("token.txt")
+                .and_then(|m| m.modified())
+                .ok()
+                .filter(|_| connected)
+                .map(|t| DateTime::<Utc>::from(t).format("%Y-%m-%d %H:%M:%S").to_string()),
         }
     }
-}
 
-pub async fn get_rank_match_lp(
-    page: i64,
-    char_id: i64,
-) -> Result<Vec<responses::LpPlayer>, String> {
-    let token = get_token().await?;
-    let request_data = requests::generate_rank_match_lp_request(&token, page, char_id);
-    let request_data = encrypt_data(&request_data);
-
-    let response = HTTP_CLIENT
-        .post("https://ggst-game.guiltygear.com/api/ranking/rank_match_lp")
-        .header(header::CACHE_CONTROL, "no-store")
-        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header("x-client-version", "1")
-        .form(&[("data", request_data)])
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
-
-    let response_bytes = response.bytes().await.map_err(|e| e.to_string())?;
-
-    match decrypt_response::<responses::RankMatchLp>(&response_bytes) {
-        Ok(r) => Ok(r.body.players),
-        Err(e) => {
-            error!("get_rank_match_lp decrypt_response() error: {}", e);
-            Err("Couldn't get rank match LP".to_owned())
+    async fn post(&self, endpoint: &str, fixture: &str, data: String) -> Result<Vec<u8>, Error> {
+        let response = HTTP_CLIENT
+            .post(format!("{}/{}", self.base_url, endpoint))
+            .header(header::CACHE_CONTROL, "no-store")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header("x-client-version", "1")
+            .form(&[("data", data)])
+            .send()
+            .await
+            .map_err(|e| Error::UpstreamUnavailable(format!("Request failed: {}", e)))?;
+
+        let response_bytes = response.bytes().await.map_err(|e| Error::UpstreamUnavailable(e.to_string()))?.to_vec();
+
+        if let Some(dir) = &self.record_dir
+            && let Err(e) = std::fs::write(dir.join(fixture), &response_bytes)
+        {
+            warn!("Recording {fixture} failed: {e}");
         }
+
+        Ok(response_bytes)
     }
 }
 
-fn encrypt_data<T: Serialize>(data: &T) -> String {
-    let key =
-        hex::decode("EEBC1F57487F51921C0465665F8AE6D1658BB26DE6F8A069A3520293A572078F").unwrap();
+/// Answers from responses `HttpApi` recorded, for running the puller and handlers offline
+pub struct FixtureApi {
+    dir: PathBuf,
+}
 
-    let bytes = rmp_serde::to_vec(data).unwrap();
+impl FixtureApi {
+    pub fn new(dir: PathBuf) -> FixtureApi {
+        FixtureApi { dir }
+    }
+}
+
+#[async_trait]
+impl GgstApi for FixtureApi {
+    async fn token(&self) -> Result<String, Error> {
+        Ok("fixture".to_string())
+    }
+
+    async fn is_connected(&self) -> bool {
+        true
+    }
+
+    async fn post(&self, endpoint: &str, fixture: &str, _data: String) -> Result<Vec<u8>, Error> {
+        std::fs::read(self.dir.join(fixture)).map_err(|e| Error::UpstreamUnavailable(format!("No fixture {fixture} for {endpoint}: {e}")))
+    }
+}
+
+fn cipher() -> Aes256Gcm {
+    Aes256Gcm::new_from_slice(&hex::decode(KEY).unwrap()).unwrap()
+}
+
+/// Nonce followed by the AES-GCM ciphertext of `bytes`, the format of both requests and responses
+fn encrypt(bytes: &[u8]) -> Vec<u8> {
     //let mut nonce = [0u8; 12];
     //getrandom(&mut nonce).unwrap();
     let nonce: [u8; 12] = *b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
     let nonce_ga = nonce.into();
 
-    let aes_gcm = Aes256Gcm::new_from_slice(&key).unwrap();
-    let encrypted = aes_gcm.encrypt(&nonce_ga, &bytes[..]).unwrap();
+    let encrypted = cipher().encrypt(&nonce_ga, bytes).unwrap();
 
     let mut data: Vec<u8> = Vec::new();
     data.extend_from_slice(&nonce);
     data.extend_from_slice(&encrypted);
-
-    let r = base64_url::encode(&data);
-
-    r
+    data
 }
 
-fn decrypt_response<T: for<'a> Deserialize<'a>>(
-    bytes: &[u8],
-) -> Result<Response<T>, Box<dyn Error>> {
-    let key =
-        hex::decode("EEBC1F57487F51921C0465665F8AE6D1658BB26DE6F8A069A3520293A572078F").unwrap();
-    let aes_gcm = Aes256Gcm::new_from_slice(&key).unwrap();
-
+fn decrypt(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     if bytes.len() < 12 {
         error!("decrypt_response: response too short ({} bytes)", bytes.len());
         return Err(format!("response too short: {} bytes", bytes.len()).into());
@@ -295,13 +570,25 @@ fn decrypt_response<T: for<'a> Deserialize<'a>>(
     nonce.copy_from_slice(&bytes[..12]);
     let nonce = GenericArray::from(nonce);
 
-    let decrypted = match aes_gcm.decrypt(&nonce, &bytes[12..]) {
-        Ok(decrypted) => decrypted,
+    match cipher().decrypt(&nonce, &bytes[12..]) {
+        Ok(decrypted) => Ok(decrypted),
         Err(e) => {
             error!("decrypt_response: AES-GCM decrypt failed: {:?}", e);
-            return Err(format!("decrypt failed: {:?}", e).into());
+            Err(format!("decrypt failed: {:?}", e).into())
         }
-    };
+    }
+}
+
+fn encrypt_data<T: Serialize>(data: &T) -> String {
+    let bytes = rmp_serde::to_vec(data).unwrap();
+
+    base64_url::encode(&encrypt(&bytes))
+}
+
+fn decrypt_response<T: for<'a> Deserialize<'a>>(
+    bytes: &[u8],
+) -> Result<Response<T>, Box<dyn std::error::Error>> {
+    let decrypted = decrypt(bytes)?;
 
     match rmp_serde::from_slice::<responses::Response<T>>(&decrypted) {
         Ok(r) => Ok(r),
@@ -320,3 +607,362 @@ fn decrypt_response<T: for<'a> Deserialize<'a>>(
         }
     }
 }
+
+/// Writing fixtures by hand and serving them over HTTP like the game's API does
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{Form, Router};
+    use std::collections::HashMap;
+
+    /// Responses are msgpack arrays, these tuples serialize the same as the structs in `responses`
+    type ResponseHeader = (String, i64, String, String, String, String, String, String);
+    type Player = (String, String, String, String, i64, i64, i64, i64);
+    pub type Replay = (u64, i64, i64, i64, i64, Player, Player, i64, String, i64, u64, i64, u64);
+    type MrPlayer = (i64, i64, i64, String, String, String, String, i64, i64);
+    type LpPlayer = (i64, i64, i64, i64, String, String, String, String, i64, i64);
+
+    /// Requests need these to build their header, webhook and embed links the site url
+    pub fn init_env() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            for (key, value) in [("PLAYER_ID", "fixture"), ("API_VERSION", "fixture"), ("SITE_URL", "https://puddle.farm")] {
+                if dotenv::var(key).is_err() {
+                    // SAFETY: once, before the first request reads them through the requests lazy statics
+                    unsafe { std::env::set_var(key, value) };
+                }
+            }
+        });
+    }
+
+    fn header() -> ResponseHeader {
+        let text = || "fixture".to_string();
+        (text(), 0, "2026-10-18 12:00:00".to_string(), text(), text(), text(), text(), text())
+    }
+
+    fn player(id: i64, name: &str, platform: i64, rating: i64) -> Player {
+        (id.to_string(), name.to_string(), String::new(), String::new(), platform, 0, rating, 0)
+    }
+
+    /// (id, name, character, rating) of both players, `winner` is 1 or 2
+    pub fn replay(
+        timestamp: &str,
+        floor: i64,
+        (id_a, name_a, char_a, rating_a): (i64, &str, i64, i64),
+        (id_b, name_b, char_b, rating_b): (i64, &str, i64, i64),
+        winner: i64,
+    ) -> Replay {
+        (
+            0,
+            0,
+            floor,
+            char_a,
+            char_b,
+            player(id_a, name_a, 3, rating_a),
+            player(id_b, name_b, 3, rating_b),
+            winner,
+            timestamp.to_string(),
+            0,
+            0,
+            0,
+            0,
+        )
+    }
+
+    /// (rank, dr, character, id, name)
+    pub fn mr_player((rank, dr, char_id, id, name): (i64, i64, i64, i64, &str)) -> MrPlayer {
+        (rank, dr, char_id, id.to_string(), name.to_string(), String::new(), String::new(), 0, 0)
+    }
+
+    /// (rank, lp, character, id, name)
+    pub fn lp_player((rank, lp, char_id, id, name): (i64, i64, i64, i64, &str)) -> LpPlayer {
+        (rank, 0, lp, char_id, id.to_string(), name.to_string(), String::new(), String::new(), 0, 0)
+    }
+
+    fn write<R: Serialize, B: Serialize>(dir: &std::path::Path, endpoint: &str, request: &requests::Request<R>, body: B) {
+        let name = fixture_name(endpoint, &body_value(request.body()));
+        let bytes = rmp_serde::to_vec(&(header(), body)).unwrap();
+        std::fs::write(dir.join(name), encrypt(&bytes)).unwrap();
+    }
+
+    pub fn write_replay_page(dir: &std::path::Path, index: usize, query: &requests::ReplayQuery, replays: Vec<Replay>) {
+        init_env();
+        let request = requests::generate_replay_request(index, REPLAYS_PER_PAGE, query, "fixture");
+        write(dir, "catalog/get_replay", &request, (0i64, 0i64, 0i64, replays));
+    }
+
+    /// What GGST answers once the token expired or after a patch: its header with `status` and
+    /// the API `version` it wants, and nothing we can read
+    pub fn refused(status: i64, version: &str) -> Vec<u8> {
+        let mut header = header();
+        header.1 = status;
+        header.3 = version.to_string();
+        encrypt(&rmp_serde::to_vec(&(header, ())).unwrap())
+    }
+
+    pub fn write_refused_replay_page(dir: &std::path::Path, index: usize, query: &requests::ReplayQuery, status: i64) {
+        init_env();
+        let request = requests::generate_replay_request(index, REPLAYS_PER_PAGE, query, "fixture");
+        let name = fixture_name("catalog/get_replay", &body_value(request.body()));
+        std::fs::write(dir.join(name), refused(status, "fixture")).unwrap();
+    }
+
+    /// API version in the header of a posted request
+    pub fn request_version(data: &str) -> Option<String> {
+        let data = decrypt(&base64_url::decode(data).ok()?).ok()?;
+        let (header, _body) = rmp_serde::from_slice::<(Vec<Value>, Value)>(&data).ok()?;
+        header.get(3)?.as_str().map(|v| v.to_string())
+    }
+
+    pub fn write_mr_page(dir: &std::path::Path, page: i64, char_id: i64, players: Vec<MrPlayer>) {
+        init_env();
+        let request = requests::generate_rank_match_mr_request("fixture", page, char_id);
+        let to_date = "2026-10-18".to_string();
+        write(dir, "ranking/rank_match_mr", &request, (0i64, 0i64, 0i64, 0i64, players, 0f64, to_date.clone(), to_date));
+    }
+
+    pub fn write_lp_page(dir: &std::path::Path, page: i64, char_id: i64, players: Vec<LpPlayer>) {
+        init_env();
+        let request = requests::generate_rank_match_lp_request("fixture", page, char_id);
+        write(dir, "ranking/rank_match_lp", &request, (0i64, 0i64, 0i64, 0i64, players, 0f64));
+    }
+
+    pub fn write_player_stats(dir: &std::path::Path, player_id: i64, json: &str) {
+        init_env();
+        let request = requests::generate_player_stats_request(player_id.to_string(), "fixture");
+        write(dir, "statistics/get", &request, (0i64, json.to_string(), 0i64, Vec::<(i64, i64)>::new()));
+    }
+
+    /// An empty scratch directory for one test's fixtures
+    pub fn dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ggst-fixtures-{}-{}", test, uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Serves the fixtures in `dir` on a random local port the way the game's API would: it decrypts
+    /// the request, looks up the fixture for its body and answers with the recorded bytes.
+    /// Returns the base url for `HttpApi::new`.
+    pub async fn serve(dir: PathBuf) -> String {
+        let app = Router::new().route(
+            "/api/*endpoint",
+            post(move |Path(endpoint): Path<String>, Form(form): Form<HashMap<String, String>>| async move {
+                let request = form
+                    .get("data")
+                    .and_then(|data| base64_url::decode(data).ok())
+                    .and_then(|data| decrypt(&data).ok())
+                    .and_then(|data| rmp_serde::from_slice::<(Value, Value)>(&data).ok());
+
+                let Some((_header, body)) = request else {
+                    return Err(StatusCode::BAD_REQUEST);
+                };
+
+                std::fs::read(dir.join(fixture_name(&endpoint, &body))).map_err(|_| StatusCode::NOT_FOUND)
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        format!("http://{}/api", addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page() -> Vec<fixtures::Replay> {
+        vec![
+            fixtures::replay("2026-10-18 12:01:00", 99, (1, "Alice", 0, 10001500), (2, "Bob", 1, 10001400), 1),
+            fixtures::replay("2026-10-18 12:00:00", 99, (2, "Bob", 1, 10001410), (3, "Carol", 2, 30000), 2),
+        ]
+    }
+
+    #[tokio::test]
+    async fn fixture_api_reads_written_pages() {
+        let dir = fixtures::dir("fixture-api");
+        let query = requests::ReplayQuery::default();
+        fixtures::write_replay_page(&dir, 0, &query, page());
+
+        let api = FixtureApi::new(dir);
+        let replays = api.get_replay_page(0, &query).await.unwrap();
+
+        assert_eq!(replays.len(), 2);
+        assert_eq!(replays[0].player1.name, "Alice");
+        assert_eq!(replays[0].player2.rating, 10001400);
+        assert_eq!(replays[1].winner, 2);
+
+        // Every query is its own fixture
+        assert!(api.get_replay_page(1, &query).await.is_err());
+        assert!(api.get_replay_page(0, &requests::ReplayQuery::character(3)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn http_api_records_stand_in_responses() {
+        let served = fixtures::dir("served");
+        fixtures::write_mr_page(&served, 0, -1, vec![fixtures::mr_player((1, 1800, 4, 10, "Dan"))]);
+
+        let recorded = fixtures::dir("recorded");
+        let api = HttpApi::new(&fixtures::serve(served).await, Some(recorded.clone()), false);
+        set_token("fixture".to_string()).await;
+
+        let players = api.get_rank_match_mr(0, -1).await.unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0].player_name, "Dan");
+        assert!(api.get_rank_match_mr(1, -1).await.is_err());
+
+        // What was recorded plays back the same without a server
+        let players = FixtureApi::new(recorded).get_rank_match_mr(0, -1).await.unwrap();
+        assert_eq!(players[0].dr, 1800);
+    }
+
+    /// FixtureApi that remembers which tokens it was asked to drop
+    struct InvalidatingApi {
+        fixtures: FixtureApi,
+        invalidated: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl GgstApi for InvalidatingApi {
+        async fn token(&self) -> Result<String, Error> {
+            self.fixtures.token().await
+        }
+
+        async fn is_connected(&self) -> bool {
+            self.invalidated.lock().unwrap().is_empty()
+        }
+
+        async fn post(&self, endpoint: &str, fixture: &str, data: String) -> Result<Vec<u8>, Error> {
+            self.fixtures.post(endpoint, fixture, data).await
+        }
+
+        async fn invalidate_token(&self, token: &str) {
+            self.invalidated.lock().unwrap().push(token.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn refused_response_invalidates_token() {
+        let dir = fixtures::dir("refused");
+        let query = requests::ReplayQuery::default();
+        fixtures::write_replay_page(&dir, 0, &query, page());
+        fixtures::write_refused_replay_page(&dir, 1, &query, 1);
+
+        let api = InvalidatingApi {
+            fixtures: FixtureApi::new(dir),
+            invalidated: std::sync::Mutex::new(vec![]),
+        };
+
+        assert!(api.get_replay_page(0, &query).await.is_ok());
+        assert!(api.auth_status().await.connected);
+
+        let e = api.get_replay_page(1, &query).await.unwrap_err();
+        assert!(e.message().contains("status 1"), "{e}");
+        assert_eq!(*api.invalidated.lock().unwrap(), vec!["fixture".to_string()]);
+        assert!(!api.auth_status().await.connected);
+
+        // A missing fixture is not GGST refusing the token
+        assert!(api.get_replay_page(2, &query).await.is_err());
+        assert_eq!(api.invalidated.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn other_refusals_keep_the_token() {
+        let dir = fixtures::dir("refused-request");
+        let query = requests::ReplayQuery::default();
+        fixtures::write_refused_replay_page(&dir, 0, &query, 2);
+
+        let api = InvalidatingApi {
+            fixtures: FixtureApi::new(dir),
+            invalidated: std::sync::Mutex::new(vec![]),
+        };
+
+        let e = api.get_replay_page(0, &query).await.unwrap_err();
+        assert!(e.message().contains("status 2"), "{e}");
+        assert!(api.invalidated.lock().unwrap().is_empty());
+        assert!(api.auth_status().await.connected);
+    }
+
+    #[test]
+    fn login_backoff_grows_and_caps() {
+        assert_eq!(login_backoff(1), chrono::Duration::seconds(30));
+        assert_eq!(login_backoff(3), chrono::Duration::seconds(120));
+        assert_eq!(login_backoff(100), chrono::Duration::seconds(MAX_LOGIN_BACKOFF_SECONDS));
+    }
+
+    #[test]
+    fn session_expired_statuses_from_env() {
+        assert_eq!(parse_statuses("1, 7"), vec![1, 7]);
+        assert_eq!(parse_statuses("1,x,"), vec![1]);
+    }
+
+    /// Answers like GGST after a patch until a request comes with the "patched" version
+    struct PatchedApi {
+        fixtures: FixtureApi,
+        sent_versions: std::sync::Mutex<Vec<String>>,
+        adopted_version: std::sync::Mutex<Option<String>>,
+    }
+
+    #[async_trait]
+    impl GgstApi for PatchedApi {
+        async fn token(&self) -> Result<String, Error> {
+            self.fixtures.token().await
+        }
+
+        async fn is_connected(&self) -> bool {
+            true
+        }
+
+        async fn post(&self, endpoint: &str, fixture: &str, data: String) -> Result<Vec<u8>, Error> {
+            let version = fixtures::request_version(&data).unwrap();
+            self.sent_versions.lock().unwrap().push(version.clone());
+
+            if version == "patched" {
+                self.fixtures.post(endpoint, fixture, data).await
+            } else {
+                Ok(fixtures::refused(1, "patched"))
+            }
+        }
+
+        async fn set_api_version(&self, version: &str) {
+            *self.adopted_version.lock().unwrap() = Some(version.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn version_mismatch_retries_with_new_version() {
+        let dir = fixtures::dir("patched");
+        let query = requests::ReplayQuery::default();
+        fixtures::write_replay_page(&dir, 0, &query, page());
+
+        let api = PatchedApi {
+            fixtures: FixtureApi::new(dir),
+            sent_versions: std::sync::Mutex::new(vec![]),
+            adopted_version: std::sync::Mutex::new(None),
+        };
+
+        let replays = api.get_replay_page(0, &query).await.unwrap();
+        assert_eq!(replays.len(), 2);
+        assert_eq!(*api.sent_versions.lock().unwrap(), vec!["fixture", "patched"]);
+        assert_eq!(api.adopted_version.lock().unwrap().as_deref(), Some("patched"));
+    }
+
+    #[tokio::test]
+    async fn version_is_only_kept_once_answered() {
+        // No fixture, the retry with the new version fails too
+        let api = PatchedApi {
+            fixtures: FixtureApi::new(fixtures::dir("patched-unanswered")),
+            sent_versions: std::sync::Mutex::new(vec![]),
+            adopted_version: std::sync::Mutex::new(None),
+        };
+
+        assert!(api.get_replay_page(0, &requests::ReplayQuery::default()).await.is_err());
+        assert_eq!(*api.sent_versions.lock().unwrap(), vec!["fixture", "patched"]);
+        assert_eq!(*api.adopted_version.lock().unwrap(), None);
+    }
+}
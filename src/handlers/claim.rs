@@ -0,0 +1,49 @@
+use rand::Rng;
+
+/// How long a claim code stays valid after being issued.
+pub const CLAIM_CODE_TTL_MINUTES: i64 = 15;
+
+const CLAIM_CODE_LENGTH: usize = 8;
+
+// No 0/O or 1/I, the code has to be typed in-game with a controller.
+const CLAIM_CODE_CHARSET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+pub fn generate_claim_code() -> String {
+    let mut rng = rand::rng();
+
+    (0..CLAIM_CODE_LENGTH)
+        .map(|_| CLAIM_CODE_CHARSET[rng.random_range(0..CLAIM_CODE_CHARSET.len())] as char)
+        .collect()
+}
+
+pub fn comment_contains_code(comment: &str, code: &str) -> bool {
+    !code.is_empty() && comment.to_uppercase().contains(&code.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_claim_code_format() {
+        let code = generate_claim_code();
+
+        assert_eq!(code.len(), CLAIM_CODE_LENGTH);
+        assert!(code.bytes().all(|b| CLAIM_CODE_CHARSET.contains(&b)));
+    }
+
+    #[test]
+    fn comment_contains_code_ignores_case_and_surrounding_text() {
+        assert!(comment_contains_code("gg! abcd2345 see you", "ABCD2345"));
+    }
+
+    #[test]
+    fn comment_contains_code_missing() {
+        assert!(!comment_contains_code("gg!", "ABCD2345"));
+    }
+
+    #[test]
+    fn comment_contains_code_empty_code() {
+        assert!(!comment_contains_code("gg!", ""));
+    }
+}
@@ -6,6 +6,23 @@ pub struct Pagination {
     pub offset: Option<usize>,
 }
 
+lazy_static::lazy_static! {
+    /// Public address of the site, for links in embeds and webhooks
+    pub static ref SITE_URL: String = dotenv::var("SITE_URL").expect("SITE_URL must be set.");
+}
+
+/// Shown in place of a private player's name wherever they appear.
+pub const PRIVATE_PLAYER_NAME: &str = "Hidden";
+
+pub fn platform_name(platform: i16) -> &'static str {
+    match platform {
+        1 => "PS",
+        2 => "XB",
+        3 => "PC",
+        _ => "???",
+    }
+}
+
 #[derive(Serialize, Clone)]
 pub struct TagResponse {
     pub tag: String,
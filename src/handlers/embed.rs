@@ -0,0 +1,137 @@
+pub struct PlayerEmbed {
+    pub id: i64,
+    pub name: String,
+    pub char_short: String,
+    pub character: String,
+    pub rating: i64,
+    pub global_rank: Option<i32>,
+    pub char_rank: Option<i32>,
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Same units the frontend shows: DR for Vanquisher and up, RP below.
+pub fn format_rating(rating: i64) -> String {
+    if rating >= 10000000 {
+        format!("{} DR", rating - 10000000)
+    } else {
+        format!("{} RP", rating)
+    }
+}
+
+/// Games shown in the rating chart attached to player embeds
+const EMBED_CHART_GAMES: i32 = 100;
+
+/// `images` in order of preference, crawlers that show one take the first
+fn render(site_url: &str, path: &str, title: &str, description: &str, images: &[String]) -> String {
+    let url = format!("{}{}", site_url, path);
+    let mut image_tags: String = images
+        .iter()
+        .map(|image| format!("<meta property=\"og:image\" content=\"{}\">\n", escape_html(image)))
+        .collect();
+    if !images.is_empty() {
+        image_tags.push_str("<meta name=\"twitter:card\" content=\"summary\">\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<meta property=\"og:site_name\" content=\"Puddle Farm\">
+<meta property=\"og:type\" content=\"profile\">
+<meta property=\"og:title\" content=\"{title}\">
+<meta property=\"og:description\" content=\"{description}\">
+<meta property=\"og:url\" content=\"{url}\">
+{image_tags}</head>
+<body><a href=\"{url}\">{title}</a></body>
+</html>
+",
+        title = escape_html(title),
+        description = escape_html(description),
+        url = escape_html(&url),
+        image_tags = image_tags,
+    )
+}
+
+pub fn render_player_embed(site_url: &str, path: &str, embed: &PlayerEmbed) -> String {
+    let mut description = format!("{} - {}", embed.character, format_rating(embed.rating));
+    if let Some(rank) = embed.char_rank {
+        description.push_str(&format!(" - #{} {}", rank, embed.character));
+    }
+    if let Some(rank) = embed.global_rank {
+        description.push_str(&format!(" - #{} Global", rank));
+    }
+
+    // The avatar stays the preview, the rating chart is there for crawlers that show more than one
+    let avatar = format!("{}/api/avatar/{}", site_url, embed.id);
+    let chart = format!(
+        "{}/api/ratings/{}/{}/{}.png",
+        site_url, embed.id, embed.char_short, EMBED_CHART_GAMES
+    );
+
+    render(site_url, path, &embed.name, &description, &[avatar, chart])
+}
+
+pub fn render_default_embed(site_url: &str, path: &str) -> String {
+    render(
+        site_url,
+        path,
+        "Puddle Farm",
+        "Guilty Gear -Strive- ranked match history and leaderboards",
+        &[],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_rating_dr() {
+        assert_eq!(format_rating(10001650), "1650 DR");
+    }
+
+    #[test]
+    fn format_rating_rp() {
+        assert_eq!(format_rating(24400), "24400 RP");
+    }
+
+    #[test]
+    fn render_player_embed_escapes_name() {
+        let html = render_player_embed(
+            "https://puddle.farm",
+            "/player/1",
+            &PlayerEmbed {
+                id: 1,
+                name: "<script>\"x\"</script>".to_string(),
+                char_short: "SO".to_string(),
+                character: "Sol".to_string(),
+                rating: 10001650,
+                global_rank: Some(45),
+                char_rank: Some(12),
+            },
+        );
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;&quot;x&quot;&lt;/script&gt;"));
+        assert!(html.contains("Sol - 1650 DR - #12 Sol - #45 Global"));
+        let avatar = html.find("content=\"https://puddle.farm/api/avatar/1\"").unwrap();
+        let chart = html.find("content=\"https://puddle.farm/api/ratings/1/SO/100.png\"").unwrap();
+        assert!(avatar < chart, "the avatar is the first og:image");
+    }
+}
@@ -0,0 +1,272 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use futures_util::Stream;
+use serde::Serialize;
+
+use crate::{db, error::Error, models, CHAR_NAMES};
+
+use super::common::{platform_name, PRIVATE_PLAYER_NAME};
+
+/// Games read per query while exporting
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// csv when not given
+    pub fn from_param(format: Option<&str>) -> Result<ExportFormat, Error> {
+        match format {
+            None | Some("csv") => Ok(ExportFormat::Csv),
+            Some("ndjson") => Ok(ExportFormat::Ndjson),
+            Some(_) => Err(Error::BadInput("format must be csv or ndjson".to_string())),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// The first line of the file, CSV only
+    pub fn header(self) -> Option<String> {
+        match self {
+            ExportFormat::Csv => Some(format!("{}\n", CSV_COLUMNS.join(","))),
+            ExportFormat::Ndjson => None,
+        }
+    }
+}
+
+const CSV_COLUMNS: &[&str] = &[
+    "timestamp",
+    "floor",
+    "character",
+    "character_short",
+    "rating",
+    "opponent_id",
+    "opponent_name",
+    "opponent_platform",
+    "opponent_character",
+    "opponent_character_short",
+    "opponent_rating",
+    "result",
+];
+
+/// A game from the exported player's side
+#[derive(Serialize)]
+struct ExportRow {
+    timestamp: String,
+    floor: i16,
+    character: &'static str,
+    character_short: &'static str,
+    rating: i64,
+    opponent_id: String,
+    opponent_name: String,
+    opponent_platform: &'static str,
+    opponent_character: &'static str,
+    opponent_character_short: &'static str,
+    opponent_rating: i64,
+    /// "win" or "loss"
+    result: &'static str,
+}
+
+impl ExportRow {
+    fn new(player_id: i64, game: models::Game, private_players: &HashSet<i64>) -> ExportRow {
+        let is_a = game.id_a == player_id;
+        let (own_char, rating) = if is_a { (game.char_a, game.value_a) } else { (game.char_b, game.value_b) };
+        let (opponent_id, opponent_name, opponent_platform, opponent_char, opponent_rating) = if is_a {
+            (game.id_b, game.name_b, game.platform_b, game.char_b, game.value_b)
+        } else {
+            (game.id_a, game.name_a, game.platform_a, game.char_a, game.value_a)
+        };
+        let (opponent_id, opponent_name) = if private_players.contains(&opponent_id) {
+            (0, PRIVATE_PLAYER_NAME.to_string())
+        } else {
+            (opponent_id, opponent_name)
+        };
+        let won = (is_a && game.winner == 1) || (!is_a && game.winner == 2);
+
+        ExportRow {
+            timestamp: game.real_timestamp.unwrap_or(game.timestamp).to_string(),
+            floor: game.game_floor,
+            character: CHAR_NAMES[own_char as usize].1,
+            character_short: CHAR_NAMES[own_char as usize].0,
+            rating,
+            opponent_id: opponent_id.to_string(),
+            opponent_name,
+            opponent_platform: platform_name(opponent_platform),
+            opponent_character: CHAR_NAMES[opponent_char as usize].1,
+            opponent_character_short: CHAR_NAMES[opponent_char as usize].0,
+            opponent_rating,
+            result: if won { "win" } else { "loss" },
+        }
+    }
+
+    /// One line in `format`, newline included
+    fn render(&self, format: ExportFormat) -> Result<String, Error> {
+        match format {
+            ExportFormat::Csv => {
+                let fields = [
+                    Cow::from(self.timestamp.as_str()),
+                    Cow::from(self.floor.to_string()),
+                    csv_field(self.character),
+                    Cow::from(self.character_short),
+                    Cow::from(self.rating.to_string()),
+                    Cow::from(self.opponent_id.as_str()),
+                    csv_field(&self.opponent_name),
+                    Cow::from(self.opponent_platform),
+                    csv_field(self.opponent_character),
+                    Cow::from(self.opponent_character_short),
+                    Cow::from(self.opponent_rating.to_string()),
+                    Cow::from(self.result),
+                ];
+                Ok(format!("{}\n", fields.join(",")))
+            }
+            ExportFormat::Ndjson => Ok(format!("{}\n", serde_json::to_string(self)?)),
+        }
+    }
+}
+
+/// Quoted when it has a comma, quote or line break, which player names can. A name a spreadsheet would
+/// run as a formula (starting with =, +, -, @, tab or carriage return) gets a leading ' so it stays text.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::from(format!("'{}", field))
+    } else {
+        Cow::from(field)
+    };
+
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::from(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        field
+    }
+}
+
+/// (timestamp, id_a, id_b) of the last game exported, None before the first batch
+type Cursor = Option<(chrono::NaiveDateTime, i64, i64)>;
+
+async fn next_batch(
+    pool: &crate::Pool,
+    player_id: i64,
+    format: ExportFormat,
+    after: Cursor,
+) -> Result<(String, Option<Cursor>), Error> {
+    let mut db = pool.get().await?;
+    let games = db::get_games_after(player_id, after, EXPORT_BATCH_SIZE, &mut db).await?;
+
+    let next = match games.last() {
+        Some(last) if games.len() as i64 == EXPORT_BATCH_SIZE => Some(Some((last.timestamp, last.id_a, last.id_b))),
+        _ => None,
+    };
+
+    let opponents = games.iter().flat_map(|g| [g.id_a, g.id_b]).filter(|id| *id != player_id).collect();
+    let private_players = db::get_private_players(opponents, &mut db).await?;
+
+    let mut chunk = String::new();
+    for game in games {
+        chunk.push_str(&ExportRow::new(player_id, game, &private_players).render(format)?);
+    }
+
+    Ok((chunk, next))
+}
+
+/// Every game `player_id` played on any character, oldest first, one chunk per batch so the whole history
+/// is never in memory at once. Starts with the header row for CSV.
+pub fn export_games(
+    pool: crate::Pool,
+    player_id: i64,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<String, Error>> {
+    use futures_util::StreamExt;
+
+    let header = futures_util::stream::iter(format.header().map(Ok));
+    let rows = futures_util::stream::unfold(Some(None), move |cursor: Option<Cursor>| {
+        let pool = pool.clone();
+        async move {
+            // None once the last batch was read
+            let after = cursor?;
+            match next_batch(&pool, player_id, format, after).await {
+                Ok((chunk, next)) => Some((Ok(chunk), next)),
+                Err(e) => Some((Err(e), None)),
+            }
+        }
+    });
+
+    header.chain(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game() -> models::Game {
+        models::Game {
+            timestamp: chrono::DateTime::from_timestamp(0, 0).unwrap().naive_utc(),
+            real_timestamp: None,
+            id_a: 1,
+            name_a: "Test".to_string(),
+            char_a: 0,
+            platform_a: 1,
+            id_b: 2,
+            name_b: "Sol, \"the\" Badguy".to_string(),
+            char_b: 1,
+            platform_b: 3,
+            winner: 2,
+            game_floor: 99,
+            value_a: 1000,
+            value_b: 2000,
+        }
+    }
+
+    #[test]
+    fn csv_rows_are_escaped_and_from_the_players_side() {
+        let row = ExportRow::new(1, game(), &HashSet::new()).render(ExportFormat::Csv).unwrap();
+        assert_eq!(
+            row,
+            format!(
+                "1970-01-01 00:00:00,99,{},{},1000,2,\"Sol, \"\"the\"\" Badguy\",PC,{},{},2000,loss\n",
+                CHAR_NAMES[0].1, CHAR_NAMES[0].0, CHAR_NAMES[1].1, CHAR_NAMES[1].0
+            )
+        );
+    }
+
+    #[test]
+    fn csv_fields_never_start_a_formula() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-Sol"), "'-Sol");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("Sol=Ky"), "Sol=Ky");
+    }
+
+    #[test]
+    fn ndjson_rows_hide_private_opponents() {
+        let row = ExportRow::new(2, game(), &HashSet::from([1])).render(ExportFormat::Ndjson).unwrap();
+        let json: serde_json::Value = serde_json::from_str(row.trim_end()).unwrap();
+        assert_eq!(json["opponent_id"], "0");
+        assert_eq!(json["opponent_name"], PRIVATE_PLAYER_NAME);
+        assert_eq!(json["opponent_platform"], "PS");
+        assert_eq!(json["result"], "win");
+    }
+
+    #[test]
+    fn format_defaults_to_csv() {
+        assert_eq!(ExportFormat::from_param(None).unwrap(), ExportFormat::Csv);
+        assert_eq!(ExportFormat::from_param(Some("ndjson")).unwrap(), ExportFormat::Ndjson);
+        assert!(ExportFormat::from_param(Some("parquet")).is_err());
+    }
+}
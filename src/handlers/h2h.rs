@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Serialize, Serializer};
+
+use crate::{models, CHAR_NAMES};
+
+use super::common::TagResponse;
+use super::player_history::group_sets;
+
+fn serialize_i64_as_string<S: Serializer>(v: &i64, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&v.to_string())
+}
+
+#[derive(Serialize)]
+pub struct H2HResponse {
+    player_a: H2HPlayer,
+    player_b: H2HPlayer,
+    wins_a: i64,
+    wins_b: i64,
+    sets_a: i64,
+    sets_b: i64,
+    pairings: Vec<H2HPairing>,
+    meetings: Vec<H2HMeeting>,
+    recent_sets: Vec<H2HSet>,
+    tags: HashMap<String, Vec<TagResponse>>, //player_id to tags
+}
+
+#[derive(Serialize)]
+struct H2HPlayer {
+    #[serde(serialize_with = "serialize_i64_as_string")]
+    id: i64,
+    name: String,
+    longest_streak: i64,
+}
+
+/// Record for one (character of a, character of b) combination
+#[derive(Serialize)]
+struct H2HPairing {
+    char_a: &'static str,
+    char_b: &'static str,
+    wins_a: i64,
+    wins_b: i64,
+    is_legend_a: bool,
+    is_legend_b: bool,
+}
+
+#[derive(Serialize)]
+struct H2HMeeting {
+    timestamp: String,
+    char_a: &'static str,
+    char_b: &'static str,
+    rating_a: i64,
+    rating_b: i64,
+    winner_a: bool,
+}
+
+#[derive(Serialize)]
+struct H2HSet {
+    timestamp: String,
+    floor: String,
+    char_a: &'static str,
+    char_b: &'static str,
+    wins_a: i64,
+    wins_b: i64,
+    rating_a: i64,
+    rating_b: i64,
+}
+
+/// Side of `player_a` in a game: (won, char a, char b, rating a, rating b)
+fn from_a(player_a: i64, game: &models::Game) -> (bool, i16, i16, i64, i64) {
+    if game.id_a == player_a {
+        (game.winner == 1, game.char_a, game.char_b, game.value_a, game.value_b)
+    } else {
+        (game.winner == 2, game.char_b, game.char_a, game.value_b, game.value_a)
+    }
+}
+
+fn timestamp(game: &models::Game) -> String {
+    match game.real_timestamp {
+        Some(ts) => ts.to_string(),
+        None => game.timestamp.to_string(),
+    }
+}
+
+/// `games` are all the games between the two players, newest first.
+pub fn handle_get_h2h(
+    player_a: (i64, String),
+    player_b: (i64, String),
+    games: Vec<models::Game>,
+    player_tags: HashMap<i64, Vec<(String, String)>>,
+    legend_keys: HashSet<(i64, i64)>,
+    set_count: usize,
+) -> H2HResponse {
+    let (id_a, id_b) = (player_a.0, player_b.0);
+
+    let mut wins_a = 0;
+    let mut wins_b = 0;
+    let mut pairings: BTreeMap<(i16, i16), (i64, i64)> = BTreeMap::new();
+    let mut meetings = vec![];
+
+    for game in &games {
+        let (won, char_a, char_b, rating_a, rating_b) = from_a(id_a, game);
+        let pairing = pairings.entry((char_a, char_b)).or_default();
+        if won {
+            wins_a += 1;
+            pairing.0 += 1;
+        } else {
+            wins_b += 1;
+            pairing.1 += 1;
+        }
+
+        meetings.push(H2HMeeting {
+            timestamp: timestamp(game),
+            char_a: CHAR_NAMES[char_a as usize].0,
+            char_b: CHAR_NAMES[char_b as usize].0,
+            rating_a,
+            rating_b,
+            winner_a: won,
+        });
+    }
+
+    // Streaks read oldest to newest
+    let (mut longest_a, mut longest_b, mut current) = (0, 0, 0i64);
+    for game in games.iter().rev() {
+        let (won, ..) = from_a(id_a, game);
+        current = match (won, current) {
+            (true, c) if c > 0 => c + 1,
+            (true, _) => 1,
+            (false, c) if c < 0 => c - 1,
+            (false, _) => -1,
+        };
+        longest_a = longest_a.max(current);
+        longest_b = longest_b.max(-current);
+    }
+
+    let mut sets_a = 0;
+    let mut sets_b = 0;
+    let mut recent_sets = vec![];
+    for (i, set) in group_sets(id_a, &games).into_iter().enumerate() {
+        let set_wins_a = set.iter().filter(|g| from_a(id_a, g).0).count() as i64;
+        let set_wins_b = set.len() as i64 - set_wins_a;
+        match set_wins_a.cmp(&set_wins_b) {
+            std::cmp::Ordering::Greater => sets_a += 1,
+            std::cmp::Ordering::Less => sets_b += 1,
+            std::cmp::Ordering::Equal => {}
+        }
+
+        if i < set_count {
+            // Sets are newest first too, the rating going into the set is on its oldest game
+            let first = set.last().unwrap();
+            let (_, char_a, char_b, rating_a, rating_b) = from_a(id_a, first);
+            recent_sets.push(H2HSet {
+                timestamp: timestamp(first),
+                floor: first.game_floor.to_string(),
+                char_a: CHAR_NAMES[char_a as usize].0,
+                char_b: CHAR_NAMES[char_b as usize].0,
+                wins_a: set_wins_a,
+                wins_b: set_wins_b,
+                rating_a,
+                rating_b,
+            });
+        }
+    }
+
+    let tags = [id_a, id_b]
+        .iter()
+        .filter_map(|id| {
+            let tags: Vec<TagResponse> = player_tags
+                .get(id)?
+                .iter()
+                .filter(|(_, style)| !style.is_empty())
+                .map(|(tag, style)| TagResponse {
+                    tag: tag.clone(),
+                    style: style.clone(),
+                })
+                .collect();
+            (!tags.is_empty()).then(|| (id.to_string(), tags))
+        })
+        .collect();
+
+    H2HResponse {
+        player_a: H2HPlayer {
+            id: id_a,
+            name: player_a.1,
+            longest_streak: longest_a,
+        },
+        player_b: H2HPlayer {
+            id: id_b,
+            name: player_b.1,
+            longest_streak: longest_b,
+        },
+        wins_a,
+        wins_b,
+        sets_a,
+        sets_b,
+        pairings: pairings
+            .into_iter()
+            .map(|((char_a, char_b), (wins_a, wins_b))| H2HPairing {
+                char_a: CHAR_NAMES[char_a as usize].0,
+                char_b: CHAR_NAMES[char_b as usize].0,
+                wins_a,
+                wins_b,
+                is_legend_a: legend_keys.contains(&(id_a, char_a as i64)),
+                is_legend_b: legend_keys.contains(&(id_b, char_b as i64)),
+            })
+            .collect(),
+        meetings,
+        recent_sets,
+        tags,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(minutes: i64, a_is_first: bool, a_wins: bool, char_b: i16) -> models::Game {
+        let (id_a, id_b, char_a, char_b) = if a_is_first { (1, 2, 0, char_b) } else { (2, 1, char_b, 0) };
+        models::Game {
+            timestamp: chrono::DateTime::from_timestamp(minutes * 60, 0).unwrap().naive_utc(),
+            real_timestamp: None,
+            id_a,
+            name_a: "A".to_string(),
+            char_a,
+            platform_a: 1,
+            id_b,
+            name_b: "B".to_string(),
+            char_b,
+            platform_b: 1,
+            winner: if a_wins == a_is_first { 1 } else { 2 },
+            game_floor: 99,
+            value_a: 10001500,
+            value_b: 10001400,
+        }
+    }
+
+    #[test]
+    fn handle_get_h2h_record_and_sets() {
+        // Newest first: a set won 2-1 by A with both orderings of id_a/id_b, then an older set won 2-0 by B
+        let games = vec![
+            game(100, true, true, 1),
+            game(98, false, false, 1),
+            game(96, false, true, 1),
+            game(10, true, false, 2),
+            game(8, true, false, 2),
+        ];
+
+        let response = handle_get_h2h(
+            (1, "A".to_string()),
+            (2, "B".to_string()),
+            games,
+            HashMap::from([(2, vec![("VIP".to_string(), "color: gold".to_string())])]),
+            HashSet::from([(2, 2)]),
+            10,
+        );
+
+        assert_eq!((response.wins_a, response.wins_b), (2, 3));
+        assert_eq!((response.sets_a, response.sets_b), (1, 1));
+        assert_eq!(response.recent_sets.len(), 2);
+        assert_eq!((response.recent_sets[0].wins_a, response.recent_sets[0].wins_b), (2, 1));
+        assert_eq!(response.recent_sets[1].timestamp, "1970-01-01 00:08:00");
+        assert_eq!(response.player_a.longest_streak, 1);
+        assert_eq!(response.player_b.longest_streak, 2);
+        assert_eq!(response.pairings.len(), 2);
+        assert!(response.pairings.iter().any(|p| p.char_b == CHAR_NAMES[2].0 && p.is_legend_b));
+        assert!(response.tags.contains_key("2"));
+        assert_eq!(response.meetings[1].rating_a, 10001400);
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::{db, imdb, requests};
+
+/// Longest a component check waits for a pool connection, bb8 would otherwise wait 30s
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Ok,
+    Degraded,
+    Down,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Component {
+    pub status: ComponentStatus,
+    pub message: String,
+    /// Seconds since the timestamp the component is judged by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_seconds: Option<i64>,
+    /// Entries per key, for the leaderboards
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub sizes: BTreeMap<String, usize>,
+}
+
+impl Component {
+    fn new(status: ComponentStatus, message: impl Into<String>) -> Component {
+        Component {
+            status,
+            message: message.into(),
+            age_seconds: None,
+            sizes: BTreeMap::new(),
+        }
+    }
+
+    fn down(message: impl Into<String>) -> Component {
+        Component::new(ComponentStatus::Down, message)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthResponse {
+    /// The worst of the components
+    pub status: ComponentStatus,
+    pub api_version: String,
+    pub components: BTreeMap<&'static str, Component>,
+}
+
+impl HealthResponse {
+    /// 503 when anything is down, degraded still answers 200
+    pub fn status_code(&self) -> StatusCode {
+        match self.status {
+            ComponentStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::OK,
+        }
+    }
+}
+
+/// Ok up to `degraded_after` seconds old, degraded up to `down_after`, down after that
+pub fn age_status(age_seconds: i64, degraded_after: i64, down_after: i64) -> ComponentStatus {
+    if age_seconds > down_after {
+        ComponentStatus::Down
+    } else if age_seconds > degraded_after {
+        ComponentStatus::Degraded
+    } else {
+        ComponentStatus::Ok
+    }
+}
+
+fn age_component(
+    time: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+    degraded_after: i64,
+    down_after: i64,
+    missing: Component,
+) -> Component {
+    let Some(time) = time else {
+        return missing;
+    };
+
+    let age = (now - time).num_seconds();
+    Component {
+        age_seconds: Some(age),
+        ..Component::new(age_status(age, degraded_after, down_after), format!("Last at {}", time))
+    }
+}
+
+fn ggst_component(auth: Option<crate::ggst_api::AuthStatus>, now: NaiveDateTime) -> Component {
+    let Some(auth) = auth else {
+        return Component::new(ComponentStatus::Degraded, "The puller hasn't reported its login yet");
+    };
+
+    if !auth.connected {
+        return Component::down(format!(
+            "Not logged in ({} failed logins): {}, next attempt at {}",
+            auth.failed_logins,
+            auth.last_error.as_deref().unwrap_or("no error"),
+            auth.next_login.as_deref().unwrap_or("next pull"),
+        ));
+    }
+
+    let token_since = auth
+        .token_since
+        .and_then(|t| NaiveDateTime::parse_from_str(&t, "%Y-%m-%d %H:%M:%S").ok());
+    Component {
+        age_seconds: token_since.map(|t| (now - t).num_seconds()),
+        ..Component::new(ComponentStatus::Ok, "Logged in")
+    }
+}
+
+/// Everything but leaderboard_all can be missing for a while, e.g. after a new character is added
+fn leaderboard_component(sizes: Vec<(String, Option<usize>)>) -> Component {
+    let missing: Vec<&str> = sizes
+        .iter()
+        .filter(|(_, size)| size.is_none())
+        .map(|(key, _)| key.as_str())
+        .collect();
+
+    let status = if missing.contains(&"leaderboard_all") {
+        ComponentStatus::Down
+    } else if !missing.is_empty() {
+        ComponentStatus::Degraded
+    } else {
+        ComponentStatus::Ok
+    };
+
+    let message = if missing.is_empty() {
+        "All leaderboards present".to_string()
+    } else {
+        format!("Missing {}", missing.join(", "))
+    };
+
+    Component {
+        sizes: sizes
+            .into_iter()
+            .filter_map(|(key, size)| Some((key, size?)))
+            .collect(),
+        ..Component::new(status, message)
+    }
+}
+
+pub async fn handle_get_health(state: &crate::AppState) -> HealthResponse {
+    let now = chrono::Utc::now().naive_utc();
+    let mut components = BTreeMap::new();
+
+    let postgres = match tokio::time::timeout(CHECK_TIMEOUT, state.db_pool.get()).await {
+        Ok(Ok(mut db)) => match db::ping(&mut db).await {
+            Ok(()) => Component::new(ComponentStatus::Ok, "Reachable"),
+            Err(e) => Component::down(e.to_string()),
+        },
+        Ok(Err(e)) => Component::down(crate::error::Error::from(e).to_string()),
+        Err(_) => Component::down("Timed out waiting for a connection"),
+    };
+    components.insert("postgres", postgres);
+
+    let mut redis = match tokio::time::timeout(CHECK_TIMEOUT, state.redis_pool.get()).await {
+        Ok(Ok(redis)) => redis,
+        Ok(Err(e)) => {
+            return unreachable_redis(components, crate::error::Error::from(e).to_string());
+        }
+        Err(_) => {
+            return unreachable_redis(components, "Timed out waiting for a connection".to_string());
+        }
+    };
+
+    if let Err(e) = imdb::ping(&mut redis).await {
+        return unreachable_redis(components, e.to_string());
+    }
+    components.insert("redis", Component::new(ComponentStatus::Ok, "Reachable"));
+
+    let auth = imdb::get_auth_status(&mut redis).await.unwrap_or(None);
+    components.insert("ggst", ggst_component(auth, now));
+
+    let last_update_daily = imdb::get_update_time("last_update_daily", &mut redis).await.unwrap_or(None);
+
+    // The daily update clears latest_game_time, so missing is expected for a few minutes after it
+    let daily_ran_recently = last_update_daily.is_some_and(|t| (now - t).num_seconds() < 600);
+    let replays_missing = if daily_ran_recently {
+        Component::new(ComponentStatus::Degraded, "Daily update running, no replays since")
+    } else {
+        Component::down("latest_game_time does not exist")
+    };
+    let latest_game_time = imdb::get_update_time("latest_game_time", &mut redis).await.unwrap_or(None);
+    components.insert(
+        "replays",
+        age_component(latest_game_time, now, 120, 600, replays_missing),
+    );
+
+    let last_update_hourly = imdb::get_update_time("last_update_hourly", &mut redis).await.unwrap_or(None);
+    components.insert(
+        "hourly_update",
+        age_component(last_update_hourly, now, 90 * 60, 3 * 60 * 60, Component::down("Never ran")),
+    );
+    components.insert(
+        "daily_update",
+        age_component(last_update_daily, now, 25 * 60 * 60, 48 * 60 * 60, Component::down("Never ran")),
+    );
+
+    let leaderboards = match imdb::get_leaderboard_sizes(&mut redis).await {
+        Ok(sizes) => leaderboard_component(sizes),
+        Err(e) => Component::down(e.to_string()),
+    };
+    components.insert("leaderboards", leaderboards);
+
+    // What the puller found working, this process may still have the one it started with
+    let api_version = imdb::get_api_version(&mut redis)
+        .await
+        .unwrap_or_else(|_| requests::api_version());
+
+    response(components, api_version)
+}
+
+/// Everything but postgres lives in redis, so it's all down with it
+fn unreachable_redis(mut components: BTreeMap<&'static str, Component>, error: String) -> HealthResponse {
+    components.insert("redis", Component::down(error));
+    for name in ["ggst", "replays", "hourly_update", "daily_update", "leaderboards"] {
+        components.insert(name, Component::down("Redis unreachable"));
+    }
+
+    response(components, requests::api_version())
+}
+
+fn response(components: BTreeMap<&'static str, Component>, api_version: String) -> HealthResponse {
+    HealthResponse {
+        status: components
+            .values()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(ComponentStatus::Ok),
+        api_version,
+        components,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn age_status_thresholds() {
+        assert_eq!(age_status(120, 120, 600), ComponentStatus::Ok);
+        assert_eq!(age_status(121, 120, 600), ComponentStatus::Degraded);
+        assert_eq!(age_status(601, 120, 600), ComponentStatus::Down);
+    }
+
+    #[test]
+    fn leaderboards_missing_char_is_degraded() {
+        let component = leaderboard_component(vec![
+            ("leaderboard_all".to_string(), Some(1000)),
+            ("leaderboard_char_0".to_string(), None),
+        ]);
+        assert_eq!(component.status, ComponentStatus::Degraded);
+        assert_eq!(component.message, "Missing leaderboard_char_0");
+        assert_eq!(component.sizes, BTreeMap::from([("leaderboard_all".to_string(), 1000)]));
+
+        let component = leaderboard_component(vec![("leaderboard_all".to_string(), None)]);
+        assert_eq!(component.status, ComponentStatus::Down);
+    }
+
+    #[test]
+    fn overall_status_is_the_worst() {
+        let components = BTreeMap::from([
+            ("postgres", Component::new(ComponentStatus::Ok, "Reachable")),
+            ("replays", Component::new(ComponentStatus::Degraded, "Late")),
+        ]);
+        let health = response(components, "0.0.0".to_string());
+        assert_eq!(health.status, ComponentStatus::Degraded);
+        assert_eq!(health.status_code(), StatusCode::OK);
+
+        let health = unreachable_redis(BTreeMap::new(), "refused".to_string());
+        assert_eq!(health.status, ComponentStatus::Down);
+        assert_eq!(health.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
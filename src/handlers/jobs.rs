@@ -0,0 +1,99 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::error::Error;
+use crate::models::JobRun;
+use crate::scheduler::{self, JOBS};
+use crate::{db, imdb};
+
+#[derive(Serialize)]
+pub struct JobResponse {
+    pub name: &'static str,
+    pub interval_seconds: i64,
+    /// Some pull instance holds the lock
+    pub running: bool,
+    /// Triggered and not picked up by the scheduler yet
+    pub triggered: bool,
+    pub next_run: String,
+    pub last_success: Option<String>,
+    pub last_run: Option<JobRunResponse>,
+}
+
+#[derive(Serialize)]
+pub struct JobRunResponse {
+    pub started: String,
+    pub finished: Option<String>,
+    pub success: Option<bool>,
+    pub error: Option<String>,
+}
+
+impl From<&JobRun> for JobRunResponse {
+    fn from(run: &JobRun) -> JobRunResponse {
+        JobRunResponse {
+            started: run.started.to_string(),
+            finished: run.finished.map(|t| t.to_string()),
+            success: run.success,
+            error: run.error.clone(),
+        }
+    }
+}
+
+/// The admin endpoints are off unless ADMIN_KEY is set, and a wrong key looks the same
+pub fn check_admin_key(key: &str) -> Result<(), Error> {
+    match std::env::var("ADMIN_KEY") {
+        Ok(admin_key) if !admin_key.is_empty() && keys_match(&admin_key, key) => Ok(()),
+        _ => Err(Error::NotFound("Not found".to_string())),
+    }
+}
+
+/// Compares hashes in constant time, so neither the timing nor the length gives the key away
+fn keys_match(admin_key: &str, key: &str) -> bool {
+    Sha256::digest(admin_key.as_bytes()).ct_eq(&Sha256::digest(key.as_bytes())).into()
+}
+
+pub async fn handle_get_jobs(
+    db: &mut crate::Connection<'_>,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Vec<JobResponse>, Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let attempts = db::get_latest_job_runs(false, db).await?;
+    let successes = db::get_latest_job_runs(true, db).await?;
+
+    let mut jobs = Vec::new();
+    for job in JOBS {
+        let last_run = attempts.iter().find(|r| r.job == job.name);
+        let last_success = successes.iter().find(|r| r.job == job.name).map(|r| r.started);
+
+        jobs.push(JobResponse {
+            name: job.name,
+            interval_seconds: job.interval_seconds,
+            running: imdb::is_job_locked(job.name, redis).await?,
+            triggered: imdb::is_job_triggered(job.name, redis).await?,
+            next_run: scheduler::next_run(job, last_success, last_run.map(|r| r.started), now).to_string(),
+            last_success: last_success.map(|t| t.to_string()),
+            last_run: last_run.map(JobRunResponse::from),
+        });
+    }
+
+    Ok(jobs)
+}
+
+/// The puller runs it on its next tick, within a minute
+pub async fn handle_trigger_job(name: &str, redis: &mut crate::RedisConnection<'_>) -> Result<(), Error> {
+    let job = scheduler::find_job(name).ok_or_else(|| Error::NotFound(format!("Unknown job {}", name)))?;
+    imdb::trigger_job(job.name, redis).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_match_only_the_same_key() {
+        assert!(keys_match("secret", "secret"));
+        assert!(!keys_match("secret", "secreT"));
+        assert!(!keys_match("secret", "secret2"));
+        assert!(!keys_match("secret", ""));
+    }
+}
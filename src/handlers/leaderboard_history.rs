@@ -0,0 +1,172 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Serialize, Serializer};
+
+use crate::models::LeaderboardSnapshot;
+use crate::CHAR_NAMES;
+
+use super::common::PRIVATE_PLAYER_NAME;
+
+fn serialize_i64_as_string<S: Serializer>(v: &i64, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&v.to_string())
+}
+
+#[derive(Serialize)]
+pub struct RankHistoryResponse {
+    pub history: Vec<RankHistoryEntry>,
+}
+
+#[derive(Serialize)]
+pub struct RankHistoryEntry {
+    pub date: String,
+    pub rank: i32,
+    pub rating: i64,
+}
+
+#[derive(Serialize)]
+pub struct MoversResponse {
+    pub date: Option<String>,
+    pub previous_date: Option<String>,
+    pub climbers: Vec<MoverResponse>,
+    pub fallers: Vec<MoverResponse>,
+}
+
+#[derive(Serialize)]
+pub struct MoverResponse {
+    #[serde(serialize_with = "serialize_i64_as_string")]
+    pub id: i64,
+    pub name: String,
+    pub char_short: String,
+    pub char_long: String,
+    pub rating: i64,
+    pub rank: i32,
+    pub previous_rank: i32,
+    pub rank_delta: i64,
+}
+
+pub fn handle_get_rank_history(history: Vec<LeaderboardSnapshot>) -> RankHistoryResponse {
+    RankHistoryResponse {
+        history: history
+            .into_iter()
+            .map(|s| RankHistoryEntry {
+                date: s.snapshot_date.to_string(),
+                rank: s.rank,
+                rating: s.rating,
+            })
+            .collect(),
+    }
+}
+
+/// Positive when the player climbed since the previous snapshot.
+pub fn rank_delta(previous_rank: i64, rank: i64) -> i64 {
+    previous_rank - rank
+}
+
+/// Biggest climbers and fallers between two snapshots of the same leaderboard.
+/// Players that are new on the board or dropped off it have no delta and are left out.
+pub fn handle_get_movers(
+    current: Vec<LeaderboardSnapshot>,
+    previous: Vec<LeaderboardSnapshot>,
+    private_players: HashSet<i64>,
+    count: usize,
+) -> MoversResponse {
+    let date = current.first().map(|s| s.snapshot_date.to_string());
+    let previous_date = previous.first().map(|s| s.snapshot_date.to_string());
+
+    let previous_ranks: HashMap<(i64, i16), i32> = previous
+        .iter()
+        .map(|s| ((s.player_id, s.char_id), s.rank))
+        .collect();
+
+    let mut movers: Vec<MoverResponse> = current
+        .into_iter()
+        .filter_map(|s| {
+            let previous_rank = *previous_ranks.get(&(s.player_id, s.char_id))?;
+            let delta = rank_delta(previous_rank as i64, s.rank as i64);
+            if delta == 0 {
+                return None;
+            }
+
+            let (id, name) = if private_players.contains(&s.player_id) {
+                (0, PRIVATE_PLAYER_NAME.to_string())
+            } else {
+                (s.player_id, s.name)
+            };
+
+            Some(MoverResponse {
+                id,
+                name,
+                char_short: CHAR_NAMES[s.char_id as usize].0.to_string(),
+                char_long: CHAR_NAMES[s.char_id as usize].1.to_string(),
+                rating: s.rating,
+                rank: s.rank,
+                previous_rank,
+                rank_delta: delta,
+            })
+        })
+        .collect();
+
+    // Ties go to the higher ranked player
+    movers.sort_by_key(|m| (std::cmp::Reverse(m.rank_delta), m.rank));
+    let fallers_start = movers.iter().position(|m| m.rank_delta < 0).unwrap_or(movers.len());
+    let mut fallers = movers.split_off(fallers_start);
+    fallers.sort_by_key(|m| (m.rank_delta, m.rank));
+
+    movers.truncate(count);
+    fallers.truncate(count);
+
+    MoversResponse {
+        date,
+        previous_date,
+        climbers: movers,
+        fallers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn snapshot(day: u32, player_id: i64, rank: i32) -> LeaderboardSnapshot {
+        LeaderboardSnapshot {
+            snapshot_date: NaiveDate::from_ymd_opt(2026, 10, day).unwrap(),
+            board: "all".to_string(),
+            player_id,
+            char_id: 0,
+            rank,
+            name: format!("Player {}", player_id),
+            rating: 10001500,
+        }
+    }
+
+    #[test]
+    fn handle_get_movers_sorts_and_skips_new_entries() {
+        let previous = vec![snapshot(17, 1, 1), snapshot(17, 2, 2), snapshot(17, 3, 3), snapshot(17, 4, 4)];
+        let current = vec![snapshot(18, 4, 1), snapshot(18, 1, 2), snapshot(18, 5, 3), snapshot(18, 3, 4), snapshot(18, 2, 5)];
+
+        let response = handle_get_movers(current, previous, HashSet::new(), 10);
+
+        assert_eq!(response.date, Some("2026-10-18".to_string()));
+        assert_eq!(response.previous_date, Some("2026-10-17".to_string()));
+
+        let climbers: Vec<(i64, i64)> = response.climbers.iter().map(|m| (m.id, m.rank_delta)).collect();
+        assert_eq!(climbers, vec![(4, 3)]);
+
+        let fallers: Vec<(i64, i64)> = response.fallers.iter().map(|m| (m.id, m.rank_delta)).collect();
+        assert_eq!(fallers, vec![(2, -3), (1, -1), (3, -1)]);
+    }
+
+    #[test]
+    fn handle_get_movers_hides_private_players() {
+        let previous = vec![snapshot(17, 1, 1), snapshot(17, 2, 2)];
+        let current = vec![snapshot(18, 2, 1), snapshot(18, 1, 2)];
+
+        let response = handle_get_movers(current, previous, HashSet::from([2]), 1);
+
+        assert_eq!(response.climbers.len(), 1);
+        assert_eq!(response.climbers[0].id, 0);
+        assert_eq!(response.climbers[0].name, PRIVATE_PLAYER_NAME);
+        assert_eq!(response.fallers[0].id, 1);
+    }
+}
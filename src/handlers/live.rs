@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use bb8_redis::redis;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::error;
+
+use crate::error::Error;
+use crate::models::Game;
+use crate::CHAR_NAMES;
+
+use super::common::PRIVATE_PLAYER_NAME;
+
+/// Redis pub/sub channel the pull process publishes new games to
+pub const LIVE_CHANNEL: &str = "live_games";
+/// Games a client can fall behind by before it skips ahead
+const LIVE_BUFFER: usize = 256;
+/// Wait before subscribing again after Redis dropped the subscription
+const RESUBSCRIBE_SECONDS: u64 = 5;
+
+/// The web server's one subscription to LIVE_CHANNEL, every /api/live client reads from it
+#[derive(Clone)]
+pub struct LiveFeed {
+    redis_url: String,
+    sender: broadcast::Sender<LiveGame>,
+}
+
+impl LiveFeed {
+    pub fn new(redis_url: String) -> LiveFeed {
+        let (sender, _) = broadcast::channel(LIVE_BUFFER);
+        LiveFeed { redis_url, sender }
+    }
+
+    /// Games published from now on. A client that falls LIVE_BUFFER games behind misses those.
+    pub fn games(&self) -> impl Stream<Item = LiveGame> + use<> {
+        futures_util::stream::unfold(self.sender.subscribe(), |mut games| async move {
+            loop {
+                match games.recv().await {
+                    Ok(game) => return Some((game, games)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Forwards LIVE_CHANNEL to the clients, subscribing again whenever Redis drops the subscription
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.forward().await {
+                error!("Live feed: {e}");
+            }
+            tokio::time::sleep(Duration::from_secs(RESUBSCRIBE_SECONDS)).await;
+        }
+    }
+
+    async fn forward(&self) -> Result<(), Error> {
+        // Pooled connections can't subscribe
+        let client = redis::Client::open(self.redis_url.as_str())?;
+        let mut pubsub = client.get_async_pubsub().await?;
+        pubsub.subscribe(LIVE_CHANNEL).await?;
+
+        let mut messages = pubsub.into_on_message();
+        while let Some(msg) = messages.next().await {
+            let game = msg
+                .get_payload::<String>()
+                .ok()
+                .and_then(|payload| serde_json::from_str::<LiveGame>(&payload).ok());
+
+            // Fails when no client is listening, there's nobody to miss the game
+            if let Some(game) = game {
+                let _ = self.sender.send(game);
+            }
+        }
+
+        Err(Error::UpstreamUnavailable("Redis ended the live subscription".to_string()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LiveGame {
+    pub timestamp: String,
+    pub floor: i16,
+    pub winner: i16,
+    pub player_a: LivePlayer,
+    pub player_b: LivePlayer,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LivePlayer {
+    // Strings like everywhere else in the api, player ids don't fit in a JS number
+    pub id: String,
+    pub name: String,
+    pub char_short: String,
+    pub rating: i64,
+}
+
+impl LivePlayer {
+    fn new(id: i64, name: &str, char_id: i16, rating: i64, private: bool) -> LivePlayer {
+        let (id, name) = if private {
+            (0, PRIVATE_PLAYER_NAME.to_string())
+        } else {
+            (id, name.to_string())
+        };
+
+        LivePlayer {
+            id: id.to_string(),
+            name,
+            char_short: CHAR_NAMES[char_id as usize].0.to_string(),
+            rating,
+        }
+    }
+}
+
+impl LiveGame {
+    pub fn from_game(game: &Game, private_players: &HashSet<i64>) -> LiveGame {
+        LiveGame {
+            timestamp: game.timestamp.to_string(),
+            floor: game.game_floor,
+            winner: game.winner,
+            player_a: LivePlayer::new(
+                game.id_a,
+                &game.name_a,
+                game.char_a,
+                game.value_a,
+                private_players.contains(&game.id_a),
+            ),
+            player_b: LivePlayer::new(
+                game.id_b,
+                &game.name_b,
+                game.char_b,
+                game.value_b,
+                private_players.contains(&game.id_b),
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LiveParams {
+    pub player_id: Option<i64>,
+    pub char_short: Option<String>,
+    pub min_rating: Option<i64>,
+}
+
+/// All filters have to match. With both a player and a character, it's that player on that character.
+pub struct LiveFilter {
+    player_id: Option<String>,
+    char_short: Option<String>,
+    min_rating: Option<i64>,
+}
+
+impl LiveFilter {
+    pub fn from_params(params: LiveParams) -> Result<LiveFilter, Error> {
+        if let Some(char_short) = &params.char_short
+            && !CHAR_NAMES.iter().any(|(c, _)| c == char_short)
+        {
+            return Err(Error::BadInput("Character not found".to_string()));
+        }
+
+        Ok(LiveFilter {
+            player_id: params.player_id.map(|id| id.to_string()),
+            char_short: params.char_short,
+            min_rating: params.min_rating,
+        })
+    }
+
+    fn matches_player(&self, player: &LivePlayer) -> bool {
+        self.player_id.as_ref().is_none_or(|id| *id == player.id)
+            && self.char_short.as_ref().is_none_or(|c| *c == player.char_short)
+    }
+
+    pub fn matches(&self, game: &LiveGame) -> bool {
+        let side = self.matches_player(&game.player_a) || self.matches_player(&game.player_b);
+        let rating = self
+            .min_rating
+            .is_none_or(|min| game.player_a.rating >= min || game.player_b.rating >= min);
+
+        side && rating
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn game() -> LiveGame {
+        LiveGame::from_game(
+            &Game {
+                timestamp: NaiveDateTime::parse_from_str("2026-10-18 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+                id_a: 1,
+                name_a: "Alice".to_string(),
+                char_a: 0,
+                platform_a: 1,
+                id_b: 2,
+                name_b: "Bob".to_string(),
+                char_b: 1,
+                platform_b: 1,
+                winner: 1,
+                game_floor: 99,
+                value_a: 10001500,
+                value_b: 30000,
+                real_timestamp: None,
+            },
+            &HashSet::from([2]),
+        )
+    }
+
+    fn filter(player_id: Option<i64>, char_short: Option<&str>, min_rating: Option<i64>) -> LiveFilter {
+        LiveFilter::from_params(LiveParams {
+            player_id,
+            char_short: char_short.map(|c| c.to_string()),
+            min_rating,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn from_game_hides_private_players() {
+        let game = game();
+
+        assert_eq!(game.player_a.id, "1");
+        assert_eq!(game.player_b.id, "0");
+        assert_eq!(game.player_b.name, PRIVATE_PLAYER_NAME);
+    }
+
+    #[test]
+    fn live_filter_matches() {
+        let game = game();
+        let sol = CHAR_NAMES[0].0;
+        let ky = CHAR_NAMES[1].0;
+
+        assert!(filter(None, None, None).matches(&game));
+        assert!(filter(Some(1), None, None).matches(&game));
+        assert!(!filter(Some(3), None, None).matches(&game));
+        assert!(filter(None, Some(ky), None).matches(&game));
+        assert!(filter(Some(1), Some(sol), None).matches(&game));
+        assert!(!filter(Some(1), Some(ky), None).matches(&game));
+        assert!(filter(None, None, Some(10001000)).matches(&game));
+        assert!(!filter(None, None, Some(10002000)).matches(&game));
+        // A private player can't be followed by id
+        assert!(!filter(Some(2), None, None).matches(&game));
+    }
+
+    #[tokio::test]
+    async fn live_feed_sends_every_client_each_game() {
+        let feed = LiveFeed::new(String::new());
+        let mut first = std::pin::pin!(feed.games());
+        let mut second = std::pin::pin!(feed.games());
+
+        feed.sender.send(game()).unwrap();
+
+        assert_eq!(first.next().await, Some(game()));
+        assert_eq!(second.next().await, Some(game()));
+    }
+
+    #[test]
+    fn live_filter_unknown_character() {
+        assert!(LiveFilter::from_params(LiveParams {
+            player_id: None,
+            char_short: Some("??".to_string()),
+            min_rating: None,
+        })
+        .is_err());
+    }
+}
@@ -4,4 +4,16 @@ pub mod player_history;
 pub mod top;
 pub mod search;
 pub mod avatar;
-pub mod rating_sync;
\ No newline at end of file
+pub mod rating_sync;
+pub mod claim;
+pub mod embed;
+pub mod rating_chart;
+pub mod leaderboard_history;
+pub mod live;
+pub mod webhook;
+pub mod h2h;
+pub mod health;
+pub mod jobs;
+pub mod sessions;
+pub mod export;
+pub mod performance;
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{rating, CHAR_NAMES};
+
+/// Days of ranked games the performance rating is computed over
+pub const PERFORMANCE_WINDOW_DAYS: i64 = 30;
+/// Fewest games in the window to make the over-performer leaderboard
+pub const MIN_LEADERBOARD_GAMES: i64 = 50;
+/// Entries kept on the over-performer leaderboard
+pub const LEADERBOARD_SIZE: usize = 100;
+
+fn round_to(v: f64, places: i32) -> f64 {
+    let scale = 10f64.powi(places);
+    (v * scale).round() / scale
+}
+
+/// Results against the rating each opponent had at game time. A win counts `1 - expected`, a loss
+/// `-expected`, so beating strong opponents is worth more and losing to weak ones costs more.
+#[derive(Default, Clone, Debug)]
+pub struct Performance {
+    pub games: i64,
+    pub wins: i64,
+    expected_wins: f64,
+    opponent_strength: f64,
+}
+
+impl Performance {
+    /// Hidden (0) ratings tell nothing about the game, they're skipped
+    pub fn add(&mut self, own_rating: i64, opponent_rating: i64, won: bool) {
+        if own_rating <= 0 || opponent_rating <= 0 {
+            return;
+        }
+
+        self.games += 1;
+        self.wins += won as i64;
+        self.expected_wins += rating::win_probability(own_rating as f64, opponent_rating as f64);
+        self.opponent_strength += rating::strength(opponent_rating as f64);
+    }
+
+    /// Wins above expected per game, the sum of the residuals over the games
+    pub fn residual_per_game(&self) -> f64 {
+        (self.wins as f64 - self.expected_wins) / self.games as f64
+    }
+
+    pub fn response(&self) -> Option<PerformanceResponse> {
+        (self.games > 0).then(|| PerformanceResponse {
+            games: self.games,
+            wins: self.wins,
+            losses: self.games - self.wins,
+            expected_wins: round_to(self.expected_wins, 2),
+            residual_per_game: round_to(self.residual_per_game(), 3),
+            strength_of_schedule: round_to(self.opponent_strength / self.games as f64, 2),
+            window_days: PERFORMANCE_WINDOW_DAYS,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PerformanceResponse {
+    pub games: i64,
+    pub wins: i64,
+    pub losses: i64,
+    /// Wins the ratings predicted
+    pub expected_wins: f64,
+    /// (wins - expected_wins) / games, above 0 is beating the odds
+    pub residual_per_game: f64,
+    /// Average opponent on the Elo-like scale of `rating::strength`
+    pub strength_of_schedule: f64,
+    pub window_days: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TopPerformer {
+    pub rank: usize,
+    pub id: String,
+    pub name: String,
+    pub char_short: String,
+    pub char_long: String,
+    pub performance: PerformanceResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TopPerformersResponse {
+    pub last_update: Option<String>,
+    pub performers: Vec<TopPerformer>,
+}
+
+/// The `LEADERBOARD_SIZE` best (id, char_id, performance) by residual per game among those with
+/// `MIN_LEADERBOARD_GAMES`, `name` gives the shown name and None leaves a player out (private).
+pub fn top_performers(
+    performances: impl IntoIterator<Item = ((i64, i16), Performance)>,
+    name: impl Fn(i64) -> Option<String>,
+) -> Vec<TopPerformer> {
+    let mut candidates: Vec<((i64, i16), Performance)> = performances
+        .into_iter()
+        .filter(|(_, p)| p.games >= MIN_LEADERBOARD_GAMES)
+        .collect();
+    candidates.sort_by(|a, b| b.1.residual_per_game().total_cmp(&a.1.residual_per_game()));
+
+    candidates
+        .into_iter()
+        .filter_map(|((id, char_id), performance)| {
+            Some(TopPerformer {
+                rank: 0,
+                id: id.to_string(),
+                name: name(id)?,
+                char_short: CHAR_NAMES[char_id as usize].0.to_string(),
+                char_long: CHAR_NAMES[char_id as usize].1.to_string(),
+                performance: performance.response()?,
+            })
+        })
+        .take(LEADERBOARD_SIZE)
+        .enumerate()
+        .map(|(i, performer)| TopPerformer { rank: i + 1, ..performer })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upsets_count_more_than_expected_wins() {
+        let mut upset = Performance::default();
+        upset.add(1500 + 10_000_000, 1900 + 10_000_000, true);
+        let mut expected = Performance::default();
+        expected.add(1900 + 10_000_000, 1500 + 10_000_000, true);
+
+        assert!(upset.residual_per_game() > 0.9);
+        assert!(expected.residual_per_game() < 0.1);
+        assert!(upset.response().unwrap().strength_of_schedule > expected.response().unwrap().strength_of_schedule);
+
+        // Hidden ratings are left out
+        upset.add(0, 1500, false);
+        assert_eq!(upset.games, 1);
+    }
+
+    #[test]
+    fn top_performers_need_enough_games_and_skip_private_players() {
+        let performance = |games: i64, wins: i64| {
+            let mut p = Performance::default();
+            for i in 0..games {
+                p.add(1500 + 10_000_000, 1500 + 10_000_000, i < wins);
+            }
+            p
+        };
+
+        let top = top_performers(
+            vec![
+                ((1, 0), performance(MIN_LEADERBOARD_GAMES, MIN_LEADERBOARD_GAMES / 2)),
+                ((2, 0), performance(MIN_LEADERBOARD_GAMES, MIN_LEADERBOARD_GAMES)),
+                ((3, 1), performance(MIN_LEADERBOARD_GAMES - 1, MIN_LEADERBOARD_GAMES - 1)),
+                ((4, 0), performance(MIN_LEADERBOARD_GAMES, MIN_LEADERBOARD_GAMES)),
+            ],
+            |id| (id != 4).then(|| format!("Player {id}")),
+        );
+
+        assert_eq!(
+            top.iter().map(|p| (p.rank, p.id.as_str())).collect::<Vec<_>>(),
+            vec![(1, "2"), (2, "1")]
+        );
+        assert_eq!(top[0].performance.residual_per_game, 0.5);
+    }
+}
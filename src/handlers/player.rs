@@ -6,9 +6,10 @@ fn serialize_i64_as_string<S: Serializer>(v: &i64, s: S) -> Result<S::Ok, S::Err
     s.serialize_str(&v.to_string())
 }
 
-use crate::{models::{Player, PlayerRating}, CHAR_NAMES};
+use crate::{error::Error, models::{Player, PlayerRating}, CHAR_NAMES};
 
-use super::common::TagResponse;
+use super::common::{platform_name, TagResponse, PRIVATE_PLAYER_NAME};
+use super::performance::{Performance, PerformanceResponse};
 
 #[derive(Serialize)]
 pub struct PlayerResponse {
@@ -17,6 +18,7 @@ pub struct PlayerResponse {
     name: String,
     ratings: Vec<PlayerResponsePlayer>,
     platform: String,
+    status: String,
     top_global: i32,
     tags: Vec<TagResponse>,
 }
@@ -30,6 +32,8 @@ struct PlayerResponsePlayer {
     top_char: i32,
     top_defeated: TopDefeated,
     top_rating: TopRating,
+    /// None without ranked games in the performance window
+    performance: Option<PerformanceResponse>,
     is_legend: bool,
 }
 
@@ -49,16 +53,44 @@ pub struct TopRating {
     pub value: i64,
 }
 
+/// What the player did on each character, by char_id
+pub struct CharacterStats {
+    pub match_counts: HashMap<i16, i32>,
+    /// Rank on the character's leaderboard
+    pub top_chars: HashMap<i16, i32>,
+    pub top_defeated: HashMap<i16, TopDefeated>,
+    pub top_rating: HashMap<i16, TopRating>,
+    pub performance: HashMap<i16, Performance>,
+}
+
 pub async fn handle_get_player(
     player_char: Vec<(Player, PlayerRating)>,
-    match_counts: HashMap<i16, i32>,
-    top_chars: HashMap<i16, i32>,
-    top_defeated: HashMap<i16, TopDefeated>,
-    top_rating: HashMap<i16, TopRating>,
+    stats: CharacterStats,
     top_global: i32,
     tags: Vec<(String, String)>,
     legend_keys: std::collections::HashSet<(i64, i64)>,
-) -> Result<PlayerResponse, String> {
+    private_players: std::collections::HashSet<i64>,
+) -> Result<PlayerResponse, Error> {
+    if player_char[0].0.private {
+        return Ok(PlayerResponse {
+            id: player_char[0].0.id,
+            name: PRIVATE_PLAYER_NAME.to_string(),
+            ratings: vec![],
+            platform: "???".to_string(),
+            status: "Private".to_string(),
+            top_global: 0,
+            tags: vec![],
+        });
+    }
+
+    let CharacterStats {
+        match_counts,
+        top_chars,
+        top_defeated,
+        top_rating,
+        performance,
+    } = stats;
+
     let ratings: Vec<PlayerResponsePlayer> = player_char
         .iter()
         .map(|p| PlayerResponsePlayer {
@@ -67,16 +99,21 @@ pub async fn handle_get_player(
             character: CHAR_NAMES[p.1.char_id as usize].1.to_string(),
             match_count: match_counts.get(&p.1.char_id).unwrap().clone(),
             top_char: top_chars.get(&p.1.char_id).unwrap().clone(),
-            top_defeated: top_defeated
-                .get(&p.1.char_id)
-                .unwrap_or(&TopDefeated {
+            top_defeated: match top_defeated.get(&p.1.char_id) {
+                Some(td) if private_players.contains(&td.id) => TopDefeated {
+                    id: 0,
+                    name: PRIVATE_PLAYER_NAME.to_string(),
+                    ..td.clone()
+                },
+                Some(td) => td.clone(),
+                None => TopDefeated {
                     timestamp: "N/A".to_string(),
                     id: 0,
                     name: "N/A".to_string(),
                     char_short: "N/A".to_string(),
                     value: 0,
-                })
-                .clone(),
+                },
+            },
             top_rating: top_rating
                 .get(&p.1.char_id)
                 .unwrap_or(&TopRating {
@@ -84,6 +121,7 @@ pub async fn handle_get_player(
                     value: 0,
                 })
                 .clone(),
+            performance: performance.get(&p.1.char_id).and_then(Performance::response),
             is_legend: legend_keys.contains(&(p.0.id, p.1.char_id as i64)),
         })
         .collect();
@@ -92,12 +130,8 @@ pub async fn handle_get_player(
         id: player_char[0].0.id,
         name: player_char[0].0.name.clone(),
         ratings,
-        platform: match player_char[0].0.platform {
-            1 => "PS".to_string(),
-            2 => "XB".to_string(),
-            3 => "PC".to_string(),
-            _ => "???".to_string(),
-        },
+        platform: platform_name(player_char[0].0.platform).to_string(),
+        status: "Public".to_string(),
         top_global,
         tags: tags
             .iter()
@@ -124,13 +158,17 @@ use super::*;
 
         let response = handle_get_player(
             player_char,
-            match_counts,
-            top_chars,
-            top_defeated,
-            top_rating,
+            CharacterStats {
+                match_counts,
+                top_chars,
+                top_defeated,
+                top_rating,
+                performance: HashMap::new(),
+            },
             top_global,
             tags,
             HashSet::new(),
+            HashSet::new(),
         )
         .await
         .unwrap();
@@ -148,13 +186,17 @@ use super::*;
 
         let response = handle_get_player(
             player_char,
-            match_counts,
-            top_chars,
-            top_defeated,
-            top_rating,
+            CharacterStats {
+                match_counts,
+                top_chars,
+                top_defeated,
+                top_rating,
+                performance: HashMap::new(),
+            },
             top_global,
             tags,
             HashSet::new(),
+            HashSet::new(),
         )
         .await
         .unwrap();
@@ -172,13 +214,17 @@ use super::*;
 
         let response = handle_get_player(
             player_char,
-            match_counts,
-            top_chars,
-            top_defeated,
-            top_rating,
+            CharacterStats {
+                match_counts,
+                top_chars,
+                top_defeated,
+                top_rating,
+                performance: HashMap::new(),
+            },
             top_global,
             tags,
             HashSet::new(),
+            HashSet::new(),
         )
         .await
         .unwrap();
@@ -195,13 +241,17 @@ use super::*;
 
         let response = handle_get_player(
             player_char,
-            match_counts,
-            top_chars,
-            top_defeated,
-            top_rating,
+            CharacterStats {
+                match_counts,
+                top_chars,
+                top_defeated,
+                top_rating,
+                performance: HashMap::new(),
+            },
             top_global,
             tags,
             HashSet::new(),
+            HashSet::new(),
         )
         .await
         .unwrap();
@@ -218,13 +268,17 @@ use super::*;
 
         let response = handle_get_player(
             player_char,
-            match_counts,
-            top_chars,
-            top_defeated,
-            top_rating,
+            CharacterStats {
+                match_counts,
+                top_chars,
+                top_defeated,
+                top_rating,
+                performance: HashMap::new(),
+            },
             top_global,
             tags,
             HashSet::new(),
+            HashSet::new(),
         )
         .await
         .unwrap();
@@ -232,6 +286,123 @@ use super::*;
         assert_eq!(response.platform, "PC");
     }
 
+    #[tokio::test]
+    async fn get_player_private() {
+        let (mut player_char, match_counts, top_chars, top_defeated, top_rating, top_global, tags) =
+            get_test_player_data();
+
+        player_char[0].0.private = true;
+
+        let response = handle_get_player(
+            player_char,
+            CharacterStats {
+                match_counts,
+                top_chars,
+                top_defeated,
+                top_rating,
+                performance: HashMap::new(),
+            },
+            top_global,
+            tags,
+            HashSet::new(),
+            HashSet::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status, "Private");
+        assert_eq!(response.name, PRIVATE_PLAYER_NAME);
+        assert!(response.ratings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_player_private_top_defeated() {
+        let (player_char, match_counts, top_chars, _top_defeated, top_rating, top_global, tags) =
+            get_test_player_data();
+
+        let mut top_defeated = HashMap::new();
+        top_defeated.insert(
+            0,
+            TopDefeated {
+                timestamp: "2024-01-01 00:00:00".to_string(),
+                id: 2,
+                name: "Opponent".to_string(),
+                char_short: "SO".to_string(),
+                value: 2000,
+            },
+        );
+
+        let response = handle_get_player(
+            player_char,
+            CharacterStats {
+                match_counts,
+                top_chars,
+                top_defeated,
+                top_rating,
+                performance: HashMap::new(),
+            },
+            top_global,
+            tags,
+            HashSet::new(),
+            HashSet::from([2]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status, "Public");
+        assert_eq!(response.ratings[0].top_defeated.id, 0);
+        assert_eq!(response.ratings[0].top_defeated.name, PRIVATE_PLAYER_NAME);
+        assert_eq!(response.ratings[0].top_defeated.value, 2000);
+    }
+
+    #[tokio::test]
+    async fn get_player_performance() {
+        let (player_char, match_counts, top_chars, top_defeated, top_rating, top_global, tags) =
+            get_test_player_data();
+
+        let response = handle_get_player(
+            player_char.clone(),
+            CharacterStats {
+                match_counts: match_counts.clone(),
+                top_chars: top_chars.clone(),
+                top_defeated: top_defeated.clone(),
+                top_rating: top_rating.clone(),
+                performance: HashMap::new(),
+            },
+            top_global,
+            tags.clone(),
+            HashSet::new(),
+            HashSet::new(),
+        )
+        .await
+        .unwrap();
+        assert!(response.ratings[0].performance.is_none());
+
+        let mut performance = Performance::default();
+        performance.add(1000, 1000, true);
+        performance.add(1000, 1000, false);
+        let response = handle_get_player(
+            player_char,
+            CharacterStats {
+                match_counts,
+                top_chars,
+                top_defeated,
+                top_rating,
+                performance: HashMap::from([(0, performance)]),
+            },
+            top_global,
+            tags,
+            HashSet::new(),
+            HashSet::new(),
+        )
+        .await
+        .unwrap();
+
+        let performance = response.ratings[0].performance.as_ref().unwrap();
+        assert_eq!((performance.games, performance.wins, performance.losses), (2, 1, 1));
+        assert_eq!(performance.residual_per_game, 0.0);
+    }
+
     fn get_test_player_data() -> (
         Vec<(Player, PlayerRating)>,
         HashMap<i16, i32>,
@@ -248,6 +419,7 @@ use super::*;
                 platform: 1,
                 api_key: None,
                 rcode_check_code: None,
+                private: false,
             },
             PlayerRating {
                 char_id: 0,
@@ -1,3 +1,4 @@
+use std::borrow::Borrow;
 use std::collections::HashMap;
 
 use serde::{Serialize, Serializer};
@@ -6,16 +7,44 @@ fn serialize_i64_as_string<S: Serializer>(v: &i64, s: S) -> Result<S::Ok, S::Err
     s.serialize_str(&v.to_string())
 }
 
-use crate::{models, CHAR_NAMES};
+use crate::{db::HistoryGame, error::Error, models, CHAR_NAMES};
 
-use super::common::TagResponse;
+use super::common::{TagResponse, PRIVATE_PLAYER_NAME};
 
 #[derive(Serialize)]
 pub struct PlayerGamesResponse {
     history: Vec<PlayerSet>,
+    sessions: Vec<PlayerSession>,
     tags: HashMap<String, Vec<TagResponse>>, //player_id to tags
 }
 
+#[derive(Serialize)]
+pub struct PlayerSetsResponse {
+    sets: Vec<PlayerGameSet>,
+    tags: HashMap<String, Vec<TagResponse>>, //player_id to tags
+}
+
+/// Consecutive games against the same opponent, see `group_sets`
+#[derive(Serialize)]
+struct PlayerGameSet {
+    timestamp: String,
+    floor: String,
+    opponent_name: String,
+    opponent_platform: &'static str,
+    #[serde(serialize_with = "serialize_i64_as_string")]
+    opponent_id: i64,
+    opponent_character: &'static str,
+    opponent_character_short: &'static str,
+    opponent_is_legend: bool,
+    wins: i64,
+    losses: i64,
+    score: String,
+    rating_before: i64,
+    rating_after: Option<i64>,
+    rating_change: Option<i64>,
+    games: Vec<PlayerSet>,
+}
+
 #[derive(Serialize)]
 struct PlayerSet {
     timestamp: String,
@@ -28,22 +57,175 @@ struct PlayerSet {
     opponent_character: &'static str,
     opponent_character_short: &'static str,
     opponent_rating_value: i64,
+    /// How much the game moved each rating, None before the side's next game is known
+    /// or when `rating_change` can't tell
+    own_rating_delta: Option<i64>,
+    opponent_rating_delta: Option<i64>,
     result_win: bool,
     opponent_is_legend: bool,
 }
 
+/// Games on the page no more than `SESSION_GAP_MINUTES` apart, see `split_sessions`
+#[derive(Serialize)]
+struct PlayerSession {
+    start: String,
+    end: String,
+    games: usize,
+    wins: usize,
+    losses: usize,
+    /// From before the oldest game to after the newest one
+    rating_change: Option<i64>,
+}
+
+/// Longest gap between two games of the same set
+pub const SET_WINDOW_MINUTES: i64 = 15;
+/// Longest gap between two games of the same session, unless the sessions endpoint is asked for another
+pub const SESSION_GAP_MINUTES: i64 = 30;
+/// Most sets per page when grouping
+pub const MAX_SETS_COUNT: usize = 100;
+/// Deepest page when grouping, every page reads the games of all the sets before it
+pub const MAX_SETS_OFFSET: usize = 1000;
+
+pub fn game_time(game: &models::Game) -> chrono::NaiveDateTime {
+    game.real_timestamp.unwrap_or(game.timestamp)
+}
+
+/// (opponent id, own character, opponent character) from `player_id`'s side
+fn set_key(player_id: i64, game: &models::Game) -> (i64, i16, i16) {
+    if game.id_a == player_id {
+        (game.id_b, game.char_a, game.char_b)
+    } else {
+        (game.id_a, game.char_b, game.char_a)
+    }
+}
+
+/// Splits `games` (newest first, as `db::get_games` returns them) into sets: runs of consecutive
+/// games against the same opponent and characters, at most `SET_WINDOW_MINUTES` apart.
+pub fn group_sets<G: Borrow<models::Game>>(player_id: i64, games: &[G]) -> Vec<&[G]> {
+    let window = chrono::Duration::minutes(SET_WINDOW_MINUTES);
+    let mut sets = vec![];
+    let mut start = 0;
+
+    for i in 1..=games.len() {
+        let (game, previous) = (games.get(i).map(G::borrow), games[i - 1].borrow());
+        let same_set = game.is_some_and(|game| {
+            set_key(player_id, game) == set_key(player_id, previous)
+                && game_time(previous) - game_time(game) <= window
+        });
+
+        if !same_set {
+            sets.push(&games[start..i]);
+            start = i;
+        }
+    }
+
+    sets
+}
+
+/// Splits `games` (newest first) into sessions: runs of games at most `gap_minutes` apart
+pub fn split_sessions<G: Borrow<models::Game>>(games: &[G], gap_minutes: i64) -> Vec<&[G]> {
+    let gap = chrono::Duration::minutes(gap_minutes);
+    let mut sessions = vec![];
+    let mut start = 0;
+
+    for i in 1..=games.len() {
+        let same_session = games
+            .get(i)
+            .is_some_and(|game| game_time(games[i - 1].borrow()) - game_time(game.borrow()) <= gap);
+
+        if !same_session {
+            sessions.push(&games[start..i]);
+            start = i;
+        }
+    }
+
+    sessions
+}
+
+/// Change between two ratings, None when one is hidden (0) or they're on different sides of Vanquisher
+pub fn rating_change(before: i64, after: i64) -> Option<i64> {
+    let hidden = before == 0 || after == 0;
+    let promoted = (before >= 10000000) != (after >= 10000000);
+    (!hidden && !promoted).then_some(after - before)
+}
+
+/// `sets` are newest first, each newest game first. Ratings on a game are from before it was played,
+/// so `rating_after_page` is the rating going into the game right after the newest set:
+/// the current rating on the first page, or that game's rating on later pages.
+pub async fn handle_get_player_sets(
+    player_id: i64,
+    sets: Vec<Vec<HistoryGame>>,
+    rating_after_page: Option<i64>,
+    player_tags: HashMap<i64, Vec<(String, String)>>,
+    legend_keys: std::collections::HashSet<(i64, i64)>,
+    private_players: std::collections::HashSet<i64>,
+) -> Result<PlayerSetsResponse, Error> {
+    let set_lengths: Vec<usize> = sets.iter().map(|s| s.len()).collect();
+    let games = sets.into_iter().flatten().collect();
+
+    let history = handle_get_player_history(player_id, games, player_tags, legend_keys, private_players).await?;
+    let mut rows = history.history.into_iter();
+
+    let mut response = PlayerSetsResponse {
+        sets: vec![],
+        tags: history.tags,
+    };
+
+    let mut rating_after = rating_after_page;
+    for len in set_lengths {
+        let games: Vec<PlayerSet> = rows.by_ref().take(len).collect();
+        let first = games.last().unwrap();
+        let wins = games.iter().filter(|g| g.result_win).count() as i64;
+        let losses = games.len() as i64 - wins;
+        let rating_before = first.own_rating_value;
+
+        response.sets.push(PlayerGameSet {
+            timestamp: first.timestamp.clone(),
+            floor: first.floor.clone(),
+            opponent_name: first.opponent_name.clone(),
+            opponent_platform: first.opponent_platform,
+            opponent_id: first.opponent_id,
+            opponent_character: first.opponent_character,
+            opponent_character_short: first.opponent_character_short,
+            opponent_is_legend: first.opponent_is_legend,
+            wins,
+            losses,
+            score: format!("{}-{}", wins, losses),
+            rating_before,
+            rating_after,
+            rating_change: rating_after.and_then(|after| rating_change(rating_before, after)),
+            games,
+        });
+
+        rating_after = Some(rating_before);
+    }
+
+    Ok(response)
+}
+
+/// `games` are newest first. Sessions are split from the games on this page only,
+/// so the oldest one may continue on the next page.
 pub async fn handle_get_player_history(
     player_id: i64,
-    games: Vec<models::Game>,
+    games: Vec<HistoryGame>,
     player_tags: HashMap<i64, Vec<(String, String)>>,
     legend_keys: std::collections::HashSet<(i64, i64)>,
-) -> Result<PlayerGamesResponse, String> {
+    private_players: std::collections::HashSet<i64>,
+) -> Result<PlayerGamesResponse, Error> {
     let mut response: PlayerGamesResponse = PlayerGamesResponse {
         history: vec![],
+        sessions: vec![],
         tags: HashMap::new(),
     };
 
-    for game in games {
+    let session_lengths: Vec<usize> = split_sessions(&games, SESSION_GAP_MINUTES).iter().map(|s| s.len()).collect();
+
+    for HistoryGame {
+        game,
+        own_rating_after,
+        opponent_rating_after,
+    } in games
+    {
         let own_rating_value = if game.id_a == player_id {
             game.value_a
         } else {
@@ -62,6 +244,12 @@ pub async fn handle_get_player_history(
             game.name_a.clone()
         };
 
+        let (opponent_id, opponent_name) = if private_players.contains(&opponent_id) {
+            (0, PRIVATE_PLAYER_NAME.to_string())
+        } else {
+            (opponent_id, opponent_name)
+        };
+
         let opponent_platform = if game.id_a == player_id {
             game.platform_b
         } else {
@@ -122,8 +310,12 @@ pub async fn handle_get_player_history(
             opponent_character,
             opponent_character_short,
             opponent_rating_value: opponent_rating_value,
+            own_rating_delta: own_rating_after.and_then(|after| rating_change(own_rating_value, after)),
+            opponent_rating_delta: opponent_rating_after
+                .and_then(|after| rating_change(opponent_rating_value, after)),
             result_win,
-            opponent_is_legend: legend_keys.contains(&(opponent_id, opponent_char_id as i64)),
+            opponent_is_legend: opponent_id != 0
+                && legend_keys.contains(&(opponent_id, opponent_char_id as i64)),
         });
 
         if opponent_id != 0 && player_tags.contains_key(&opponent_id) {
@@ -148,6 +340,24 @@ pub async fn handle_get_player_history(
             }
         }
     }
+
+    let mut rows = response.history.iter();
+    for len in session_lengths {
+        let games: Vec<&PlayerSet> = rows.by_ref().take(len).collect();
+        let (newest, oldest) = (games[0], games[len - 1]);
+        let wins = games.iter().filter(|g| g.result_win).count();
+        let newest_rating_after = newest.own_rating_delta.map(|delta| newest.own_rating_value + delta);
+
+        response.sessions.push(PlayerSession {
+            start: oldest.timestamp.clone(),
+            end: newest.timestamp.clone(),
+            games: len,
+            wins,
+            losses: len - wins,
+            rating_change: newest_rating_after.and_then(|after| rating_change(oldest.own_rating_value, after)),
+        });
+    }
+
     Ok(response)
 }
 
@@ -164,7 +374,7 @@ mod tests {
       let (mut games, player_tags) = get_test_player_history_data();
       games[0].platform_b = 3;
 
-      let response = handle_get_player_history(player_id, games, player_tags, HashSet::new())
+      let response = handle_get_player_history(player_id, history(games), player_tags, HashSet::new(), HashSet::new())
       .await
       .unwrap();
 
@@ -178,7 +388,7 @@ mod tests {
       let (mut games, player_tags) = get_test_player_history_data();
       games[0].platform_b = 2;
 
-      let response = handle_get_player_history(player_id, games, player_tags, HashSet::new())
+      let response = handle_get_player_history(player_id, history(games), player_tags, HashSet::new(), HashSet::new())
       .await
       .unwrap();
 
@@ -192,7 +402,7 @@ mod tests {
       let (mut games, player_tags) = get_test_player_history_data();
       games[0].platform_b = 1;
 
-      let response = handle_get_player_history(player_id, games, player_tags, HashSet::new())
+      let response = handle_get_player_history(player_id, history(games), player_tags, HashSet::new(), HashSet::new())
       .await
       .unwrap();
 
@@ -205,7 +415,7 @@ mod tests {
       let player_id = 1;
       let (games, player_tags) = get_test_player_history_data();
 
-      let response = handle_get_player_history(player_id, games, player_tags, HashSet::new())
+      let response = handle_get_player_history(player_id, history(games), player_tags, HashSet::new(), HashSet::new())
       .await
       .unwrap();
 
@@ -218,7 +428,7 @@ mod tests {
       let player_id = 2;
       let (games, player_tags) = get_test_player_history_data();
 
-      let response = handle_get_player_history(player_id, games, player_tags, HashSet::new())
+      let response = handle_get_player_history(player_id, history(games), player_tags, HashSet::new(), HashSet::new())
       .await
       .unwrap();
 
@@ -231,13 +441,107 @@ mod tests {
       let player_id = 2;
       let (games, player_tags) = get_test_player_history_data();
 
-      let response = handle_get_player_history(player_id, games, player_tags, HashSet::new())
+      let response = handle_get_player_history(player_id, history(games), player_tags, HashSet::new(), HashSet::new())
       .await
       .unwrap();
 
       assert_eq!(response.history[1].result_win, true);
     }
 
+    #[tokio::test]
+    async fn get_player_history_private_opponent() {
+
+      let player_id = 1;
+      let (games, mut player_tags) = get_test_player_history_data();
+      player_tags.insert(2, vec![("VIP".to_string(), "color: gold".to_string())]);
+
+      let response = handle_get_player_history(player_id, history(games), player_tags, HashSet::from([(2, 0)]), HashSet::from([2]))
+      .await
+      .unwrap();
+
+      assert_eq!(response.history[0].opponent_id, 0);
+      assert_eq!(response.history[0].opponent_name, PRIVATE_PLAYER_NAME);
+      assert_eq!(response.history[0].opponent_is_legend, false);
+      assert!(response.tags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_player_sets_score_and_rating_change() {
+
+      let player_id = 1;
+      let (games, player_tags) = get_test_player_history_data();
+      let games = history(games);
+      let sets = group_sets(player_id, &games).into_iter().map(|s| s.to_vec()).collect();
+
+      let response = handle_get_player_sets(player_id, sets, Some(1600), player_tags, HashSet::new(), HashSet::new())
+      .await
+      .unwrap();
+
+      assert_eq!(response.sets.len(), 1);
+      assert_eq!(response.sets[0].score, "1-1");
+      assert_eq!(response.sets[0].rating_before, 1500);
+      assert_eq!(response.sets[0].rating_after, Some(1600));
+      assert_eq!(response.sets[0].rating_change, Some(100));
+      assert_eq!(response.sets[0].games.len(), 2);
+    }
+
+    #[test]
+    fn group_sets_splits_on_opponent_and_window() {
+      let (mut games, _) = get_test_player_history_data();
+      let mut other_opponent = models::Game { id_b: 3, ..games[1].clone() };
+      other_opponent.timestamp = chrono::DateTime::from_timestamp(-60, 0).unwrap().naive_utc();
+      let mut much_later = other_opponent.clone();
+      much_later.timestamp = chrono::DateTime::from_timestamp(-60 - (SET_WINDOW_MINUTES + 1) * 60, 0).unwrap().naive_utc();
+      games.push(other_opponent);
+      games.push(much_later);
+
+      let sets = group_sets(1, &games);
+
+      assert_eq!(sets.iter().map(|s| s.len()).collect::<Vec<_>>(), vec![2, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn get_player_history_deltas_and_sessions() {
+
+      let player_id = 1;
+      let (mut games, player_tags) = get_test_player_history_data();
+      let mut earlier = games[1].clone();
+      earlier.value_a = 1400;
+      earlier.timestamp = chrono::DateTime::from_timestamp(-(SESSION_GAP_MINUTES + 1) * 60, 0).unwrap().naive_utc();
+      games.push(earlier);
+
+      let mut games = history(games);
+      games[0].own_rating_after = Some(1100);
+      games[0].opponent_rating_after = Some(1950);
+      games[1].own_rating_after = Some(1000);
+      games[2].own_rating_after = Some(1500);
+
+      let response = handle_get_player_history(player_id, games, player_tags, HashSet::new(), HashSet::new())
+      .await
+      .unwrap();
+
+      assert_eq!(response.history[0].own_rating_delta, Some(100));
+      assert_eq!(response.history[0].opponent_rating_delta, Some(-50));
+      assert_eq!(response.history[1].own_rating_delta, Some(-500));
+      assert_eq!(response.history[1].opponent_rating_delta, None);
+
+      assert_eq!(response.sessions.len(), 2);
+      assert_eq!((response.sessions[0].games, response.sessions[0].wins, response.sessions[0].losses), (2, 1, 1));
+      assert_eq!(response.sessions[0].rating_change, Some(-400));
+      assert_eq!(response.sessions[1].rating_change, Some(100));
+    }
+
+    fn history(games: Vec<models::Game>) -> Vec<HistoryGame> {
+      games
+        .into_iter()
+        .map(|game| HistoryGame {
+          game,
+          own_rating_after: None,
+          opponent_rating_after: None,
+        })
+        .collect()
+    }
+
     fn get_test_player_history_data()
     -> (Vec<models::Game>, HashMap<i64, Vec<(String,String)>>) {
       let games = vec![
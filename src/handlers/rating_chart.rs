@@ -0,0 +1,182 @@
+use axum::body::Bytes;
+use image::{Rgba, RgbaImage};
+
+const WIDTH: u32 = 600;
+const HEIGHT: u32 = 300;
+const PADDING: u32 = 16;
+const GRID_LINES: u32 = 4;
+
+const BACKGROUND: Rgba<u8> = Rgba([18, 18, 18, 255]);
+const GRID: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const DIVIDER: Rgba<u8> = Rgba([96, 96, 96, 255]);
+// Same colours RatingChart uses for the two rating systems
+const LP_LINE: Rgba<u8> = Rgba([75, 192, 192, 255]);
+const DR_LINE: Rgba<u8> = Rgba([255, 99, 132, 255]);
+
+fn is_vanquisher(rating: i64) -> bool {
+    rating >= 10000000
+}
+
+/// Min and max of the ratings on one side of the Vanquisher split.
+/// A flat line gets some room so it ends up in the middle of the chart.
+fn bounds(ratings: &[i64], vanquisher: bool) -> Option<(i64, i64)> {
+    let mut values = ratings.iter().filter(|r| is_vanquisher(**r) == vanquisher);
+    let first = *values.next()?;
+    let (min, max) = values.fold((first, first), |(min, max), r| (min.min(*r), max.max(*r)));
+
+    if min == max {
+        Some((min - 1, max + 1))
+    } else {
+        Some((min, max))
+    }
+}
+
+fn to_point(index: usize, count: usize, rating: i64, (min, max): (i64, i64)) -> (i32, i32) {
+    let plot_width = (WIDTH - PADDING * 2) as f64;
+    let plot_height = (HEIGHT - PADDING * 2) as f64;
+
+    let x = if count > 1 {
+        index as f64 / (count - 1) as f64 * plot_width
+    } else {
+        plot_width / 2.0
+    };
+    let y = (max - rating) as f64 / (max - min) as f64 * plot_height;
+
+    ((x + PADDING as f64).round() as i32, (y + PADDING as f64).round() as i32)
+}
+
+fn put(img: &mut RgbaImage, x: i32, y: i32, color: Rgba<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < img.width() && (y as u32) < img.height() {
+        img.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+// Bresenham, drawn 2px thick so it survives Discord's downscaling
+fn draw_line(img: &mut RgbaImage, (x0, y0): (i32, i32), (x1, y1): (i32, i32), color: Rgba<u8>) {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+
+    loop {
+        put(img, x, y, color);
+        put(img, x + 1, y, color);
+        put(img, x, y + 1, color);
+
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+/// Renders `ratings` (oldest first) as a PNG line chart.
+///
+/// LP and DR are on scales that are 10,000,000 apart, so each side of the Vanquisher split gets
+/// its own y axis and colour, the line is broken where the player crosses it and a divider marks the spot.
+pub fn render_rating_chart(ratings: &[i64]) -> Bytes {
+    let mut img = RgbaImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+
+    for i in 0..=GRID_LINES {
+        let y = (PADDING + (HEIGHT - PADDING * 2) * i / GRID_LINES) as i32;
+        draw_line(&mut img, (PADDING as i32, y), ((WIDTH - PADDING) as i32, y), GRID);
+    }
+
+    let lp_bounds = bounds(ratings, false);
+    let dr_bounds = bounds(ratings, true);
+
+    let points: Vec<((i32, i32), bool)> = ratings
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let vanquisher = is_vanquisher(*r);
+            let bounds = if vanquisher { dr_bounds } else { lp_bounds }.unwrap();
+            (to_point(i, ratings.len(), *r, bounds), vanquisher)
+        })
+        .collect();
+
+    for pair in points.windows(2) {
+        let (a, a_vanquisher) = pair[0];
+        let (b, b_vanquisher) = pair[1];
+
+        if a_vanquisher == b_vanquisher {
+            let color = if a_vanquisher { DR_LINE } else { LP_LINE };
+            draw_line(&mut img, a, b, color);
+        } else {
+            let x = (a.0 + b.0) / 2;
+            draw_line(&mut img, (x, PADDING as i32), (x, (HEIGHT - PADDING) as i32), DIVIDER);
+        }
+    }
+
+    if let [(point, vanquisher)] = points[..] {
+        let color = if vanquisher { DR_LINE } else { LP_LINE };
+        draw_line(&mut img, (point.0 - 2, point.1), (point.0 + 2, point.1), color);
+    }
+
+    let mut output = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut output);
+    img.write_to(&mut cursor, image::ImageFormat::Png).unwrap();
+
+    Bytes::from(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(png: &Bytes) -> RgbaImage {
+        image::load_from_memory(png).unwrap().to_rgba8()
+    }
+
+    fn count(img: &RgbaImage, color: Rgba<u8>) -> usize {
+        img.pixels().filter(|p| **p == color).count()
+    }
+
+    #[test]
+    fn render_rating_chart_empty() {
+        let img = decode(&render_rating_chart(&[]));
+
+        assert_eq!(img.dimensions(), (WIDTH, HEIGHT));
+        assert_eq!(count(&img, LP_LINE), 0);
+        assert_eq!(count(&img, DR_LINE), 0);
+    }
+
+    #[test]
+    fn render_rating_chart_lp_only() {
+        let img = decode(&render_rating_chart(&[24000, 24300, 24100, 24600]));
+
+        assert!(count(&img, LP_LINE) > 0);
+        assert_eq!(count(&img, DR_LINE), 0);
+        assert_eq!(count(&img, DIVIDER), 0);
+    }
+
+    #[test]
+    fn render_rating_chart_vanquisher_split() {
+        let img = decode(&render_rating_chart(&[44000, 44800, 45000, 10001500, 10001510, 10001495]));
+
+        assert!(count(&img, LP_LINE) > 0);
+        assert!(count(&img, DR_LINE) > 0);
+        assert!(count(&img, DIVIDER) > 0);
+
+        // The DR side is scaled on its own, so it spans the full height instead of sitting on the top edge
+        let top = PADDING;
+        let bottom = HEIGHT - PADDING;
+        let dr_rows: Vec<u32> = img
+            .enumerate_pixels()
+            .filter(|(_, _, p)| **p == DR_LINE)
+            .map(|(_, y, _)| y)
+            .collect();
+        assert!(dr_rows.iter().any(|y| *y <= top + 1));
+        assert!(dr_rows.iter().any(|y| *y >= bottom - 1));
+    }
+}
@@ -1,14 +1,14 @@
 use serde_json::Value;
-use crate::{CHAR_NAMES, db};
+use crate::{error::Error, CHAR_NAMES, db};
 
 pub async fn parse_player_stats_and_update_ratings(
     player_id: i64,
     json_data: &str,
     db: &mut crate::Connection<'_>,
-) -> Result<Vec<(i16, i64)>, String> {
+) -> Result<Vec<(i16, i64)>, Error> {
     let parsed: Value = match serde_json::from_str(json_data) {
         Ok(parsed) => parsed,
-        Err(e) => return Err(format!("Failed to parse JSON: {}", e)),
+        Err(e) => return Err(Error::from(e).context("Failed to parse player stats")),
     };
 
     let mut updated_ratings = Vec::new();
@@ -47,7 +47,7 @@ pub async fn parse_player_stats_and_update_ratings(
                 Ok(()) => {
                     updated_ratings.push((char_id, rating_value));
                 },
-                Err(e) => return Err(format!("Failed to update rating for character {}: {}", char_code, e)),
+                Err(e) => return Err(e.context(&format!("Failed to update rating for character {}", char_code))),
             }
         }
     }
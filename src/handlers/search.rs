@@ -1,6 +1,13 @@
+use std::collections::{HashMap, HashSet};
+
 use serde::{Deserialize, Serialize, Serializer};
 
-use crate::{models::{Player, PlayerRating}, CHAR_NAMES};
+use crate::{db::SearchResult, error::Error, CHAR_NAMES};
+
+use super::common::TagResponse;
+
+/// Most results per page, and the default
+const MAX_SEARCH_COUNT: usize = 100;
 
 fn serialize_i64_as_string<S: Serializer>(v: &i64, s: S) -> Result<S::Ok, S::Error> {
     s.serialize_str(&v.to_string())
@@ -15,27 +22,147 @@ struct PlayerSearchResponse {
     #[serde(serialize_with = "serialize_i64_as_string")]
     id: i64,
     name: String,
+    /// The previous name that matched, when it wasn't the current one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_name: Option<String>,
     rating: i64,
     char_short: String,
     char_long: String,
+    is_legend: bool,
+    tags: Vec<TagResponse>,
 }
 #[derive(Deserialize)]
 pub struct SearchParams {
     pub search_string: String,
     pub exact: Option<bool>,
+    pub char_short: Option<String>,
+    /// PS, XB or PC
+    pub platform: Option<String>,
+    pub min_rating: Option<i64>,
+    pub max_rating: Option<i64>,
+    pub count: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// `SearchParams` checked and resolved to ids for `db::find_player`
+pub struct SearchFilters {
+    pub search: String,
+    pub exact: bool,
+    pub char_id: Option<i16>,
+    pub platform: Option<i16>,
+    pub min_rating: Option<i64>,
+    pub max_rating: Option<i64>,
+    pub count: i64,
+    pub offset: i64,
 }
 
-pub async fn player_search(data: Vec<(Player, PlayerRating)>) -> Result<SearchResponse, String> {
+impl SearchFilters {
+    pub fn from_params(params: SearchParams) -> Result<SearchFilters, Error> {
+        let char_id = match &params.char_short {
+            Some(char_short) => match CHAR_NAMES.iter().position(|(c, _)| c == char_short) {
+                Some(char_id) => Some(char_id as i16),
+                None => return Err(Error::BadInput("Character not found".to_string())),
+            },
+            None => None,
+        };
+
+        let platform = match params.platform.as_deref() {
+            Some("PS") => Some(1),
+            Some("XB") => Some(2),
+            Some("PC") => Some(3),
+            Some(_) => return Err(Error::BadInput("Platform must be PS, XB or PC".to_string())),
+            None => None,
+        };
+
+        Ok(SearchFilters {
+            search: params.search_string,
+            exact: params.exact.unwrap_or(false),
+            char_id,
+            platform,
+            min_rating: params.min_rating,
+            max_rating: params.max_rating,
+            count: params.count.unwrap_or(MAX_SEARCH_COUNT).min(MAX_SEARCH_COUNT) as i64,
+            offset: params.offset.unwrap_or(0) as i64,
+        })
+    }
+}
+
+pub async fn player_search(
+    data: Vec<SearchResult>,
+    player_tags: HashMap<i64, Vec<(String, String)>>,
+    legend_keys: HashSet<(i64, i64)>,
+) -> Result<SearchResponse, Error> {
     let results = data
-        .iter()
+        .into_iter()
         .map(|p| PlayerSearchResponse {
-            id: p.0.id,
-            name: p.0.name.clone(),
-            rating: p.1.value,
-            char_short: CHAR_NAMES[p.1.char_id as usize].0.to_string(),
-            char_long: CHAR_NAMES[p.1.char_id as usize].1.to_string(),
+            id: p.id,
+            previous_name: (p.matched_name != p.name).then_some(p.matched_name),
+            name: p.name,
+            rating: p.value,
+            char_short: CHAR_NAMES[p.char_id as usize].0.to_string(),
+            char_long: CHAR_NAMES[p.char_id as usize].1.to_string(),
+            is_legend: legend_keys.contains(&(p.id, p.char_id as i64)),
+            tags: player_tags
+                .get(&p.id)
+                .map(|tags| {
+                    tags.iter()
+                        .map(|(tag, style)| TagResponse {
+                            tag: tag.clone(),
+                            style: style.clone(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
         })
         .collect();
 
     Ok(SearchResponse { results })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(char_short: Option<&str>, platform: Option<&str>, count: Option<usize>) -> SearchParams {
+        SearchParams {
+            search_string: "sol".to_string(),
+            exact: None,
+            char_short: char_short.map(str::to_string),
+            platform: platform.map(str::to_string),
+            min_rating: None,
+            max_rating: None,
+            count,
+            offset: None,
+        }
+    }
+
+    #[test]
+    fn filters_resolve_char_and_platform() {
+        let filters = SearchFilters::from_params(params(Some("KY"), Some("PC"), Some(500))).unwrap();
+        assert_eq!(filters.char_id, Some(1));
+        assert_eq!(filters.platform, Some(3));
+        assert_eq!(filters.count, MAX_SEARCH_COUNT as i64);
+
+        assert!(SearchFilters::from_params(params(Some("XX"), None, None)).is_err());
+        assert!(SearchFilters::from_params(params(None, Some("Switch"), None)).is_err());
+    }
+
+    #[tokio::test]
+    async fn search_response_has_previous_name_tags_and_legend() {
+        let data = vec![SearchResult {
+            id: 7,
+            name: "NewName".to_string(),
+            matched_name: "OldName".to_string(),
+            char_id: 0,
+            value: 1500,
+        }];
+        let tags = HashMap::from([(7, vec![("Dev".to_string(), "gold".to_string())])]);
+        let legend_keys = HashSet::from([(7, 0)]);
+
+        let response = player_search(data, tags, legend_keys).await.unwrap();
+        let result = &response.results[0];
+        assert_eq!(result.previous_name.as_deref(), Some("OldName"));
+        assert!(result.is_legend);
+        assert_eq!(result.tags[0].tag, "Dev");
+    }
+}
@@ -0,0 +1,161 @@
+use serde::Serialize;
+
+use crate::{db::HistoryGame, CHAR_NAMES};
+
+use super::player_history::{game_time, rating_change, split_sessions};
+
+/// Most games the sessions endpoint reads, the oldest session may be cut short past it
+pub const MAX_SESSION_GAMES: i64 = 5000;
+
+#[derive(Serialize)]
+pub struct SessionsResponse {
+    sessions: Vec<SessionResponse>,
+}
+
+/// Games no more than the gap apart, newest first
+#[derive(Serialize)]
+struct SessionResponse {
+    start: String,
+    end: String,
+    duration_minutes: i64,
+    games: usize,
+    wins: usize,
+    losses: usize,
+    rating_start: i64,
+    /// None until the player's next game after the session is known
+    rating_end: Option<i64>,
+    rating_peak: i64,
+    rating_change: Option<i64>,
+    /// Hidden (0) ratings left out, None if every opponent's was
+    opponent_average_rating: Option<i64>,
+    matchups: Vec<SessionMatchup>,
+}
+
+#[derive(Serialize)]
+struct SessionMatchup {
+    char_short: &'static str,
+    char_long: &'static str,
+    games: usize,
+    wins: usize,
+    losses: usize,
+}
+
+/// (own rating, opponent rating, opponent character, won) from `player_id`'s side
+fn own_side(player_id: i64, history: &HistoryGame) -> (i64, i64, i16, bool) {
+    let game = &history.game;
+    if game.id_a == player_id {
+        (game.value_a, game.value_b, game.char_b, game.winner == 1)
+    } else {
+        (game.value_b, game.value_a, game.char_a, game.winner == 2)
+    }
+}
+
+/// `games` are newest first, as `db::get_games_since` returns them
+pub fn handle_get_sessions(player_id: i64, games: &[HistoryGame], gap_minutes: i64) -> SessionsResponse {
+    let sessions = split_sessions(games, gap_minutes)
+        .into_iter()
+        .map(|session| {
+            let (newest, oldest) = (&session[0], &session[session.len() - 1]);
+            let sides: Vec<(i64, i64, i16, bool)> = session.iter().map(|g| own_side(player_id, g)).collect();
+            let wins = sides.iter().filter(|(_, _, _, won)| *won).count();
+
+            let rating_start = sides[sides.len() - 1].0;
+            let rating_end = newest.own_rating_after;
+            let rating_peak = sides.iter().map(|(own, ..)| *own).chain(rating_end).max().unwrap();
+
+            let opponent_ratings: Vec<i64> = sides.iter().map(|(_, opp, ..)| *opp).filter(|r| *r != 0).collect();
+            let opponent_average_rating = (!opponent_ratings.is_empty())
+                .then(|| opponent_ratings.iter().sum::<i64>() / opponent_ratings.len() as i64);
+
+            let mut matchups: Vec<SessionMatchup> = vec![];
+            for (_, _, opponent_char, won) in &sides {
+                let (char_short, char_long) = CHAR_NAMES[*opponent_char as usize];
+                let matchup = match matchups.iter().position(|m| m.char_short == char_short) {
+                    Some(i) => &mut matchups[i],
+                    None => {
+                        matchups.push(SessionMatchup { char_short, char_long, games: 0, wins: 0, losses: 0 });
+                        matchups.last_mut().unwrap()
+                    }
+                };
+                matchup.games += 1;
+                if *won {
+                    matchup.wins += 1;
+                } else {
+                    matchup.losses += 1;
+                }
+            }
+            matchups.sort_by_key(|m| std::cmp::Reverse(m.games));
+
+            let (start, end) = (game_time(&oldest.game), game_time(&newest.game));
+            SessionResponse {
+                start: start.to_string(),
+                end: end.to_string(),
+                duration_minutes: (end - start).num_minutes(),
+                games: session.len(),
+                wins,
+                losses: session.len() - wins,
+                rating_start,
+                rating_end,
+                rating_peak,
+                rating_change: rating_end.and_then(|end| rating_change(rating_start, end)),
+                opponent_average_rating,
+                matchups,
+            }
+        })
+        .collect();
+
+    SessionsResponse { sessions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models;
+
+    fn game(minutes: i64, char_b: i16, won: bool, value_a: i64, value_b: i64, after: Option<i64>) -> HistoryGame {
+        HistoryGame {
+            game: models::Game {
+                timestamp: chrono::DateTime::from_timestamp(minutes * 60, 0).unwrap().naive_utc(),
+                real_timestamp: None,
+                id_a: 1,
+                name_a: "Test".to_string(),
+                char_a: 0,
+                platform_a: 1,
+                id_b: 2,
+                name_b: "Test2".to_string(),
+                char_b,
+                platform_b: 1,
+                winner: if won { 1 } else { 2 },
+                game_floor: 99,
+                value_a,
+                value_b,
+            },
+            own_rating_after: after,
+            opponent_rating_after: None,
+        }
+    }
+
+    #[test]
+    fn sessions_split_on_idle_gap() {
+        let games = vec![
+            game(200, 1, true, 1100, 0, Some(1150)),
+            game(190, 1, false, 1200, 1300, Some(1100)),
+            game(180, 2, true, 1000, 1100, Some(1200)),
+            game(0, 2, true, 900, 1000, Some(1000)),
+        ];
+
+        let response = handle_get_sessions(1, &games, 30);
+        assert_eq!(response.sessions.len(), 2);
+
+        let session = &response.sessions[0];
+        assert_eq!((session.games, session.wins, session.losses), (3, 2, 1));
+        assert_eq!(session.duration_minutes, 20);
+        assert_eq!((session.rating_start, session.rating_end, session.rating_peak), (1000, Some(1150), 1200));
+        assert_eq!(session.rating_change, Some(150));
+        assert_eq!(session.opponent_average_rating, Some(1200));
+        assert_eq!(session.matchups[0].char_short, CHAR_NAMES[1].0);
+        assert_eq!((session.matchups[0].games, session.matchups[0].wins), (2, 1));
+
+        assert_eq!(response.sessions[1].games, 1);
+    }
+}
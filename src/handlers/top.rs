@@ -27,4 +27,6 @@ pub struct PlayerRankResponse {
     pub char_long: String,
     pub is_legend: bool,
     pub tags: Vec<TagResponse>,
+    /// Places gained since the previous daily snapshot, None if the player is new on the board
+    pub rank_delta: Option<i64>,
 }
@@ -0,0 +1,372 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::Sha256;
+
+use crate::error::Error;
+use crate::models::Game;
+use crate::CHAR_NAMES;
+
+use super::common::PRIVATE_PLAYER_NAME;
+use super::embed::format_rating;
+
+fn serialize_i64_as_string<S: Serializer>(v: &i64, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&v.to_string())
+}
+
+/// Deliveries are dropped after this many failed attempts
+pub const MAX_ATTEMPTS: i32 = 10;
+const BASE_BACKOFF_SECONDS: i64 = 30;
+const MAX_BACKOFF_SECONDS: i64 = 6 * 60 * 60;
+const DELIVERY_TIMEOUT_SECONDS: u64 = 10;
+/// Followed players per follower
+pub const MAX_FOLLOWS: i64 = 100;
+
+pub const SIGNATURE_HEADER: &str = "X-Signature-SHA256";
+pub const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+
+const WIN_COLOR: u32 = 0x4caf50;
+const LOSS_COLOR: u32 = 0xf44336;
+const RANK_COLOR: u32 = 0x2196f3;
+
+#[derive(Serialize)]
+pub struct FollowResponse {
+    #[serde(serialize_with = "serialize_i64_as_string")]
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookParams {
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct WebhookResponse {
+    pub url: String,
+    /// HMAC key for the signature header, a new one every time the webhook is set
+    pub secret: String,
+}
+
+/// Discord's execute webhook body, other receivers can read the same fields.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DiscordMessage {
+    pub username: String,
+    pub embeds: Vec<DiscordEmbed>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DiscordEmbed {
+    pub title: String,
+    pub description: String,
+    pub url: String,
+    pub color: u32,
+}
+
+fn message(embed: DiscordEmbed) -> String {
+    serde_json::to_string(&DiscordMessage {
+        username: "Puddle Farm".to_string(),
+        embeds: vec![embed],
+    })
+    .unwrap()
+}
+
+pub fn validate_url(url: &str) -> Result<reqwest::Url, Error> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "https" && parsed.host_str().is_some() => Ok(parsed),
+        _ => Err(Error::BadInput("Webhook url must be an https url".to_string())),
+    }
+}
+
+/// False for addresses the puller's own network could answer on: loopback, private, link-local
+/// (cloud metadata), unspecified, multicast and broadcast
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_multicast()
+                || v4.is_broadcast()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (v4.octets()[0] == 100 && v4.octets()[1] & 0xc0 == 64))
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public(IpAddr::V4(v4)),
+            None => {
+                let first = v6.segments()[0];
+                !(v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_multicast()
+                    // Unique local fc00::/7 and link-local fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// `url` and the addresses its host resolves to, when it's https and every address is public.
+/// Checked when the webhook is set and again before each delivery, DNS can change in between.
+pub async fn resolve_url(url: &str) -> Result<(reqwest::Url, Vec<SocketAddr>), Error> {
+    let parsed = validate_url(url)?;
+    let host = parsed.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    let port = parsed.port_or_known_default().unwrap_or(443);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| Error::BadInput(format!("Webhook host doesn't resolve: {e}")))?
+        .collect();
+
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(Error::BadInput("Webhook host must be a public address".to_string()));
+    }
+    Ok((parsed, addrs))
+}
+pub fn generate_secret() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Message for a game of `followed_id`, from that player's side. A private opponent's name is hidden.
+pub fn game_message(site_url: &str, game: &Game, followed_id: i64, private_players: &HashSet<i64>) -> String {
+    let (name, char_id, rating, opponent_id, opponent, opponent_char, opponent_rating, won) = if game.id_a == followed_id {
+        (&game.name_a, game.char_a, game.value_a, game.id_b, &game.name_b, game.char_b, game.value_b, game.winner == 1)
+    } else {
+        (&game.name_b, game.char_b, game.value_b, game.id_a, &game.name_a, game.char_a, game.value_a, game.winner == 2)
+    };
+    let opponent = if private_players.contains(&opponent_id) { PRIVATE_PLAYER_NAME } else { opponent.as_str() };
+    let char_short = CHAR_NAMES[char_id as usize].0;
+
+    message(DiscordEmbed {
+        title: format!(
+            "{} ({}) {} against {} ({})",
+            name,
+            char_short,
+            if won { "won" } else { "lost" },
+            opponent,
+            CHAR_NAMES[opponent_char as usize].0,
+        ),
+        description: format!("{} vs {}", format_rating(rating), format_rating(opponent_rating)),
+        url: format!("{}/player/{}/{}", site_url, followed_id, char_short),
+        color: if won { WIN_COLOR } else { LOSS_COLOR },
+    })
+}
+
+/// Message for a move on the global leaderboard, `previous_rank` is None when the player just entered it.
+pub fn rank_message(
+    site_url: &str,
+    followed_id: i64,
+    name: &str,
+    char_id: i16,
+    rank: i32,
+    previous_rank: Option<i32>,
+) -> String {
+    let char_short = CHAR_NAMES[char_id as usize].0;
+    let description = match previous_rank {
+        Some(previous_rank) => format!(
+            "{:+} since yesterday (was #{})",
+            super::leaderboard_history::rank_delta(previous_rank as i64, rank as i64),
+            previous_rank
+        ),
+        None => "New on the global leaderboard".to_string(),
+    };
+
+    message(DiscordEmbed {
+        title: format!("{} ({}) is now #{} global", name, char_short, rank),
+        description,
+        url: format!("{}/player/{}/{}", site_url, followed_id, char_short),
+        color: RANK_COLOR,
+    })
+}
+
+/// Hex HMAC-SHA256 of "{timestamp}.{body}", so a captured delivery can't be replayed with a new timestamp.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Wait before the next attempt, after `attempts` failed ones.
+pub fn backoff(attempts: i32) -> chrono::Duration {
+    let seconds = BASE_BACKOFF_SECONDS.saturating_mul(1 << attempts.clamp(0, 20));
+    chrono::Duration::seconds(seconds.min(MAX_BACKOFF_SECONDS))
+}
+
+/// Posts to `url` pinned to the addresses that were just checked, without following redirects,
+/// so neither a second DNS answer nor a redirect can point it at an internal address.
+pub async fn deliver(url: &str, secret: &str, payload: &str) -> Result<(), String> {
+    let (parsed, addrs) = resolve_url(url).await.map_err(|e| e.to_string())?;
+
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(DELIVERY_TIMEOUT_SECONDS))
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(parsed.host_str().unwrap_or_default(), &addrs)
+        .build()
+        .map_err(|e| format!("Client failed: {e}"))?;
+
+    post_signed(&client, url, secret, payload).await
+}
+
+async fn post_signed(
+    client: &reqwest::Client,
+    url: &str,
+    secret: &str,
+    payload: &str,
+) -> Result<(), String> {
+    let timestamp = chrono::Utc::now().timestamp();
+
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, sign(secret, timestamp, payload))
+        .body(payload.to_string())
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {e}"))?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Receiver returned {}", response.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use chrono::NaiveDateTime;
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    /// Receiver on a random local port that records every request and answers with `status`.
+    async fn stand_in(status: StatusCode) -> (String, Received) {
+        let received: Received = Arc::new(Mutex::new(vec![]));
+        let app = Router::new().route(
+            "/hook",
+            post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: Bytes| async move {
+                    received
+                        .lock()
+                        .unwrap()
+                        .push((headers, String::from_utf8(body.to_vec()).unwrap()));
+                    status
+                }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{}/hook", addr), received)
+    }
+
+    fn game() -> Game {
+        Game {
+            timestamp: NaiveDateTime::parse_from_str("2026-10-18 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            id_a: 1,
+            name_a: "Alice".to_string(),
+            char_a: 0,
+            platform_a: 1,
+            id_b: 2,
+            name_b: "Bob".to_string(),
+            char_b: 1,
+            platform_b: 1,
+            winner: 2,
+            game_floor: 99,
+            value_a: 10001500,
+            value_b: 10001480,
+            real_timestamp: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn deliver_signs_payload() {
+        let (url, received) = stand_in(StatusCode::NO_CONTENT).await;
+        let payload = game_message("https://puddle.farm", &game(), 2, &HashSet::new());
+
+        post_signed(&reqwest::Client::new(), &url, "secret", &payload).await.unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (headers, body) = &received[0];
+        assert_eq!(body, &payload);
+
+        let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        assert_eq!(headers[SIGNATURE_HEADER].to_str().unwrap(), sign("secret", timestamp, body));
+
+        let message: DiscordMessage = serde_json::from_str(body).unwrap();
+        assert_eq!(message.embeds[0].title, format!("Bob ({}) won against Alice ({})", CHAR_NAMES[1].0, CHAR_NAMES[0].0));
+        assert_eq!(message.embeds[0].description, "1480 DR vs 1500 DR");
+    }
+
+    #[tokio::test]
+    async fn deliver_fails_on_error_status() {
+        let (url, received) = stand_in(StatusCode::TOO_MANY_REQUESTS).await;
+
+        assert!(post_signed(&reqwest::Client::new(), &url, "secret", "{}").await.is_err());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn internal_urls_are_refused() {
+        assert!(validate_url("http://example.com/hook").is_err());
+        assert!(validate_url("https://example.com/hook").is_ok());
+
+        for url in ["https://127.0.0.1/hook", "https://localhost:8001/hook", "https://[::1]/hook", "https://169.254.169.254/"] {
+            assert!(resolve_url(url).await.is_err(), "{url}");
+        }
+
+        // The stand-in is on localhost, so it never gets the delivery
+        let (url, received) = stand_in(StatusCode::NO_CONTENT).await;
+        assert!(deliver(&url, "secret", "{}").await.is_err());
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn only_public_addresses() {
+        for ip in ["10.0.0.1", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0", "100.64.0.1", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_caps() {
+        assert_eq!(backoff(0), chrono::Duration::seconds(30));
+        assert_eq!(backoff(3), chrono::Duration::seconds(240));
+        assert_eq!(backoff(MAX_ATTEMPTS), chrono::Duration::seconds(MAX_BACKOFF_SECONDS));
+    }
+
+    #[test]
+    fn game_message_hides_private_opponent() {
+        let message: DiscordMessage =
+            serde_json::from_str(&game_message("https://puddle.farm", &game(), 2, &HashSet::from([1]))).unwrap();
+
+        assert_eq!(
+            message.embeds[0].title,
+            format!("Bob ({}) won against {} ({})", CHAR_NAMES[1].0, PRIVATE_PLAYER_NAME, CHAR_NAMES[0].0)
+        );
+        assert!(!message.embeds[0].title.contains("Alice"));
+    }
+
+    #[test]
+    fn rank_message_delta() {
+        let message: DiscordMessage =
+            serde_json::from_str(&rank_message("https://puddle.farm", 1, "Alice", 0, 12, Some(20))).unwrap();
+
+        assert_eq!(message.embeds[0].description, "+8 since yesterday (was #20)");
+    }
+}
@@ -4,19 +4,20 @@ use bb8_redis::redis;
 use chrono::NaiveDateTime;
 use tracing::warn;
 
+use crate::error::Error;
 use crate::{DistributionEntry, CHAR_NAMES};
 
-async fn get_string(key: &str, redis: &mut crate::RedisConnection<'_>) -> Result<String, String> {
-    match redis::cmd("GET").arg(key).query_async(&mut **redis).await {
-        Ok(v) => Ok(v),
-        Err(_) => Err(format!("Key {} not found", key)),
+async fn get_string(key: &str, redis: &mut crate::RedisConnection<'_>) -> Result<String, Error> {
+    match redis::cmd("GET").arg(key).query_async::<Option<String>>(&mut **redis).await? {
+        Some(v) => Ok(v),
+        None => Err(Error::NotFound(format!("Key {} not found", key))),
     }
 }
 
-async fn get_int(key: &str, redis: &mut crate::RedisConnection<'_>) -> Result<i64, String> {
-    match redis::cmd("GET").arg(key).query_async(&mut **redis).await {
-        Ok(v) => Ok(v),
-        Err(_) => Err(format!("Key {} not found", key)),
+async fn get_int(key: &str, redis: &mut crate::RedisConnection<'_>) -> Result<i64, Error> {
+    match redis::cmd("GET").arg(key).query_async::<Option<i64>>(&mut **redis).await? {
+        Some(v) => Ok(v),
+        None => Err(Error::NotFound(format!("Key {} not found", key))),
     }
 }
 
@@ -33,12 +34,10 @@ pub struct Stats {
     pub one_day_players: i64,
     pub one_hour_players: i64,
 }
-pub async fn get_stats(redis: &mut crate::RedisConnection<'_>) -> Result<Stats, String> {
+pub async fn get_stats(redis: &mut crate::RedisConnection<'_>) -> Result<Stats, Error> {
     let timestamp = match get_string("last_update_hourly", redis).await {
         Ok(ts) => ts,
-        Err(_) => {
-            return Err("Stats (last_update_hourly) not found".to_string());
-        }
+        Err(e) => return Err(e.context("Stats (last_update_hourly) not found")),
     };
 
     let total_games = get_int("total_games", redis).await?;
@@ -74,7 +73,7 @@ pub struct Popularity {
     pub per_character_total: i64,
     pub last_update: String,
 }
-pub async fn get_popularity(redis: &mut crate::RedisConnection<'_>) -> Result<Popularity, String> {
+pub async fn get_popularity(redis: &mut crate::RedisConnection<'_>) -> Result<Popularity, Error> {
     let mut per_player: Vec<(String, i64)> = vec![];
 
     for e in CHAR_NAMES.iter() {
@@ -82,9 +81,7 @@ pub async fn get_popularity(redis: &mut crate::RedisConnection<'_>) -> Result<Po
 
         let value: i64 = match get_int(&key, redis).await {
             Ok(v) => v,
-            Err(_) => {
-                return Err("Popularity not found".to_string());
-            }
+            Err(e) => return Err(e.context("Popularity not found")),
         };
 
         per_player.push((e.1.to_string(), value));
@@ -127,7 +124,7 @@ pub struct MatchupEntry {
 async fn get_matchup(
     prefix: &str,
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<Vec<MatchupChar>, String> {
+) -> Result<Vec<MatchupChar>, Error> {
     let mut matchups = vec![];
 
     for c in 0..CHAR_NAMES.len() {
@@ -135,12 +132,10 @@ async fn get_matchup(
 
         let value: String = match get_string(&key, redis).await {
             Ok(v) => v,
-            Err(_) => {
-                return Err("Matchup not found".to_string());
-            }
+            Err(e) => return Err(e.context("Matchup not found")),
         };
 
-        let matchups_data: Vec<crate::pull::Matchup> = serde_json::from_str(&value).unwrap();
+        let matchups_data: Vec<crate::pull::Matchup> = serde_json::from_str(&value)?;
         let char_name = CHAR_NAMES[c].1.to_string();
         let char_short = CHAR_NAMES[c].0.to_string();
 
@@ -182,7 +177,7 @@ pub struct Matchups {
     pub last_update: String,
     pub matchups: HashMap<String, Vec<MatchupChar>>,
 }
-pub async fn get_matchups(redis: &mut crate::RedisConnection<'_>) -> Result<Matchups, String> {
+pub async fn get_matchups(redis: &mut crate::RedisConnection<'_>) -> Result<Matchups, Error> {
     let prefixes = vec!["matchup", "matchup_vanq"];
     let mut matchups: HashMap<String, Vec<MatchupChar>> = HashMap::new();
 
@@ -199,14 +194,22 @@ pub async fn get_matchups(redis: &mut crate::RedisConnection<'_>) -> Result<Matc
     })
 }
 
+/// Set by the daily `update_performance` job
+pub async fn get_top_performers(
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Vec<crate::handlers::performance::TopPerformer>, Error> {
+    let top_performers = get_string("top_performers", redis).await?;
+    Ok(serde_json::from_str(&top_performers)?)
+}
+
 pub async fn get_distribution(
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<(String, DistributionEntry), String> {
+) -> Result<(String, DistributionEntry), Error> {
     let distribution_rating = get_string("distribution_rating", redis).await?;
 
     //Deserialize distribution_rating
     let distribution_rating: Vec<crate::pull::DistributionResult> =
-        serde_json::from_str(&distribution_rating).unwrap();
+        serde_json::from_str(&distribution_rating)?;
 
     //Get one_month_players
     let one_month_players = get_int("one_month_players", redis).await?;
@@ -224,16 +227,15 @@ pub async fn get_distribution(
 
 pub async fn get_latest_game_time(
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<NaiveDateTime, String> {
+) -> Result<NaiveDateTime, Error> {
     let latest_game_time = match get_string("latest_game_time", redis).await {
         Ok(lgt) => lgt,
-        Err(_) => {
-            return Err("Failed to get latest_game_time".to_string());
-        }
+        Err(e) => return Err(e.context("Failed to get latest_game_time")),
     };
 
     let latest_game_time =
-        NaiveDateTime::parse_from_str(&latest_game_time, "%Y-%m-%d %H:%M:%S").unwrap();
+        NaiveDateTime::parse_from_str(&latest_game_time, "%Y-%m-%d %H:%M:%S")
+            .map_err(|e| Error::Internal(format!("Invalid latest_game_time: {}", e)))?;
 
     Ok(latest_game_time)
 }
@@ -241,7 +243,7 @@ pub async fn get_latest_game_time(
 pub async fn set_latest_game_time(
     timestamp: NaiveDateTime,
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     match redis::cmd("SET")
         .arg("latest_game_time")
         .arg(timestamp.to_string())
@@ -249,46 +251,165 @@ pub async fn set_latest_game_time(
         .await
     {
         Ok(_) => Ok(()),
-        Err(_) => Err("Failed to set latest_game_time".to_string()),
+        Err(e) => Err(Error::from(e).context("Failed to set latest_game_time")),
     }
 }
 
-pub async fn clear_latest_game_time(redis: &mut crate::RedisConnection<'_>) -> Result<(), String> {
+pub async fn clear_latest_game_time(redis: &mut crate::RedisConnection<'_>) -> Result<(), Error> {
     match redis::cmd("DEL")
         .arg("latest_game_time")
         .query_async::<i64>(&mut **redis)
         .await
     {
         Ok(_) => Ok(()),
-        Err(_) => Err("Failed to clear latest_game_time".to_string()),
+        Err(e) => Err(Error::from(e).context("Failed to clear latest_game_time")),
     }
 }
 
+/// Counts runs of the replay puller that didn't reach games it already had, `last_replay_gap` is the
+/// oldest game of the latest one, the missing games are before it.
+pub async fn record_replay_gap(
+    oldest_game: NaiveDateTime,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), Error> {
+    redis::pipe()
+        .cmd("INCR")
+        .arg("replay_gaps")
+        .ignore()
+        .cmd("SET")
+        .arg("last_replay_gap")
+        .arg(oldest_game.to_string())
+        .ignore()
+        .query_async::<()>(&mut **redis)
+        .await
+        .map_err(|e| Error::from(e).context("Failed to record replay gap"))
+}
+
+/// The oldest game of the latest run `record_replay_gap` counted, NotFound if there wasn't one
+pub async fn get_last_replay_gap(redis: &mut crate::RedisConnection<'_>) -> Result<NaiveDateTime, Error> {
+    let gap = get_string("last_replay_gap", redis).await?;
+
+    NaiveDateTime::parse_from_str(&gap, "%Y-%m-%d %H:%M:%S%.f")
+        .map_err(|e| Error::Internal(format!("Invalid last_replay_gap: {}", e)))
+}
+
+/// Login state of the puller, for the web server's health check
+pub async fn set_auth_status(
+    status: &crate::ggst_api::AuthStatus,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), Error> {
+    redis::cmd("SET")
+        .arg("ggst_auth")
+        .arg(serde_json::to_string(status)?)
+        .query_async::<String>(&mut **redis)
+        .await
+        .map(|_| ())
+        .map_err(|e| Error::from(e).context("Failed to set ggst_auth"))
+}
+
+/// None until the puller has run once
+pub async fn get_auth_status(
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Option<crate::ggst_api::AuthStatus>, Error> {
+    match get_string("ggst_auth", redis).await {
+        Ok(status) => serde_json::from_str(&status)
+            .map(Some)
+            .map_err(|e| Error::from(e).context("Invalid ggst_auth")),
+        Err(Error::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// API version the puller last got answers with
+pub async fn set_api_version(version: &str, redis: &mut crate::RedisConnection<'_>) -> Result<(), Error> {
+    redis::cmd("SET")
+        .arg("ggst_api_version")
+        .arg(version)
+        .query_async::<String>(&mut **redis)
+        .await
+        .map(|_| ())
+        .map_err(|e| Error::from(e).context("Failed to set ggst_api_version"))
+}
+
+pub async fn get_api_version(redis: &mut crate::RedisConnection<'_>) -> Result<String, Error> {
+    get_string("ggst_api_version", redis).await
+}
+
 pub async fn get_last_update_daily(
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<NaiveDateTime, String> {
+) -> Result<NaiveDateTime, Error> {
     let last_update_daily = match get_string("last_update_daily", redis).await {
         Ok(lud) => lud,
-        Err(_) => {
+        Err(Error::NotFound(_)) => {
             // Key doesn't exist yet (fresh deployment) - return a time in the past
             // to indicate data needs to be refreshed
             tracing::warn!("last_update_daily not found in Redis, using default (2 days ago)");
             return Ok(chrono::Utc::now().naive_utc() - chrono::Duration::days(2));
         }
+        Err(e) => return Err(e),
     };
 
     let last_update_daily =
-        NaiveDateTime::parse_from_str(&last_update_daily, "%Y-%m-%d %H:%M:%S").unwrap();
+        NaiveDateTime::parse_from_str(&last_update_daily, "%Y-%m-%d %H:%M:%S")
+            .map_err(|e| Error::Internal(format!("Invalid last_update_daily: {}", e)))?;
 
     Ok(last_update_daily)
 }
 
-pub async fn get_free_comment(id: i64, redis: &mut crate::RedisConnection<'_>) -> Result<String, String> {
+/// For the health check
+pub async fn ping(redis: &mut crate::RedisConnection<'_>) -> Result<(), Error> {
+    redis::cmd("PING")
+        .query_async::<String>(&mut **redis)
+        .await
+        .map(|_| ())
+        .map_err(Error::from)
+}
+
+/// `latest_game_time`, `last_update_hourly` or `last_update_daily`, None when it isn't set
+pub async fn get_update_time(
+    key: &str,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Option<NaiveDateTime>, Error> {
+    match get_string(key, redis).await {
+        Ok(time) => NaiveDateTime::parse_from_str(&time, "%Y-%m-%d %H:%M:%S")
+            .map(Some)
+            .map_err(|e| Error::Internal(format!("Invalid {}: {}", key, e))),
+        Err(Error::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Entries in `leaderboard_all`, `leaderboard_legend` and every `leaderboard_char_*`, None for the missing ones
+pub async fn get_leaderboard_sizes(
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<Vec<(String, Option<usize>)>, Error> {
+    let mut keys = vec!["leaderboard_all".to_string(), "leaderboard_legend".to_string()];
+    keys.extend((0..CHAR_NAMES.len()).map(|c| format!("leaderboard_char_{}", c)));
+
+    let values: Vec<Option<String>> = redis::cmd("MGET")
+        .arg(&keys)
+        .query_async(&mut **redis)
+        .await
+        .map_err(|e| Error::from(e).context("Failed to get leaderboards"))?;
+
+    Ok(keys
+        .into_iter()
+        .zip(values)
+        .map(|(key, value)| {
+            let size = value
+                .and_then(|json| serde_json::from_str::<Vec<serde::de::IgnoredAny>>(&json).ok())
+                .map(|entries| entries.len());
+            (key, size)
+        })
+        .collect())
+}
+
+pub async fn get_free_comment(id: i64, redis: &mut crate::RedisConnection<'_>) -> Result<String, Error> {
     let key = format!("comment_{}", id);
 
     match get_string(&key, redis).await {
         Ok(comment) => Ok(comment),
-        Err(_) => Err("Failed to get comment".to_string()),
+        Err(e) => Err(e.context("Failed to get comment")),
     }
 }
 
@@ -296,7 +417,7 @@ pub async fn set_free_comment(
     id: i64,
     comment: &str,
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let key = format!("comment_{}", id);
 
     match redis::cmd("SET")
@@ -308,16 +429,16 @@ pub async fn set_free_comment(
         .await
     {
         Ok(_) => Ok(()),
-        Err(_) => Err("Failed to set comment".to_string()),
+        Err(e) => Err(Error::from(e).context("Failed to set comment")),
     }
 }
 
-pub async fn get_avatar(id: i64, redis: &mut crate::RedisConnection<'_>) -> Result<String, String> {
+pub async fn get_avatar(id: i64, redis: &mut crate::RedisConnection<'_>) -> Result<String, Error> {
     let key = format!("avatar_{}", id);
 
     match get_string(&key, redis).await {
         Ok(avatar) => Ok(avatar),
-        Err(_) => Err("Failed to get avatar".to_string()),
+        Err(e) => Err(e.context("Failed to get avatar")),
     }
 }
 
@@ -325,7 +446,7 @@ pub async fn set_avatar(
     id: i64,
     avatar: &str,
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let key = format!("avatar_{}", id);
 
     match redis::cmd("SET")
@@ -337,46 +458,210 @@ pub async fn set_avatar(
         .await
     {
         Ok(_) => Ok(()),
-        Err(_) => Err("Failed to set avatar".to_string()),
+        Err(e) => Err(Error::from(e).context("Failed to set avatar")),
     }
 }
 
-pub async fn check_rating_sync_rate_limit(
-    player_id: i64,
+pub async fn get_rating_chart(
+    id: i64,
+    char_id: i16,
+    duration: i32,
+    pre_vanquisher: bool,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<String, Error> {
+    let key = format!("rating_chart_{}_{}_{}_{}", id, char_id, duration, pre_vanquisher);
+
+    match get_string(&key, redis).await {
+        Ok(chart) => Ok(chart),
+        Err(e) => Err(e.context("Failed to get rating chart")),
+    }
+}
+
+//Short expiry, a new game changes the chart
+pub async fn set_rating_chart(
+    id: i64,
+    char_id: i16,
+    duration: i32,
+    pre_vanquisher: bool,
+    chart: &str,
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<bool, String> {
-    let rate_limit_key = format!("rating_sync:{}", player_id);
+) -> Result<(), Error> {
+    let key = format!("rating_chart_{}_{}_{}_{}", id, char_id, duration, pre_vanquisher);
+
+    match redis::cmd("SET")
+        .arg(&key)
+        .arg(chart)
+        .arg("EX")
+        .arg("600")
+        .query_async::<String>(&mut **redis)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Error::from(e).context("Failed to set rating chart")),
+    }
+}
 
+async fn check_rate_limit(key: &str, redis: &mut crate::RedisConnection<'_>) -> Result<bool, Error> {
     match redis::cmd("EXISTS")
-        .arg(&rate_limit_key)
+        .arg(key)
         .query_async::<i32>(&mut **redis)
         .await
     {
         Ok(exists) => Ok(exists == 1),
         Err(_) => {
-            warn!("Failed to check rate limit for player {}", player_id);
+            warn!("Failed to check rate limit {}", key);
             Ok(false) // Allow on Redis error
         }
     }
 }
 
-pub async fn set_rating_sync_rate_limit(
-    player_id: i64,
+async fn set_rate_limit(
+    key: &str,
+    seconds: i64,
     redis: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
-    let rate_limit_key = format!("rating_sync:{}", player_id);
-
+) -> Result<(), Error> {
     match redis::cmd("SETEX")
-        .arg(&rate_limit_key)
-        .arg(60)
+        .arg(key)
+        .arg(seconds)
         .arg("1")
         .query_async::<()>(&mut **redis)
         .await
     {
         Ok(_) => Ok(()),
         Err(_) => {
-            warn!("Failed to set rate limit for player {}", player_id);
-            Err("Failed to set rate limit".to_string())
+            warn!("Failed to set rate limit {}", key);
+            Err(Error::UpstreamUnavailable("Failed to set rate limit".to_string()))
         }
     }
 }
+
+pub async fn check_rating_sync_rate_limit(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<bool, Error> {
+    check_rate_limit(&format!("rating_sync:{}", player_id), redis).await
+}
+
+pub async fn set_rating_sync_rate_limit(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), Error> {
+    set_rate_limit(&format!("rating_sync:{}", player_id), 60, redis).await
+}
+
+pub async fn check_claim_rate_limit(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<bool, Error> {
+    check_rate_limit(&format!("claim:{}", player_id), redis).await
+}
+
+pub async fn set_claim_rate_limit(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), Error> {
+    set_rate_limit(&format!("claim:{}", player_id), 60, redis).await
+}
+
+pub async fn check_claim_poll_rate_limit(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<bool, Error> {
+    check_rate_limit(&format!("claim_poll:{}", player_id), redis).await
+}
+
+pub async fn set_claim_poll_rate_limit(
+    player_id: i64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), Error> {
+    set_rate_limit(&format!("claim_poll:{}", player_id), 10, redis).await
+}
+
+/// Takes `job`'s lock for `ttl_seconds` unless another pull instance holds it. The ttl frees the
+/// lock if the holder dies mid-run.
+pub async fn acquire_job_lock(
+    job: &str,
+    owner: &str,
+    ttl_seconds: u64,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<bool, Error> {
+    redis::cmd("SET")
+        .arg(format!("job_lock:{}", job))
+        .arg(owner)
+        .arg("NX")
+        .arg("EX")
+        .arg(ttl_seconds)
+        .query_async::<Option<String>>(&mut **redis)
+        .await
+        .map(|set| set.is_some())
+        .map_err(|e| Error::from(e).context("Failed to lock job"))
+}
+
+/// Frees the lock if `owner` still holds it, it may have expired and been taken by someone else
+pub async fn release_job_lock(
+    job: &str,
+    owner: &str,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), Error> {
+    let key = format!("job_lock:{}", job);
+    let holder = redis::cmd("GET")
+        .arg(&key)
+        .query_async::<Option<String>>(&mut **redis)
+        .await?;
+
+    if holder.as_deref() == Some(owner) {
+        redis::cmd("DEL")
+            .arg(&key)
+            .query_async::<i64>(&mut **redis)
+            .await
+            .map_err(|e| Error::from(e).context("Failed to unlock job"))?;
+    }
+    Ok(())
+}
+
+async fn exists(key: &str, redis: &mut crate::RedisConnection<'_>) -> Result<bool, Error> {
+    Ok(redis::cmd("EXISTS").arg(key).query_async::<i32>(&mut **redis).await? == 1)
+}
+
+pub async fn is_job_locked(job: &str, redis: &mut crate::RedisConnection<'_>) -> Result<bool, Error> {
+    exists(&format!("job_lock:{}", job), redis).await
+}
+
+/// Asks the scheduler to run `job` on its next tick whether it's due or not
+pub async fn trigger_job(job: &str, redis: &mut crate::RedisConnection<'_>) -> Result<(), Error> {
+    redis::cmd("SET")
+        .arg(format!("job_trigger:{}", job))
+        .arg(1)
+        .query_async::<String>(&mut **redis)
+        .await
+        .map(|_| ())
+        .map_err(|e| Error::from(e).context("Failed to trigger job"))
+}
+
+pub async fn is_job_triggered(job: &str, redis: &mut crate::RedisConnection<'_>) -> Result<bool, Error> {
+    exists(&format!("job_trigger:{}", job), redis).await
+}
+
+/// True once per `trigger_job`
+pub async fn take_job_trigger(job: &str, redis: &mut crate::RedisConnection<'_>) -> Result<bool, Error> {
+    redis::cmd("DEL")
+        .arg(format!("job_trigger:{}", job))
+        .query_async::<i64>(&mut **redis)
+        .await
+        .map(|deleted| deleted > 0)
+        .map_err(|e| Error::from(e).context("Failed to read job trigger"))
+}
+
+pub async fn set_update_time(
+    key: &str,
+    time: NaiveDateTime,
+    redis: &mut crate::RedisConnection<'_>,
+) -> Result<(), Error> {
+    redis::cmd("SET")
+        .arg(key)
+        .arg(time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .query_async::<String>(&mut **redis)
+        .await
+        .map(|_| ())
+        .map_err(|e| Error::from(e).context(&format!("Failed to set {}", key)))
+}
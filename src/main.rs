@@ -1,10 +1,12 @@
 use axum::extract::{Path, Query};
 use axum::http::header::{self, ACCEPT, AUTHORIZATION, CONTENT_TYPE, ORIGIN};
 use axum::http::{HeaderMap, HeaderValue, Method};
-use axum::response::IntoResponse;
-use axum::{extract::State, http::StatusCode, response::Json, routing::get, Router};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::{extract::State, http::StatusCode, response::Json, routing::{get, post, put}, Router};
 use bb8::PooledConnection;
 use diesel_async::{pooled_connection::AsyncDieselConnectionManager, AsyncPgConnection};
+use futures_util::{Stream, StreamExt};
 use handlers::common::{Pagination, TagResponse};
 use models::Player;
 use serde::{Deserialize, Serialize, Serializer};
@@ -14,6 +16,7 @@ fn serialize_i64_as_string<S: Serializer>(v: &i64, s: S) -> Result<S::Ok, S::Err
 }
 use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
+use std::sync::Arc;
 use std::vec;
 use tower_http::cors::{Any, CorsLayer};
 use tracing_appender::non_blocking::WorkerGuard;
@@ -22,6 +25,7 @@ use tracing_subscriber::layer::SubscriberExt;
 
 use bb8_redis::{bb8, RedisConnectionManager};
 
+use crate::error::Error;
 use crate::models::PlayerRating;
 
 type Pool = bb8::Pool<AsyncDieselConnectionManager<AsyncPgConnection>>;
@@ -34,16 +38,24 @@ type RedisConnection<'a> = PooledConnection<'a, RedisConnectionManager>;
 struct AppState {
     db_pool: Pool,
     redis_pool: RedisPool,
+    ggst: Arc<dyn ggst_api::GgstApi>,
+    live: handlers::live::LiveFeed,
 }
 
 mod db;
+mod dump;
+mod embed;
+mod error;
 mod ggst_api;
 mod handlers;
 mod imdb;
+mod metrics;
 mod models;
 mod pull;
+mod rating;
 mod requests;
 mod responses;
+mod scheduler;
 mod schema;
 
 pub const CHAR_NAMES: &[(&str, &str)] = &[
@@ -83,106 +95,258 @@ pub const CHAR_NAMES: &[(&str, &str)] = &[
     ("RK", "Robo-Ky"),
 ];
 
+/// For `is_player_private`: a player we don't know has nothing to hide, any other error fails the
+/// request rather than serve a private player's data
+fn unknown_is_public(e: Error) -> Result<bool, Error> {
+    match e {
+        Error::NotFound(_) => Ok(false),
+        e => Err(e),
+    }
+}
+
 async fn player(
     State(pools): State<AppState>,
     Path(id): Path<i64>,
-) -> Result<Json<crate::handlers::player::PlayerResponse>, (StatusCode, String)> {
-    let mut db = pools.db_pool.get().await.unwrap();
+) -> Result<Json<crate::handlers::player::PlayerResponse>, Error> {
+    let mut db = pools.db_pool.get().await?;
 
     let (player_char, match_counts, mut top_chars, top_defeated, top_rating, mut top_global, tags) =
         match db::get_player_response_data(id, &mut db).await {
             Ok(response) => response,
-            Err(e) => return Err((StatusCode::NOT_FOUND, e)),
+            Err(e) => return Err(e),
         };
 
-    let mut redis = pools.redis_pool.get().await.unwrap();
-    let legend_keys = get_legend_keys(&mut redis).await;
+    let defeated_ids: HashSet<i64> = top_defeated.values().map(|td| td.id).collect();
+    let private_players = db::get_private_players(defeated_ids, &mut db).await?;
 
-    let leaderboard: Vec<responses::LeaderboardEntry> = read_leaderboard(&mut redis, "leaderboard_all")
-        .await
-        .unwrap_or_else(|_| vec![]);
-
-    let player_id_str = id.to_string();
+    let mut redis = pools.redis_pool.get().await?;
+    let legend_keys = get_legend_keys(&mut redis).await;
 
-    if !leaderboard.is_empty() {
-        // top_global: best rank across all chars
-        if let Some(entry) = leaderboard.iter()
-            .filter(|e| e.player_id == player_id_str)
-            .min_by_key(|e| e.rank)
-        {
-            top_global = entry.rank as i32;
-        }
+    let (global_rank, char_ranks) = read_player_ranks(id, &player_char, &mut redis).await;
+    if let Some(rank) = global_rank {
+        top_global = rank;
     }
+    top_chars.extend(char_ranks);
 
-    // top_chars: position within each character's specific leaderboard
-    for (_, rating) in &player_char {
-        let key = format!("leaderboard_char_{}", rating.char_id);
-        let char_leaderboard = read_leaderboard(&mut redis, &key).await.unwrap_or_else(|_| vec![]);
-        if let Some(pos) = char_leaderboard.iter().position(|e| e.player_id == player_id_str) {
-            top_chars.insert(rating.char_id, (pos + 1) as i32);
-        }
-    }
+    let since = chrono::Utc::now().naive_utc()
+        - chrono::Duration::days(handlers::performance::PERFORMANCE_WINDOW_DAYS);
+    let performance = db::get_performance(id, since, &mut db).await?;
 
-    match handlers::player::handle_get_player(
-        player_char,
+    let stats = handlers::player::CharacterStats {
         match_counts,
         top_chars,
         top_defeated,
         top_rating,
+        performance,
+    };
+
+    match handlers::player::handle_get_player(
+        player_char,
+        stats,
         top_global,
         tags,
         legend_keys,
+        private_players,
     )
     .await
     {
         Ok(response) => Ok(Json(response)),
-        Err(e) => Err((StatusCode::NOT_FOUND, e)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Best global rank and the position within each character's leaderboard, from the Redis leaderboards.
+async fn read_player_ranks(
+    id: i64,
+    player_char: &[(Player, PlayerRating)],
+    redis: &mut crate::RedisConnection<'_>,
+) -> (Option<i32>, HashMap<i16, i32>) {
+    let player_id_str = id.to_string();
+
+    // top_global: best rank across all chars
+    let global_rank = read_leaderboard(redis, "leaderboard_all")
+        .await
+        .unwrap_or_else(|_| vec![])
+        .iter()
+        .filter(|e| e.player_id == player_id_str)
+        .min_by_key(|e| e.rank)
+        .map(|e| e.rank as i32);
+
+    // top_chars: position within each character's specific leaderboard
+    let mut char_ranks = HashMap::new();
+    for (_, rating) in player_char {
+        let key = format!("leaderboard_char_{}", rating.char_id);
+        let char_leaderboard = read_leaderboard(redis, &key).await.unwrap_or_else(|_| vec![]);
+        if let Some(pos) = char_leaderboard.iter().position(|e| e.player_id == player_id_str) {
+            char_ranks.insert(rating.char_id, (pos + 1) as i32);
+        }
     }
+
+    (global_rank, char_ranks)
+}
+
+#[derive(Deserialize)]
+struct HistoryParams {
+    /// "sets" to group consecutive games against the same opponent, count and offset are then in sets
+    group: Option<String>,
 }
 
 async fn player_history(
     State(pools): State<AppState>,
     Path((player_id, char_id)): Path<(i64, String)>,
     Query(pagination): Query<Pagination>,
-) -> Result<Json<handlers::player_history::PlayerGamesResponse>, (StatusCode, String)> {
+    Query(params): Query<HistoryParams>,
+) -> Result<Response, Error> {
     let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
         Some(id) => id as i16,
         None => {
-            return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
+            return Err(Error::NotFound("Character not found".to_string()));
         }
     };
 
-    let mut db = pools.db_pool.get().await.unwrap();
+    match params.group.as_deref() {
+        None => player_games(pools, player_id, char_id, pagination)
+            .await
+            .map(|r| r.into_response()),
+        Some("sets") => player_sets(pools, player_id, char_id, pagination)
+            .await
+            .map(|r| r.into_response()),
+        Some(_) => Err(Error::BadInput("Unknown group".to_string())),
+    }
+}
+
+async fn player_sets(
+    pools: AppState,
+    player_id: i64,
+    char_id: i16,
+    pagination: Pagination,
+) -> Result<Json<handlers::player_history::PlayerSetsResponse>, Error> {
+    use handlers::player_history::{MAX_SETS_COUNT, MAX_SETS_OFFSET};
+
+    let count = pagination.count.unwrap_or(MAX_SETS_COUNT).min(MAX_SETS_COUNT);
+    let offset = pagination.offset.unwrap_or(0);
+    if offset > MAX_SETS_OFFSET {
+        return Err(Error::BadInput(format!("offset must be at most {} when grouping sets", MAX_SETS_OFFSET)));
+    }
+
+    let mut db = pools.db_pool.get().await?;
+
+    let (sets, rating_after_page) = if db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        (vec![], None)
+    } else {
+        match db::get_game_sets(player_id, char_id, count, offset, &mut db).await {
+            Ok(sets) => sets,
+            Err(e) => return Err(e),
+        }
+    };
+
+    let player_ids: HashSet<i64> = sets.iter().flatten().flat_map(|g| [g.game.id_a, g.game.id_b]).collect();
+    let player_tags = db::get_tags_from_player_list(player_ids.clone(), &mut db).await.unwrap_or_default();
+    let private_players = db::get_private_players(player_ids, &mut db).await?;
+
+    let mut redis = pools.redis_pool.get().await?;
+    let legend_keys = get_legend_keys(&mut redis).await;
+
+    match handlers::player_history::handle_get_player_sets(
+        player_id,
+        sets,
+        rating_after_page,
+        player_tags,
+        legend_keys,
+        private_players,
+    )
+    .await
+    {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => Err(e),
+    }
+}
+
+async fn player_games(
+    pools: AppState,
+    player_id: i64,
+    char_id: i16,
+    pagination: Pagination,
+) -> Result<Json<handlers::player_history::PlayerGamesResponse>, Error> {
+    let mut db = pools.db_pool.get().await?;
 
     let count = pagination.count.unwrap_or(100) as i64;
     let offset = pagination.offset.unwrap_or(0) as i64;
 
-    let games: Vec<models::Game> =
+    let games: Vec<db::HistoryGame> = if db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        vec![]
+    } else {
         match db::get_games(player_id, char_id, count, offset, &mut db).await {
             Ok(games) => games,
-            Err(e) => return Err((StatusCode::NOT_FOUND, e)),
-        };
+            Err(e) => return Err(e),
+        }
+    };
 
     //Get tags
     let mut player_ids = HashSet::new();
     for game in &games {
-        player_ids.insert(game.id_a);
-        player_ids.insert(game.id_b);
+        player_ids.insert(game.game.id_a);
+        player_ids.insert(game.game.id_b);
     }
-    let player_tags = match db::get_tags_from_player_list(player_ids, &mut db).await {
+    let player_tags = match db::get_tags_from_player_list(player_ids.clone(), &mut db).await {
         Ok(tags) => tags,
         Err(_) => HashMap::new(),
     };
+    let private_players = db::get_private_players(player_ids, &mut db).await?;
 
-    let mut redis = pools.redis_pool.get().await.unwrap();
+    let mut redis = pools.redis_pool.get().await?;
     let legend_keys = get_legend_keys(&mut redis).await;
 
-    match handlers::player_history::handle_get_player_history(player_id, games, player_tags, legend_keys).await {
+    match handlers::player_history::handle_get_player_history(player_id, games, player_tags, legend_keys, private_players).await {
         Ok(response) => Ok(Json(response)),
-        Err(e) => Err((StatusCode::NOT_FOUND, e)),
+        Err(e) => Err(e),
     }
 }
 
+#[derive(Deserialize)]
+struct H2HParams {
+    sets: Option<usize>,
+}
+
+async fn h2h(
+    State(pools): State<AppState>,
+    Path((player_a, player_b)): Path<(i64, i64)>,
+    Query(params): Query<H2HParams>,
+) -> Result<Json<handlers::h2h::H2HResponse>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    let players = match db::get_player_names(HashSet::from([player_a, player_b]), &mut db).await {
+        Ok(players) => players,
+        Err(e) => return Err(e),
+    };
+    let (name_a, name_b) = match (players.get(&player_a), players.get(&player_b)) {
+        (Some((name_a, false)), Some((name_b, false))) => (name_a.clone(), name_b.clone()),
+        (Some(_), Some(_)) => return Err(Error::NotFound("Player is private".to_string())),
+        _ => return Err(Error::NotFound("Player not found".to_string())),
+    };
+
+    let games = match db::get_h2h_games(player_a, player_b, &mut db).await {
+        Ok(games) => games,
+        Err(e) => return Err(e),
+    };
+
+    let player_tags = db::get_tags_from_player_list(HashSet::from([player_a, player_b]), &mut db)
+        .await
+        .unwrap_or_default();
+
+    let mut redis = pools.redis_pool.get().await?;
+    let legend_keys = get_legend_keys(&mut redis).await;
+
+    Ok(Json(handlers::h2h::handle_get_h2h(
+        (player_a, name_a),
+        (player_b, name_b),
+        games,
+        player_tags,
+        legend_keys,
+        params.sets.unwrap_or(10),
+    )))
+}
+
 async fn get_legend_keys(redis: &mut crate::RedisConnection<'_>) -> HashSet<(i64, i64)> {
     use bb8_redis::redis;
     let data: Result<String, redis::RedisError> = redis::cmd("GET")
@@ -206,6 +370,8 @@ fn build_rank_response(
     entries: &[responses::LeaderboardEntry],
     legend_keys: &HashSet<(i64, i64)>,
     player_tags: &HashMap<i64, Vec<(String, String)>>,
+    private_players: &HashSet<i64>,
+    previous_ranks: &HashMap<(i64, i64), i64>,
     offset: usize,
     count: usize,
 ) -> handlers::top::RankResponse {
@@ -216,6 +382,22 @@ fn build_rank_response(
         .take(count)
         .map(|e| {
             let id = e.player_id.parse::<i64>().unwrap_or(0);
+            let rank_delta = previous_ranks
+                .get(&(id, e.char_id))
+                .map(|previous_rank| handlers::leaderboard_history::rank_delta(*previous_rank, e.rank));
+            if private_players.contains(&id) {
+                return PlayerRankResponse {
+                    rank: e.rank,
+                    id: 0,
+                    name: handlers::common::PRIVATE_PLAYER_NAME.to_string(),
+                    rating: e.rating,
+                    char_short: CHAR_NAMES[e.char_id as usize].0.to_string(),
+                    char_long: CHAR_NAMES[e.char_id as usize].1.to_string(),
+                    is_legend: false,
+                    tags: vec![],
+                    rank_delta,
+                };
+            }
             let tags = player_tags.get(&id).map(|t| {
                 t.iter().map(|(tag, style)| TagResponse {
                     tag: tag.clone(),
@@ -231,6 +413,7 @@ fn build_rank_response(
                 char_long: CHAR_NAMES[e.char_id as usize].1.to_string(),
                 is_legend: legend_keys.contains(&(id, e.char_id)),
                 tags,
+                rank_delta,
             }
         })
         .collect();
@@ -240,16 +423,15 @@ fn build_rank_response(
 async fn read_leaderboard(
     redis: &mut crate::RedisConnection<'_>,
     key: &str,
-) -> Result<Vec<responses::LeaderboardEntry>, (StatusCode, String)> {
+) -> Result<Vec<responses::LeaderboardEntry>, Error> {
     use bb8_redis::redis;
-    let data: Result<String, redis::RedisError> = redis::cmd("GET")
+    let data: Option<String> = redis::cmd("GET")
         .arg(key)
         .query_async(&mut **redis)
-        .await;
+        .await?;
     match data {
-        Ok(json) => serde_json::from_str(&json)
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
-        Err(_) => Err((StatusCode::SERVICE_UNAVAILABLE, "Leaderboard not yet available".to_string())),
+        Some(json) => Ok(serde_json::from_str(&json)?),
+        None => Err(Error::UpstreamUnavailable("Leaderboard not yet available".to_string())),
     }
 }
 
@@ -274,8 +456,8 @@ async fn read_last_update_daily(redis: &mut crate::RedisConnection<'_>) -> Optio
 async fn top_legend(
     State(pools): State<AppState>,
     Query(pagination): Query<Pagination>,
-) -> Result<Json<handlers::top::RankResponse>, (StatusCode, String)> {
-    let mut redis = pools.redis_pool.get().await.unwrap();
+) -> Result<Json<handlers::top::RankResponse>, Error> {
+    let mut redis = pools.redis_pool.get().await?;
     let entries = read_leaderboard(&mut redis, "leaderboard_legend").await?;
     let legend_keys: HashSet<(i64, i64)> = entries
         .iter()
@@ -286,9 +468,11 @@ async fn top_legend(
     let player_ids: HashSet<i64> = entries.iter().skip(offset).take(count)
         .filter_map(|e| e.player_id.parse::<i64>().ok()).collect();
     let last_update = read_last_update_hourly(&mut redis).await;
-    let mut db = pools.db_pool.get().await.unwrap();
-    let player_tags = db::get_tags_from_player_list(player_ids, &mut db).await.unwrap_or_default();
-    let mut response = build_rank_response(&entries, &legend_keys, &player_tags, offset, count);
+    let mut db = pools.db_pool.get().await?;
+    let player_tags = db::get_tags_from_player_list(player_ids.clone(), &mut db).await.unwrap_or_default();
+    let private_players = db::get_private_players(player_ids, &mut db).await?;
+    let previous_ranks = db::get_previous_ranks("legend", 0, &mut db).await.unwrap_or_default();
+    let mut response = build_rank_response(&entries, &legend_keys, &player_tags, &private_players, &previous_ranks, offset, count);
     response.last_update = last_update;
     Ok(Json(response))
 }
@@ -296,8 +480,8 @@ async fn top_legend(
 async fn top(
     State(pools): State<AppState>,
     Query(pagination): Query<Pagination>,
-) -> Result<Json<handlers::top::RankResponse>, (StatusCode, String)> {
-    let mut redis = pools.redis_pool.get().await.unwrap();
+) -> Result<Json<handlers::top::RankResponse>, Error> {
+    let mut redis = pools.redis_pool.get().await?;
     let entries = read_leaderboard(&mut redis, "leaderboard_all").await?;
     let legend_keys = get_legend_keys(&mut redis).await;
     let last_update = read_last_update_daily(&mut redis).await;
@@ -305,9 +489,11 @@ async fn top(
     let offset = pagination.offset.unwrap_or(0);
     let player_ids: HashSet<i64> = entries.iter().skip(offset).take(count)
         .filter_map(|e| e.player_id.parse::<i64>().ok()).collect();
-    let mut db = pools.db_pool.get().await.unwrap();
-    let player_tags = db::get_tags_from_player_list(player_ids, &mut db).await.unwrap_or_default();
-    let mut response = build_rank_response(&entries, &legend_keys, &player_tags, offset, count);
+    let mut db = pools.db_pool.get().await?;
+    let player_tags = db::get_tags_from_player_list(player_ids.clone(), &mut db).await.unwrap_or_default();
+    let private_players = db::get_private_players(player_ids, &mut db).await?;
+    let previous_ranks = db::get_previous_ranks("all", 0, &mut db).await.unwrap_or_default();
+    let mut response = build_rank_response(&entries, &legend_keys, &player_tags, &private_players, &previous_ranks, offset, count);
     response.last_update = last_update;
     Ok(Json(response))
 }
@@ -316,12 +502,12 @@ async fn top_char(
     State(pools): State<AppState>,
     Path(char_id): Path<String>,
     Query(pagination): Query<Pagination>,
-) -> Result<Json<handlers::top::RankResponse>, (StatusCode, String)> {
+) -> Result<Json<handlers::top::RankResponse>, Error> {
     let char_idx = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
         Some(id) => id as i64,
-        None => return Err((StatusCode::NOT_FOUND, "Character not found".to_string())),
+        None => return Err(Error::NotFound("Character not found".to_string())),
     };
-    let mut redis = pools.redis_pool.get().await.unwrap();
+    let mut redis = pools.redis_pool.get().await?;
     let key = format!("leaderboard_char_{}", char_idx);
     let entries = read_leaderboard(&mut redis, &key).await?;
     let legend_keys = get_legend_keys(&mut redis).await;
@@ -330,56 +516,240 @@ async fn top_char(
     let offset = pagination.offset.unwrap_or(0);
     let player_ids: HashSet<i64> = entries.iter().skip(offset).take(count)
         .filter_map(|e| e.player_id.parse::<i64>().ok()).collect();
-    let mut db = pools.db_pool.get().await.unwrap();
-    let player_tags = db::get_tags_from_player_list(player_ids, &mut db).await.unwrap_or_default();
-    let mut response = build_rank_response(&entries, &legend_keys, &player_tags, offset, count);
+    let mut db = pools.db_pool.get().await?;
+    let player_tags = db::get_tags_from_player_list(player_ids.clone(), &mut db).await.unwrap_or_default();
+    let private_players = db::get_private_players(player_ids, &mut db).await?;
+    let previous_ranks = db::get_previous_ranks("char", char_idx as i16, &mut db).await.unwrap_or_default();
+    let mut response = build_rank_response(&entries, &legend_keys, &player_tags, &private_players, &previous_ranks, offset, count);
     response.last_update = last_update;
     Ok(Json(response))
 }
 
-async fn characters() -> Result<Json<Vec<(&'static str, &'static str)>>, (StatusCode, String)> {
+/// Over-performers from the last daily run, players who went private since are hidden
+async fn top_performers(
+    State(pools): State<AppState>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<handlers::performance::TopPerformersResponse>, Error> {
+    let mut redis = pools.redis_pool.get().await?;
+    let performers = imdb::get_top_performers(&mut redis).await?;
+    let last_update = read_last_update_daily(&mut redis).await;
+    let count = pagination.count.unwrap_or(100);
+    let offset = pagination.offset.unwrap_or(0);
+    let mut performers: Vec<_> = performers.into_iter().skip(offset).take(count).collect();
+
+    let player_ids: HashSet<i64> = performers.iter().filter_map(|p| p.id.parse::<i64>().ok()).collect();
+    let mut db = pools.db_pool.get().await?;
+    let private_players = db::get_private_players(player_ids, &mut db).await?;
+    for performer in performers.iter_mut() {
+        if performer.id.parse::<i64>().is_ok_and(|id| private_players.contains(&id)) {
+            performer.id = "0".to_string();
+            performer.name = handlers::common::PRIVATE_PLAYER_NAME.to_string();
+        }
+    }
+
+    Ok(Json(handlers::performance::TopPerformersResponse { last_update, performers }))
+}
+
+#[derive(Deserialize)]
+struct ExportParams {
+    format: Option<String>,
+}
+
+/// Streams every game the player played, see `handlers::export::export_games`
+async fn export(
+    State(pools): State<AppState>,
+    Path(player_id): Path<i64>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, Error> {
+    let format = handlers::export::ExportFormat::from_param(params.format.as_deref())?;
+
+    let private = {
+        let mut db = pools.db_pool.get().await?;
+        db::is_player_private(player_id, &mut db).await?
+    };
+
+    let body = if private {
+        axum::body::Body::from(format.header().unwrap_or_default())
+    } else {
+        axum::body::Body::from_stream(handlers::export::export_games(pools.db_pool.clone(), player_id, format))
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    let disposition = format!("attachment; filename=\"{}.{}\"", player_id, format.extension());
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&disposition).map_err(|e| Error::Internal(e.to_string()))?,
+    );
+
+    Ok((headers, body).into_response())
+}
+
+#[derive(Deserialize)]
+struct SessionsParams {
+    days: Option<i64>,
+    /// Minutes without a game that end a session
+    gap: Option<i64>,
+}
+
+/// Sessions in the last `days` (30, at most 365), from at most `handlers::sessions::MAX_SESSION_GAMES` games
+async fn sessions(
+    State(pools): State<AppState>,
+    Path((player_id, char_id)): Path<(i64, String)>,
+    Query(params): Query<SessionsParams>,
+) -> Result<Json<handlers::sessions::SessionsResponse>, Error> {
+    let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
+        Some(id) => id as i16,
+        None => return Err(Error::NotFound("Character not found".to_string())),
+    };
+    let gap = params.gap.unwrap_or(handlers::player_history::SESSION_GAP_MINUTES);
+    if !(1..=24 * 60).contains(&gap) {
+        return Err(Error::BadInput("gap must be between 1 and 1440 minutes".to_string()));
+    }
+
+    let mut db = pools.db_pool.get().await?;
+
+    let games = if db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        vec![]
+    } else {
+        let since = chrono::Utc::now().naive_utc() - chrono::Duration::days(params.days.unwrap_or(30).clamp(1, 365));
+        db::get_games_since(player_id, char_id, since, handlers::sessions::MAX_SESSION_GAMES, &mut db).await?
+    };
+
+    Ok(Json(handlers::sessions::handle_get_sessions(player_id, &games, gap)))
+}
+
+#[derive(Deserialize)]
+struct RankHistoryParams {
+    days: Option<i64>,
+}
+
+async fn rank_history(
+    State(pools): State<AppState>,
+    Path((player_id, char_id)): Path<(i64, String)>,
+    Query(params): Query<RankHistoryParams>,
+) -> Result<Json<handlers::leaderboard_history::RankHistoryResponse>, Error> {
+    let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
+        Some(id) => id as i16,
+        None => return Err(Error::NotFound("Character not found".to_string())),
+    };
+
+    let mut db = pools.db_pool.get().await?;
+
+    if db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        return Ok(Json(handlers::leaderboard_history::handle_get_rank_history(vec![])));
+    }
+
+    let since = chrono::Utc::now().date_naive() - chrono::Duration::days(params.days.unwrap_or(90));
+    match db::get_rank_history(player_id, char_id, since, &mut db).await {
+        Ok(history) => Ok(Json(handlers::leaderboard_history::handle_get_rank_history(history))),
+        Err(e) => Err(e),
+    }
+}
+
+/// `board` is "all", "legend" or a character's short name
+async fn movers(
+    State(pools): State<AppState>,
+    Path(board): Path<String>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<handlers::leaderboard_history::MoversResponse>, Error> {
+    let (board, char_id) = match board.as_str() {
+        "all" => ("all", 0),
+        "legend" => ("legend", 0),
+        char_short => match CHAR_NAMES.iter().position(|(c, _)| *c == char_short) {
+            Some(id) => ("char", id as i16),
+            None => return Err(Error::NotFound("Leaderboard not found".to_string())),
+        },
+    };
+
+    let mut db = pools.db_pool.get().await?;
+
+    let dates = match db::get_latest_snapshot_dates(board, char_id, &mut db).await {
+        Ok(dates) => dates,
+        Err(e) => return Err(e),
+    };
+    let (current, previous) = match dates[..] {
+        [current, previous] => (
+            db::get_leaderboard_snapshot(board, char_id, current, &mut db).await,
+            db::get_leaderboard_snapshot(board, char_id, previous, &mut db).await,
+        ),
+        _ => return Err(Error::UpstreamUnavailable("Not enough snapshots yet".to_string())),
+    };
+    let (current, previous) = match (current, previous) {
+        (Ok(current), Ok(previous)) => (current, previous),
+        (Err(e), _) | (_, Err(e)) => return Err(e),
+    };
+
+    let player_ids: HashSet<i64> = current.iter().map(|s| s.player_id).collect();
+    let private_players = db::get_private_players(player_ids, &mut db).await?;
+
+    Ok(Json(handlers::leaderboard_history::handle_get_movers(
+        current,
+        previous,
+        private_players,
+        pagination.count.unwrap_or(10),
+    )))
+}
+
+/// Server-sent events, one `game` event per new game matching the filters.
+async fn live(
+    State(pools): State<AppState>,
+    Query(params): Query<handlers::live::LiveParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, std::convert::Infallible>>>, Error> {
+    let filter = handlers::live::LiveFilter::from_params(params)?;
+
+    let stream = pools.live.games().filter_map(move |game| {
+        let event = Some(game)
+            .filter(|game| filter.matches(game))
+            .and_then(|game| Event::default().event("game").json_data(game).ok())
+            .map(Ok);
+        std::future::ready(event)
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+async fn characters() -> Result<Json<Vec<(&'static str, &'static str)>>, Error> {
     Ok(Json(CHAR_NAMES.to_vec()))
 }
 
 async fn player_search(
     State(pools): State<AppState>,
     Query(search_params): Query<crate::handlers::search::SearchParams>,
-) -> Result<Json<crate::handlers::search::SearchResponse>, (StatusCode, String)> {
-    let mut db = pools.db_pool.get().await.unwrap();
+) -> Result<Json<crate::handlers::search::SearchResponse>, Error> {
+    let filters = handlers::search::SearchFilters::from_params(search_params)?;
+    let mut db = pools.db_pool.get().await?;
 
-    let data: Vec<(Player, PlayerRating)> = match db::find_player(search_params, &mut db).await {
+    let data = match db::find_player(&filters, &mut db).await {
         Ok(data) => data,
-        Err(e) => return Err((StatusCode::NOT_FOUND, e)),
+        Err(e) => return Err(e),
     };
 
-    //TODO tags
+    let player_ids: HashSet<i64> = data.iter().map(|p| p.id).collect();
+    let player_tags = db::get_tags_from_player_list(player_ids, &mut db).await.unwrap_or_default();
+    let mut redis = pools.redis_pool.get().await?;
+    let legend_keys = get_legend_keys(&mut redis).await;
 
-    match handlers::search::player_search(data).await {
+    match handlers::search::player_search(data, player_tags, legend_keys).await {
         Ok(response) => Ok(Json(response)),
-        Err(e) => Err((StatusCode::NOT_FOUND, e)),
+        Err(e) => Err(e),
     }
 }
 
 async fn rating_sync (
     State(pools): State<AppState>,
     Path(player_id): Path<i64>,
-) -> Result<Json<String>, (StatusCode, String)> {
-    if !std::fs::exists("token.txt").unwrap_or(false) {
-        return Err((
-            StatusCode::NOT_FOUND,
-            "GGST is not connected, patch?".to_string(),
-        ));
+) -> Result<Json<String>, Error> {
+    if !pools.ggst.is_connected().await {
+        return Err(Error::NotFound("GGST is not connected, patch?".to_string()));
     }
 
-    let mut redis = pools.redis_pool.get().await.unwrap();
+    let mut redis = pools.redis_pool.get().await?;
 
     // Check if player has synced in the last 60 seconds
     match imdb::check_rating_sync_rate_limit(player_id, &mut redis).await {
         Ok(true) => {
-            return Err((
-                StatusCode::TOO_MANY_REQUESTS,
-                "Rating sync is limited to once per minute".to_string(),
-            ));
+            return Err(Error::RateLimited("Rating sync is limited to once per minute".to_string()));
         },
         Ok(false) => {}, // Not rate limited, proceed
         Err(_) => {
@@ -390,15 +760,12 @@ async fn rating_sync (
     // Set rate limit key with 60 second expiry
     let _ = imdb::set_rating_sync_rate_limit(player_id, &mut redis).await;
 
-    let mut db = pools.db_pool.get().await.unwrap();
+    let mut db = pools.db_pool.get().await?;
 
-    let json_response = match ggst_api::get_player_stats(player_id.to_string()).await {
+    let json_response = match pools.ggst.get_player_stats(player_id.to_string()).await {
         Ok(json) => json,
         Err(e) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to get player stats: {}", e),
-            ));
+            return Err(Error::Internal(format!("Failed to get player stats: {}", e)));
         }
     };
 
@@ -411,49 +778,313 @@ async fn rating_sync (
                 Ok(Json(format!("Updated {} character ratings", count)))
             }
         },
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to update ratings: {}", e),
-        )),
+        Err(e) => Err(Error::Internal(format!("Failed to update ratings: {}", e))),
     }
 }
 
+async fn claim(
+    State(pools): State<AppState>,
+    Path(player_id): Path<i64>,
+) -> Result<Json<String>, Error> {
+    let mut redis = pools.redis_pool.get().await?;
+
+    if let Ok(true) = imdb::check_claim_rate_limit(player_id, &mut redis).await {
+        return Err(Error::RateLimited("Claim codes are limited to one per minute".to_string()));
+    }
+
+    let mut db = pools.db_pool.get().await?;
+
+    let code = handlers::claim::generate_claim_code();
+    let expiry = chrono::Utc::now().naive_utc()
+        + chrono::Duration::minutes(handlers::claim::CLAIM_CODE_TTL_MINUTES);
+
+    // Reissuing a pending code would let anyone keep the player from ever claiming
+    if !db::set_claim_code(player_id, &code, expiry, &mut db).await? {
+        return match db::get_claim_code_expiry(player_id, &mut db).await? {
+            Some(expiry) => Err(Error::RateLimited(format!(
+                "A claim code is already pending until {} UTC",
+                expiry.format("%Y-%m-%d %H:%M:%S")
+            ))),
+            None => Err(Error::NotFound("Player not found".to_string())),
+        };
+    }
+
+    let _ = imdb::set_claim_rate_limit(player_id, &mut redis).await;
+
+    Ok(Json(code))
+}
+
+async fn claim_poll(
+    State(pools): State<AppState>,
+    Path(player_id): Path<i64>,
+) -> Result<Json<String>, Error> {
+    if !pools.ggst.is_connected().await {
+        return Err(Error::UpstreamUnavailable("GGST is not connected, patch?".to_string()));
+    }
+
+    let mut redis = pools.redis_pool.get().await?;
+
+    if let Ok(true) = imdb::check_claim_poll_rate_limit(player_id, &mut redis).await {
+        return Err(Error::RateLimited("Claim polling is limited to once every 10 seconds".to_string()));
+    }
+
+    let _ = imdb::set_claim_poll_rate_limit(player_id, &mut redis).await;
+
+    let mut db = pools.db_pool.get().await?;
+
+    let code = match db::get_claim_code(player_id, &mut db).await {
+        Ok(code) => code,
+        Err(e) => return Err(e),
+    };
+
+    // Always ask GGST directly, the cached comment is up to a day old
+    let comment = match pools.ggst.get_player_comment(player_id.to_string()).await {
+        Ok(comment) => comment,
+        Err(e) => return Err(e),
+    };
+    let _ = imdb::set_free_comment(player_id, &comment, &mut redis).await;
+
+    if !handlers::claim::comment_contains_code(&comment, &code) {
+        return Ok(Json("false".to_string()));
+    }
+
+    let api_key = match db::get_player_api_key(player_id, &mut db).await {
+        Ok(api_key) => api_key,
+        Err(e) => return Err(e),
+    };
+
+    // The code is single use
+    db::clear_claim_code(player_id, &mut db).await?;
+
+    Ok(Json(api_key))
+}
+
 #[derive(Serialize)]
 struct SettingsResponse {
     #[serde(serialize_with = "serialize_i64_as_string")]
     id: i64,
     name: String,
+    status: String,
+    /// Name and id shown in the public dataset dump instead of an anonymous id
+    dataset_opt_in: bool,
 }
 async fn settings(
     State(pools): State<AppState>,
     Path(key): Path<String>,
-) -> Result<Json<SettingsResponse>, (StatusCode, String)> {
-    let mut db = pools.db_pool.get().await.unwrap();
+) -> Result<Json<SettingsResponse>, Error> {
+    let mut db = pools.db_pool.get().await?;
 
     let player_rating = match db::get_player_id_and_name_using_key(key, &mut db).await {
         Ok(player_rating) => player_rating,
-        Err(_) => {
-            return Err((StatusCode::NOT_FOUND, "Player not found".to_string()));
-        }
+        Err(e) => return Err(e),
     };
 
+    let dataset_opt_in = db::is_dataset_opt_in(player_rating.0, &mut db).await?;
+
     Ok(Json(SettingsResponse {
         id: player_rating.0,
         name: player_rating.1,
+        status: if player_rating.2 { "Private" } else { "Public" }.to_string(),
+        dataset_opt_in,
     }))
 }
 
+async fn toggle_private(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<String>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    match db::toggle_private(key, &mut db).await {
+        Ok(_) => Ok(Json("true".to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+async fn toggle_dataset_opt_in(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<String>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    match db::toggle_dataset_opt_in(key, &mut db).await {
+        Ok(_) => Ok(Json("true".to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+async fn follows(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<Vec<handlers::webhook::FollowResponse>>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    let (player_id, _, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(e) => return Err(e),
+    };
+
+    match db::get_follows(player_id, &mut db).await {
+        //Keep the id of private players so they can still be unfollowed
+        Ok(follows) => Ok(Json(
+            follows
+                .into_iter()
+                .map(|(id, name, private)| handlers::webhook::FollowResponse {
+                    id,
+                    name: if private { handlers::common::PRIVATE_PLAYER_NAME.to_string() } else { name },
+                })
+                .collect(),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+async fn follow(
+    State(pools): State<AppState>,
+    Path((key, followed_id)): Path<(String, i64)>,
+) -> Result<Json<String>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    let (player_id, _, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(e) => return Err(e),
+    };
+
+    if !db::player_exists(&mut db, followed_id).await.unwrap_or(false)
+        || db::is_player_private(followed_id, &mut db).await.or_else(unknown_is_public)?
+    {
+        return Err(Error::NotFound("Followed player not found".to_string()));
+    }
+
+    match db::add_follow(player_id, followed_id, &mut db).await {
+        Ok(_) => Ok(Json("true".to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+async fn unfollow(
+    State(pools): State<AppState>,
+    Path((key, followed_id)): Path<(String, i64)>,
+) -> Result<Json<String>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    let (player_id, _, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(e) => return Err(e),
+    };
+
+    match db::remove_follow(player_id, followed_id, &mut db).await {
+        Ok(true) => Ok(Json("true".to_string())),
+        Ok(false) => Err(Error::NotFound("Not following this player".to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+async fn get_webhook(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<handlers::webhook::WebhookResponse>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    let (player_id, _, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(e) => return Err(e),
+    };
+
+    match db::get_webhook(player_id, &mut db).await {
+        Ok(Some(webhook)) => Ok(Json(handlers::webhook::WebhookResponse {
+            url: webhook.url,
+            secret: webhook.secret,
+        })),
+        Ok(None) => Err(Error::NotFound("Webhook not set".to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+async fn set_webhook(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+    Json(params): Json<handlers::webhook::WebhookParams>,
+) -> Result<Json<handlers::webhook::WebhookResponse>, Error> {
+    handlers::webhook::resolve_url(&params.url).await?;
+
+    let mut db = pools.db_pool.get().await?;
+
+    let (player_id, _, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(e) => return Err(e),
+    };
+
+    let webhook = models::Webhook {
+        player_id,
+        url: params.url,
+        secret: handlers::webhook::generate_secret(),
+    };
+
+    match db::set_webhook(webhook.clone(), &mut db).await {
+        Ok(_) => Ok(Json(handlers::webhook::WebhookResponse {
+            url: webhook.url,
+            secret: webhook.secret,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+async fn delete_webhook(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<String>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    let (player_id, _, _) = match db::get_player_id_and_name_using_key(key, &mut db).await {
+        Ok(player) => player,
+        Err(e) => return Err(e),
+    };
+
+    match db::delete_webhook(player_id, &mut db).await {
+        Ok(true) => Ok(Json("true".to_string())),
+        Ok(false) => Err(Error::NotFound("Webhook not set".to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+async fn jobs(
+    State(pools): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<Vec<handlers::jobs::JobResponse>>, Error> {
+    handlers::jobs::check_admin_key(&key)?;
+
+    let mut db = pools.db_pool.get().await?;
+    let mut redis = pools.redis_pool.get().await?;
+
+    Ok(Json(handlers::jobs::handle_get_jobs(&mut db, &mut redis).await?))
+}
+
+async fn trigger_job(
+    State(pools): State<AppState>,
+    Path((key, name)): Path<(String, String)>,
+) -> Result<(StatusCode, Json<String>), Error> {
+    handlers::jobs::check_admin_key(&key)?;
+
+    let mut redis = pools.redis_pool.get().await?;
+    handlers::jobs::handle_trigger_job(&name, &mut redis).await?;
+
+    Ok((StatusCode::ACCEPTED, Json("true".to_string())))
+}
+
 async fn alias(
     State(pools): State<AppState>,
     Path(player_id): Path<i64>,
-) -> Result<Json<Vec<String>>, (StatusCode, String)> {
-    let mut db = pools.db_pool.get().await.unwrap();
+) -> Result<Json<Vec<String>>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    if db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        return Ok(Json(vec![]));
+    }
 
     let alias: Vec<String> = match db::get_aliases(player_id, &mut db).await {
         Ok(alias) => alias,
-        Err(_) => {
-            return Err((StatusCode::NOT_FOUND, "Player not found".to_string()));
-        }
+        Err(e) => return Err(e),
     };
 
     Ok(Json(alias))
@@ -469,24 +1100,56 @@ struct RatingsParams {
     pre_vanquisher: Option<bool>,
 }
 
+//The router can't match a suffix inside a segment, so :duration is either "100" or "100.png"
 async fn ratings(
     State(pools): State<AppState>,
-    Path((player_id, char_id, duration)): Path<(i64, String, i32)>,
+    Path((player_id, char_id, duration)): Path<(i64, String, String)>,
     Query(params): Query<RatingsParams>,
-) -> Result<Json<Vec<RatingsResponse>>, (StatusCode, String)> {
+) -> Result<Response, Error> {
     let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
         Some(id) => id as i16,
         None => {
-            return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
+            return Err(Error::NotFound("Character not found".to_string()));
         }
     };
 
-    let mut db = pools.db_pool.get().await.unwrap();
+    let (duration, png) = match duration.strip_suffix(".png") {
+        Some(duration) => (duration, true),
+        None => (duration.as_str(), false),
+    };
+    let duration = match duration.parse::<i32>() {
+        Ok(duration) => duration,
+        Err(_) => return Err(Error::BadInput("Invalid duration".to_string())),
+    };
+
+    if png {
+        return rating_chart(pools, player_id, char_id, duration, params.pre_vanquisher.unwrap_or(false))
+            .await
+            .map(|r| r.into_response());
+    }
+
+    ratings_json(pools, player_id, char_id, duration, params.pre_vanquisher.unwrap_or(false))
+        .await
+        .map(|r| r.into_response())
+}
+
+async fn ratings_json(
+    pools: AppState,
+    player_id: i64,
+    char_id: i16,
+    duration: i32,
+    pre_vanquisher: bool,
+) -> Result<Json<Vec<RatingsResponse>>, Error> {
+    let mut db = pools.db_pool.get().await?;
+
+    if db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        return Ok(Json(vec![]));
+    }
 
-    let results = match db::get_ratings(player_id, char_id, duration, params.pre_vanquisher.unwrap_or(false), &mut db).await {
+    let results = match db::get_ratings(player_id, char_id, duration, pre_vanquisher, &mut db).await {
         Ok(results) => results,
         Err(e) => {
-            return Err((StatusCode::NOT_FOUND, e));
+            return Err(e);
         }
     };
 
@@ -501,23 +1164,77 @@ async fn ratings(
     Ok(Json(ratings))
 }
 
+async fn rating_chart(
+    pools: AppState,
+    player_id: i64,
+    char_id: i16,
+    duration: i32,
+    pre_vanquisher: bool,
+) -> Result<impl IntoResponse, Error> {
+    let mut db = pools.db_pool.get().await?;
+    let mut redis = pools.redis_pool.get().await?;
+
+    if db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        return Err(Error::NotFound("Player is private".to_string()));
+    }
+
+    let png = match crate::imdb::get_rating_chart(player_id, char_id, duration, pre_vanquisher, &mut redis).await {
+        Ok(chart) => match base64_url::decode(&chart) {
+            Ok(png) => axum::body::Bytes::from(png),
+            Err(e) => return Err(Error::Internal(e.to_string())),
+        },
+        Err(_) => {
+            let results = match db::get_ratings(player_id, char_id, duration, pre_vanquisher, &mut db).await {
+                Ok(results) => results,
+                Err(e) => {
+                    return Err(e);
+                }
+            };
+
+            //Newest first from the db, the chart reads left to right
+            let ratings: Vec<i64> = results.iter().rev().map(|r| r.value).collect();
+            let png = crate::handlers::rating_chart::render_rating_chart(&ratings);
+
+            let _ = crate::imdb::set_rating_chart(
+                player_id,
+                char_id,
+                duration,
+                pre_vanquisher,
+                &base64_url::encode(&png),
+                &mut redis,
+            )
+            .await;
+            png
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
+
+    Ok((headers, png))
+}
+
 async fn player_matchups(
     State(pools): State<AppState>,
     Path((player_id, char_id, duration)): Path<(i64, String, i32)>,
-) -> Result<Json<MatchupCharResponse>, (StatusCode, String)> {
+) -> Result<Json<MatchupCharResponse>, Error> {
     let char_id = match CHAR_NAMES.iter().position(|(c, _)| *c == char_id) {
         Some(id) => id as i16,
         None => {
-            return Err((StatusCode::NOT_FOUND, "Character not found".to_string()));
+            return Err(Error::NotFound("Character not found".to_string()));
         }
     };
 
-    let mut db = pools.db_pool.get().await.unwrap();
+    let mut db = pools.db_pool.get().await?;
 
-    let char_matchup = match db::get_matchups(player_id, char_id, duration, &mut db).await {
-        Ok(char_matchup) => char_matchup,
-        Err(e) => {
-            return Err((StatusCode::NOT_FOUND, e));
+    let char_matchup = if db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        vec![]
+    } else {
+        match db::get_matchups(player_id, char_id, duration, &mut db).await {
+            Ok(char_matchup) => char_matchup,
+            Err(e) => {
+                return Err(e);
+            }
         }
     };
 
@@ -550,13 +1267,13 @@ struct StatsResponse {
     one_day_players: i64,
     one_hour_players: i64,
 }
-async fn stats(State(pools): State<AppState>) -> Result<Json<StatsResponse>, (StatusCode, String)> {
-    let mut redis = pools.redis_pool.get().await.unwrap();
+async fn stats(State(pools): State<AppState>) -> Result<Json<StatsResponse>, Error> {
+    let mut redis = pools.redis_pool.get().await?;
 
     let stats = match imdb::get_stats(&mut redis).await {
         Ok(stats) => stats,
         Err(e) => {
-            return Err((StatusCode::NOT_FOUND, e));
+            return Err(e);
         }
     };
 
@@ -604,13 +1321,13 @@ struct PopularityResult {
 }
 async fn popularity(
     State(pools): State<AppState>,
-) -> Result<Json<PopularityResult>, (StatusCode, String)> {
-    let mut redis = pools.redis_pool.get().await.unwrap();
+) -> Result<Json<PopularityResult>, Error> {
+    let mut redis = pools.redis_pool.get().await?;
 
     let results = match imdb::get_popularity(&mut redis).await {
         Ok(results) => results,
         Err(e) => {
-            return Err((StatusCode::NOT_FOUND, e));
+            return Err(e);
         }
     };
 
@@ -660,27 +1377,27 @@ struct MatchupEntry {
 
 async fn matchups(
     State(pools): State<AppState>,
-) -> Result<Json<MatchupResponse>, (StatusCode, String)> {
-    let mut redis = pools.redis_pool.get().await.unwrap();
+) -> Result<Json<MatchupResponse>, Error> {
+    let mut redis = pools.redis_pool.get().await?;
 
     let matchups = match imdb::get_matchups(&mut redis).await {
         Ok(matchups) => matchups,
         Err(e) => {
-            return Err((StatusCode::NOT_FOUND, e));
+            return Err(e);
         }
     };
 
     let data_all = match matchups.matchups.get("matchup") {
         Some(data) => data,
         None => {
-            return Err((StatusCode::NOT_FOUND, "Matchup not found".to_string()));
+            return Err(Error::NotFound("Matchup not found".to_string()));
         }
     };
 
     let data_vanq = match matchups.matchups.get("matchup_vanq") {
         Some(data) => data,
         None => {
-            return Err((StatusCode::NOT_FOUND, "Matchup vanq not found".to_string()));
+            return Err(Error::NotFound("Matchup vanq not found".to_string()));
         }
     };
 
@@ -738,13 +1455,13 @@ struct Supporter {
 }
 async fn supporters(
     State(pools): State<AppState>,
-) -> Result<Json<Vec<Supporter>>, (StatusCode, String)> {
-    let mut db = pools.db_pool.get().await.unwrap();
+) -> Result<Json<Vec<Supporter>>, Error> {
+    let mut db = pools.db_pool.get().await?;
 
     let supporters: Vec<(i64, String)> = match db::get_supporters(&mut db).await {
         Ok(supporters) => supporters,
         Err(e) => {
-            return Err((StatusCode::NOT_FOUND, e));
+            return Err(e);
         }
     };
 
@@ -790,13 +1507,13 @@ struct DistributionEntry {
 }
 async fn distribution(
     State(pools): State<AppState>,
-) -> Result<Json<DistributionResponse>, (StatusCode, String)> {
-    let mut redis = pools.redis_pool.get().await.unwrap();
+) -> Result<Json<DistributionResponse>, Error> {
+    let mut redis = pools.redis_pool.get().await?;
 
     let (ts, distrubition_entry) = match imdb::get_distribution(&mut redis).await {
         Ok(data) => data,
         Err(e) => {
-            return Err((StatusCode::NOT_FOUND, e));
+            return Err(e);
         }
     };
 
@@ -806,17 +1523,59 @@ async fn distribution(
     }))
 }
 
-async fn health(State(pools): State<AppState>) -> Result<String, (StatusCode, String)> {
-    let mut redis = pools.redis_pool.get().await.unwrap();
+#[derive(Deserialize)]
+struct HealthParams {
+    format: Option<String>,
+}
+
+/// Plain text for uptime monitors, unlike the JSON errors everywhere else.
+/// `?format=json` reports every component separately.
+async fn health(State(pools): State<AppState>, Query(params): Query<HealthParams>) -> Response {
+    if params.format.as_deref() == Some("json") {
+        let health = handlers::health::handle_get_health(&pools).await;
+        return (health.status_code(), Json(health)).into_response();
+    }
+
+    health_text(&pools).await.into_response()
+}
+
+/// Just the status, monitors match the body. The JSON report has the API version.
+async fn health_text(pools: &AppState) -> Result<String, (StatusCode, String)> {
+    let mut redis = pools
+        .redis_pool
+        .get()
+        .await
+        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, Error::from(e).to_string()))?;
+
+    health_status(&mut redis).await
+}
+
+async fn health_status(redis: &mut RedisConnection<'_>) -> Result<String, (StatusCode, String)> {
     let now = chrono::Utc::now().timestamp();
 
-    let last_update_daily = imdb::get_last_update_daily(&mut redis).await.unwrap();
+    if let Some(auth) = imdb::get_auth_status(redis).await.unwrap_or(None)
+        && !auth.connected
+    {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "GGST not logged in ({} failed logins): {}, next attempt at {}",
+                auth.failed_logins,
+                auth.last_error.as_deref().unwrap_or("no error"),
+                auth.next_login.as_deref().unwrap_or("next pull"),
+            ),
+        ));
+    }
+
+    let last_update_daily = imdb::get_last_update_daily(redis)
+        .await
+        .map_err(|e| (e.status(), e.to_string()))?;
 
     // If the daily task ran very recently (within 10 min), latest_game_time may be
     // temporarily absent — treat as graceful/running state, not an error.
     let daily_ran_recently = now - last_update_daily.and_utc().timestamp() < 600;
 
-    let latest_game_time = match imdb::get_latest_game_time(&mut redis).await {
+    let latest_game_time = match imdb::get_latest_game_time(redis).await {
         Ok(t) => Some(t),
         Err(_) => {
             if daily_ran_recently || now - 86400 > last_update_daily.and_utc().timestamp() {
@@ -850,34 +1609,34 @@ async fn health(State(pools): State<AppState>) -> Result<String, (StatusCode, St
 }
 
 async fn comment(Path(player_id): Path<i64>, State(pools): State<AppState>) -> impl IntoResponse {
-    //If token.txt does not exist, return 503
-    if !std::fs::exists("token.txt").unwrap_or(false) {
-        return Err((
-            StatusCode::SERVICE_UNAVAILABLE,
-            "GGST is not connected, patch?".to_string(),
-        ));
+    if !pools.ggst.is_connected().await {
+        return Err(Error::UpstreamUnavailable("GGST is not connected, patch?".to_string()));
     }
 
-    let mut db = pools.db_pool.get().await.unwrap();
-    let mut redis = pools.redis_pool.get().await.unwrap();
+    let mut db = pools.db_pool.get().await?;
+    let mut redis = pools.redis_pool.get().await?;
 
     let exists = match crate::db::player_exists(&mut db, player_id).await {
         Ok(e) => e,
-        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+        Err(e) => return Err(e),
     };
 
     if !exists {
-        return Err((StatusCode::NOT_FOUND, "Player not found".to_string()));
+        return Err(Error::NotFound("Player not found".to_string()));
+    }
+
+    if crate::db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        return Err(Error::NotFound("Player is private".to_string()));
     }
 
     let comment = match crate::imdb::get_free_comment(player_id, &mut redis).await {
         Ok(comment) => comment,
-        Err(_) => match crate::ggst_api::get_player_comment(player_id.to_string()).await {
+        Err(_) => match pools.ggst.get_player_comment(player_id.to_string()).await {
             Ok(comment) => {
                 let _ = crate::imdb::set_free_comment(player_id, &comment, &mut redis).await;
                 comment
             }
-            Err(e) => return Err((StatusCode::SERVICE_UNAVAILABLE, e)),
+            Err(e) => return Err(e),
         },
     };
 
@@ -885,34 +1644,34 @@ async fn comment(Path(player_id): Path<i64>, State(pools): State<AppState>) -> i
 }
 
 async fn avatar(Path(player_id): Path<i64>, State(pools): State<AppState>) -> impl IntoResponse {
-    //If token.txt does not exist, return 503
-    if !std::fs::exists("token.txt").unwrap_or(false) {
-        return Err((
-            StatusCode::SERVICE_UNAVAILABLE,
-            "GGST is not connected, patch?".to_string(),
-        ));
+    if !pools.ggst.is_connected().await {
+        return Err(Error::UpstreamUnavailable("GGST is not connected, patch?".to_string()));
     }
 
-    let mut db = pools.db_pool.get().await.unwrap();
-    let mut redis = pools.redis_pool.get().await.unwrap();
+    let mut db = pools.db_pool.get().await?;
+    let mut redis = pools.redis_pool.get().await?;
 
     let exists = match crate::db::player_exists(&mut db, player_id).await {
         Ok(e) => e,
-        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+        Err(e) => return Err(e),
     };
 
     if !exists {
-        return Err((StatusCode::NOT_FOUND, "Player not found".to_string()));
+        return Err(Error::NotFound("Player not found".to_string()));
+    }
+
+    if crate::db::is_player_private(player_id, &mut db).await.or_else(unknown_is_public)? {
+        return Err(Error::NotFound("Player is private".to_string()));
     }
 
     let png = match crate::imdb::get_avatar(player_id, &mut redis).await {
         Ok(avatar) => avatar,
-        Err(_) => match crate::ggst_api::get_player_avatar(player_id.to_string()).await {
+        Err(_) => match pools.ggst.get_player_avatar(player_id.to_string()).await {
             Ok(png) => {
                 let _ = crate::imdb::set_avatar(player_id, &png, &mut redis).await;
                 png
             }
-            Err(e) => return Err((StatusCode::SERVICE_UNAVAILABLE, e)),
+            Err(e) => return Err(e),
         },
     };
 
@@ -925,6 +1684,45 @@ async fn avatar(Path(player_id): Path<i64>, State(pools): State<AppState>) -> im
     Ok((headers, output))
 }
 
+#[derive(Deserialize)]
+struct CalcRatingParams {
+    rating_a: f64,
+    drift_a: f64,
+    rating_b: f64,
+    drift_b: f64,
+    a_wins: bool,
+}
+#[derive(Serialize)]
+struct CalcRatingResponse {
+    rating_a_new: f64,
+    drift_a_new: f64,
+    rating_b_new: f64,
+    drift_b_new: f64,
+    win_prob: f64,
+}
+async fn calc_rating(
+    Query(params): Query<CalcRatingParams>,
+) -> Result<Json<CalcRatingResponse>, Error> {
+    let change = match rating::calc_rating(
+        params.rating_a,
+        params.drift_a,
+        params.rating_b,
+        params.drift_b,
+        params.a_wins,
+    ) {
+        Ok(change) => change,
+        Err(e) => return Err(Error::BadInput(e)),
+    };
+
+    Ok(Json(CalcRatingResponse {
+        rating_a_new: change.rating_a_new,
+        drift_a_new: change.drift_a_new,
+        rating_b_new: change.rating_b_new,
+        drift_b_new: change.drift_b_new,
+        win_prob: change.win_prob,
+    }))
+}
+
 fn init_tracing(prefix: &str) -> WorkerGuard {
     // Create a rolling file appender
     let file_appender = tracing_appender::rolling::RollingFileAppender::new(
@@ -992,13 +1790,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let pool = bb8::Pool::builder().build(config).await?;
 
     //Redis
-    let manager =
-        RedisConnectionManager::new(std::env::var("REDIS_URL").expect("REDIS_URL")).unwrap();
+    let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL");
+    let manager = RedisConnectionManager::new(redis_url.as_str()).unwrap();
     let redis_pool = bb8::Pool::builder().build(manager).await.unwrap();
 
     let state = AppState {
         db_pool: pool,
         redis_pool,
+        ggst: ggst_api::from_env(true),
+        live: handlers::live::LiveFeed::new(redis_url),
     };
 
     let args = std::env::args().skip(1).collect::<Vec<_>>();
@@ -1026,39 +1826,116 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let state = AppState {
                 db_pool: pool,
                 redis_pool,
+                ..state
             };
 
+            if let Ok(addr) = std::env::var("METRICS_LISTEN_ADDR") {
+                let metrics_state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = metrics::serve(metrics_state, addr).await {
+                        tracing::error!("Metrics listener failed: {e}");
+                    }
+                });
+            }
+
             pull::pull_and_update_continuous(state).await;
         }
         Some("hourly") => {
             tracing_subscriber::fmt()
                 .with_max_level(tracing::Level::INFO)
                 .init();
-            pull::do_hourly_update_once(state).await
+            scheduler::run_now(state, scheduler::HOURLY_JOBS).await
         }
         Some("daily") => {
             tracing_subscriber::fmt()
                 .with_max_level(tracing::Level::INFO)
                 .init();
-            pull::do_daily_update_once(state).await
+            scheduler::run_now(state, scheduler::DAILY_JOBS).await
+        }
+        //Reads replay pages back to the last recorded gap, for filling in after downtime. Optional page limit.
+        Some("backfill") => {
+            tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::INFO)
+                .init();
+            let max_pages = args.get(1).and_then(|p| p.parse().ok());
+            pull::backfill(state, max_pages).await?;
+        }
+        //Writes the public dataset into a directory, only the games since the last dump unless "full"
+        Some("dump") => {
+            tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::INFO)
+                .init();
+            let Some(root) = args.get(1) else {
+                eprintln!("Usage: dump <directory> [full]");
+                std::process::exit(1);
+            };
+            let key = std::env::var("DUMP_KEY").expect("DUMP_KEY");
+            let full = args.get(2).map(|a| a.as_str()) == Some("full");
+            dump::dump(&state, std::path::Path::new(root), &key, full).await?;
+        }
+        //Writes every game of a player to stdout, csv (default) or ndjson
+        Some("export") => {
+            use std::io::Write;
+
+            let Some(player_id) = args.get(1).and_then(|id| id.parse::<i64>().ok()) else {
+                eprintln!("Usage: export <player_id> [csv|ndjson]");
+                std::process::exit(1);
+            };
+            let format = handlers::export::ExportFormat::from_param(args.get(2).map(|f| f.as_str()))?;
+
+            let mut stdout = std::io::stdout().lock();
+            let mut rows = std::pin::pin!(handlers::export::export_games(state.db_pool.clone(), player_id, format));
+            while let Some(chunk) = rows.next().await {
+                stdout.write_all(chunk?.as_bytes())?;
+            }
+        }
+        //Serves link previews (Open Graph tags) to Discord, Twitter, etc.
+        Some("embed") => {
+            let _guard = init_tracing("embed");
+            embed::serve(state).await?;
         }
         _ => {
             // No args, run the web server
             let _guard = init_tracing("web");
 
+            // Only the puller logs in, a login per web request would get the account flagged
+            let state = AppState {
+                ggst: ggst_api::from_env(false),
+                ..state
+            };
+
+            // One subscription for every /api/live client
+            tokio::spawn(state.live.clone().run());
+
             let app = Router::new()
                 .route("/api/player/:id", get(player))
                 .route(
                     "/api/player/:player_id/:char_id/history",
                     get(player_history),
                 )
+                .route("/api/player/:player_id/:char_id/sessions", get(sessions))
+                .route("/api/player/:id/export", get(export))
                 .route("/api/top_legend", get(top_legend))
                 .route("/api/top", get(top))
                 .route("/api/top_char/:char_id", get(top_char))
+                .route("/api/top_performers", get(top_performers))
+                .route("/api/rank_history/:player_id/:char_id", get(rank_history))
+                .route("/api/movers/:board", get(movers))
+                .route("/api/live", get(live))
+                .route("/api/h2h/:player_a/:player_b", get(h2h))
                 .route("/api/characters", get(characters))
                 .route("/api/player/search", get(player_search))
                 .route("/api/rating_sync/:player_id", get(rating_sync))
+                .route("/api/claim/:player_id", get(claim))
+                .route("/api/claim/poll/:player_id", get(claim_poll))
                 .route("/api/settings/:key", get(settings))
+                .route("/api/toggle_private/:key", get(toggle_private))
+                .route("/api/toggle_dataset_opt_in/:key", get(toggle_dataset_opt_in))
+                .route("/api/follows/:key", get(follows))
+                .route("/api/follows/:key/:player_id", put(follow).delete(unfollow))
+                .route("/api/webhook/:key", get(get_webhook).put(set_webhook).delete(delete_webhook))
+                .route("/api/admin/:key/jobs", get(jobs))
+                .route("/api/admin/:key/jobs/:name", post(trigger_job))
                 .route("/api/alias/:player_id", get(alias))
                 .route("/api/ratings/:player_id/:char_id/:duration", get(ratings))
                 .route("/api/stats", get(stats))
@@ -1071,8 +1948,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .route("/api/supporters", get(supporters))
                 .route("/api/distribution", get(distribution))
                 .route("/api/health", get(health))
+                .route("/api/calc_rating", get(calc_rating))
                 .route("/api/avatar/:player_id", get(avatar))
                 .route("/api/comment/:player_id", get(comment))
+                .route_layer(axum::middleware::from_fn(metrics::track_requests))
+                .route("/metrics", get(metrics::metrics))
                 .with_state(state);
 
             let app = if cfg!(debug_assertions) {
@@ -1088,3 +1968,244 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// State on TEST_DATABASE_URL and TEST_REDIS_URL, a migrated scratch database and redis.
+/// Tests that need them return early when they aren't set.
+#[cfg(test)]
+async fn test_state(ggst: Arc<dyn ggst_api::GgstApi>) -> Option<AppState> {
+    let (Ok(database_url), Ok(redis_url)) = (std::env::var("TEST_DATABASE_URL"), std::env::var("TEST_REDIS_URL")) else {
+        eprintln!("TEST_DATABASE_URL or TEST_REDIS_URL not set, skipping");
+        return None;
+    };
+
+    let config = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(database_url);
+    let db_pool = bb8::Pool::builder().build(config).await.unwrap();
+    let redis_pool = bb8::Pool::builder()
+        .build(RedisConnectionManager::new(redis_url.as_str()).unwrap())
+        .await
+        .unwrap();
+
+    Some(AppState {
+        db_pool,
+        redis_pool,
+        ggst,
+        live: handlers::live::LiveFeed::new(redis_url),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::prelude::*;
+    use diesel_async::RunQueryDsl;
+    use ggst_api::{fixtures, FixtureApi};
+
+    #[tokio::test]
+    async fn rating_sync_updates_ratings_from_player_stats() {
+        let dir = fixtures::dir("rating-sync");
+        // Fresh player every run, syncs are rate limited per player
+        let player_id = 810_000_000_000_000 + rand::random::<u32>() as i64;
+        fixtures::write_player_stats(&dir, player_id, r#"{"SOL_MasterRatingPt": 1500, "KYK_RankMatchRatingPt": 32000}"#);
+
+        let Some(state) = test_state(Arc::new(FixtureApi::new(dir))).await else {
+            return;
+        };
+        let mut db = state.db_pool.get().await.unwrap();
+
+        diesel::insert_into(schema::players::table)
+            .values(&Player {
+                id: player_id,
+                name: "Fixture".to_string(),
+                platform: 3,
+                api_key: None,
+                rcode_check_code: None,
+                private: false,
+            })
+            .execute(&mut db)
+            .await
+            .unwrap();
+        diesel::insert_into(schema::player_ratings::table)
+            .values(&vec![
+                PlayerRating { id: player_id, char_id: 0, value: 10001000 },
+                PlayerRating { id: player_id, char_id: 1, value: 30000 },
+            ])
+            .execute(&mut db)
+            .await
+            .unwrap();
+
+        let result = rating_sync(State(state.clone()), Path(player_id)).await;
+
+        let ratings: Vec<(i16, i64)> = schema::player_ratings::table
+            .select((schema::player_ratings::char_id, schema::player_ratings::value))
+            .filter(schema::player_ratings::id.eq(player_id))
+            .order(schema::player_ratings::char_id)
+            .load(&mut db)
+            .await
+            .unwrap();
+
+        diesel::delete(schema::player_ratings::table.filter(schema::player_ratings::id.eq(player_id)))
+            .execute(&mut db)
+            .await
+            .unwrap();
+        diesel::delete(schema::players::table.filter(schema::players::id.eq(player_id)))
+            .execute(&mut db)
+            .await
+            .unwrap();
+
+        assert_eq!(result.unwrap().0, "Updated 2 character ratings");
+        assert_eq!(ratings, vec![(0, 10001500), (1, 32000)]);
+
+        // Once per minute
+        let again = rating_sync(State(state.clone()), Path(player_id)).await;
+        assert_eq!(again.unwrap_err(), Error::RateLimited("Rating sync is limited to once per minute".to_string()));
+    }
+
+    #[tokio::test]
+    async fn claim_keeps_a_pending_code() {
+        let player_id = 820_000_000_000_000 + rand::random::<u32>() as i64;
+
+        let Some(state) = test_state(Arc::new(FixtureApi::new(fixtures::dir("claim")))).await else {
+            return;
+        };
+        let mut db = state.db_pool.get().await.unwrap();
+        let mut redis = state.redis_pool.get().await.unwrap();
+
+        diesel::insert_into(schema::players::table)
+            .values(&Player {
+                id: player_id,
+                name: "Fixture".to_string(),
+                platform: 3,
+                api_key: None,
+                rcode_check_code: None,
+                private: false,
+            })
+            .execute(&mut db)
+            .await
+            .unwrap();
+
+        let first = claim(State(state.clone()), Path(player_id)).await;
+        // Past the once a minute limit
+        bb8_redis::redis::cmd("DEL")
+            .arg(format!("claim:{}", player_id))
+            .query_async::<()>(&mut *redis)
+            .await
+            .unwrap();
+        let again = claim(State(state.clone()), Path(player_id)).await;
+        let code = db::get_claim_code(player_id, &mut db).await;
+        let expiry = db::get_claim_code_expiry(player_id, &mut db).await.unwrap().unwrap();
+
+        diesel::delete(schema::players::table.filter(schema::players::id.eq(player_id)))
+            .execute(&mut db)
+            .await
+            .unwrap();
+
+        assert_eq!(code, Ok(first.unwrap().0));
+        assert_eq!(
+            again.unwrap_err(),
+            Error::RateLimited(format!(
+                "A claim code is already pending until {} UTC",
+                expiry.format("%Y-%m-%d %H:%M:%S")
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn games_pages_have_the_ratings_after_each_game() {
+        let player_id = 830_000_000_000_000 + rand::random::<u32>() as i64;
+        let opponent_id = player_id + 1;
+
+        let Some(state) = test_state(Arc::new(FixtureApi::new(fixtures::dir("games")))).await else {
+            return;
+        };
+        let mut db = state.db_pool.get().await.unwrap();
+
+        let player = |id| Player {
+            id,
+            name: "Fixture".to_string(),
+            platform: 3,
+            api_key: None,
+            rcode_check_code: None,
+            private: false,
+        };
+        diesel::insert_into(schema::players::table)
+            .values(&vec![player(player_id), player(opponent_id)])
+            .execute(&mut db)
+            .await
+            .unwrap();
+        diesel::insert_into(schema::player_ratings::table)
+            .values(&vec![
+                PlayerRating { id: player_id, char_id: 0, value: 600 },
+                PlayerRating { id: opponent_id, char_id: 1, value: 60 },
+            ])
+            .execute(&mut db)
+            .await
+            .unwrap();
+        let start = chrono::Utc::now().naive_utc() - chrono::Duration::days(1);
+        let game = |minute: i64, char_a: i16, value_a: i64, value_b: i64| models::Game {
+            timestamp: start + chrono::Duration::minutes(minute),
+            id_a: player_id,
+            name_a: "Fixture".to_string(),
+            char_a,
+            platform_a: 3,
+            id_b: opponent_id,
+            name_b: "Fixture".to_string(),
+            char_b: 1,
+            platform_b: 3,
+            winner: 1,
+            game_floor: 99,
+            value_a,
+            value_b,
+            real_timestamp: None,
+        };
+        // The opponent plays another of the player's characters between the third and fourth game
+        let games = vec![
+            game(1, 0, 100, 10),
+            game(2, 0, 200, 20),
+            game(3, 0, 300, 30),
+            game(4, 5, 0, 35),
+            game(5, 0, 400, 40),
+            game(6, 0, 500, 50),
+        ];
+        diesel::insert_into(schema::games::table)
+            .values(&games)
+            .execute(&mut db)
+            .await
+            .unwrap();
+
+        let after = |games: Vec<db::HistoryGame>| {
+            games
+                .into_iter()
+                .map(|g| (g.game.value_a, g.own_rating_after, g.opponent_rating_after))
+                .collect::<Vec<_>>()
+        };
+        let first = db::get_games(player_id, 0, 2, 0, &mut db).await.map(after);
+        let second = db::get_games(player_id, 0, 2, 2, &mut db).await.map(after);
+        let since = db::get_games_since(player_id, 0, start, 10, &mut db).await.map(after);
+
+        diesel::delete(schema::games::table.filter(schema::games::id_a.eq(player_id)))
+            .execute(&mut db)
+            .await
+            .unwrap();
+        diesel::delete(schema::player_ratings::table.filter(schema::player_ratings::id.eq_any([player_id, opponent_id])))
+            .execute(&mut db)
+            .await
+            .unwrap();
+        diesel::delete(schema::players::table.filter(schema::players::id.eq_any([player_id, opponent_id])))
+            .execute(&mut db)
+            .await
+            .unwrap();
+
+        assert_eq!(first.unwrap(), vec![(500, Some(600), Some(60)), (400, Some(500), Some(50))]);
+        assert_eq!(second.unwrap(), vec![(300, Some(400), Some(35)), (200, Some(300), Some(30))]);
+        assert_eq!(
+            since.unwrap(),
+            vec![
+                (500, Some(600), None),
+                (400, Some(500), None),
+                (300, Some(400), None),
+                (200, Some(300), None),
+                (100, Some(200), None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{routing::get, Router};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    GaugeVec, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use tracing::info;
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "puddle_http_requests_total",
+        "API requests by route and status code",
+        &["route", "status"]
+    )
+    .unwrap();
+    static ref HTTP_LATENCY: HistogramVec = register_histogram_vec!(
+        "puddle_http_request_duration_seconds",
+        "API request latency by route",
+        &["route"]
+    )
+    .unwrap();
+    static ref POOL_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!(
+        "puddle_pool_connections",
+        "Open bb8 connections by pool, idle or in_use",
+        &["pool", "state"]
+    )
+    .unwrap();
+    static ref REPLAYS_FETCHED: IntCounterVec = register_int_counter_vec!(
+        "puddle_replays_fetched_total",
+        "Replays grab_games got from GGST, by query (all or the shard name)",
+        &["query"]
+    )
+    .unwrap();
+    static ref GAMES_INSERTED: IntCounterVec = register_int_counter_vec!(
+        "puddle_games_inserted_total",
+        "Replays grab_games inserted as new games, by query (all or the shard name)",
+        &["query"]
+    )
+    .unwrap();
+    static ref GGST_LATENCY: HistogramVec = register_histogram_vec!(
+        "puddle_ggst_request_duration_seconds",
+        "GGST API latency by endpoint",
+        &["endpoint"]
+    )
+    .unwrap();
+    static ref GGST_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "puddle_ggst_errors_total",
+        "Failed GGST API calls by endpoint and reason (transport, unreadable or refused)",
+        &["endpoint", "reason"]
+    )
+    .unwrap();
+    static ref JOB_DURATION: GaugeVec = register_gauge_vec!(
+        "puddle_job_duration_seconds",
+        "How long the last run of each hourly/daily job took",
+        &["job"]
+    )
+    .unwrap();
+    static ref JOB_LAST_SUCCESS: GaugeVec = register_gauge_vec!(
+        "puddle_job_last_success_timestamp_seconds",
+        "Unix time each hourly/daily job last succeeded",
+        &["job"]
+    )
+    .unwrap();
+    static ref JOB_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "puddle_job_failures_total",
+        "Failed runs of each hourly/daily job",
+        &["job"]
+    )
+    .unwrap();
+}
+
+/// Route layer counting requests and timing them by route pattern, so `/api/player/:id` is one series
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+
+    HTTP_LATENCY
+        .with_label_values(&[&route])
+        .observe(start.elapsed().as_secs_f64());
+    HTTP_REQUESTS
+        .with_label_values(&[&route, response.status().as_str()])
+        .inc();
+
+    response
+}
+
+pub fn record_replays(query: &str, fetched: usize, inserted: usize) {
+    REPLAYS_FETCHED.with_label_values(&[query]).inc_by(fetched as u64);
+    GAMES_INSERTED.with_label_values(&[query]).inc_by(inserted as u64);
+}
+
+pub fn record_ggst_latency(endpoint: &str, elapsed: Duration) {
+    GGST_LATENCY
+        .with_label_values(&[endpoint])
+        .observe(elapsed.as_secs_f64());
+}
+
+pub fn record_ggst_error(endpoint: &str, reason: &str) {
+    GGST_ERRORS.with_label_values(&[endpoint, reason]).inc();
+}
+
+pub fn record_job(job: &str, elapsed: Duration, success: bool) {
+    JOB_DURATION.with_label_values(&[job]).set(elapsed.as_secs_f64());
+    if success {
+        JOB_LAST_SUCCESS
+            .with_label_values(&[job])
+            .set(chrono::Utc::now().timestamp() as f64);
+    } else {
+        JOB_FAILURES.with_label_values(&[job]).inc();
+    }
+}
+
+fn record_pool(pool: &str, state: bb8::State) {
+    let idle = state.idle_connections as i64;
+    POOL_CONNECTIONS.with_label_values(&[pool, "idle"]).set(idle);
+    POOL_CONNECTIONS
+        .with_label_values(&[pool, "in_use"])
+        .set(state.connections as i64 - idle);
+}
+
+/// Everything registered, in the Prometheus text format. Pool gauges are read at scrape time.
+pub fn render(state: &crate::AppState) -> String {
+    record_pool("postgres", state.db_pool.state());
+    record_pool("redis", state.redis_pool.state());
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+pub async fn metrics(State(state): State<crate::AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, TextEncoder::new().format_type().to_string())],
+        render(&state),
+    )
+}
+
+/// `/metrics` for the puller, which doesn't run the web server
+pub async fn serve(state: crate::AppState, addr: String) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let app = Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(state);
+
+    info!("Metrics listening on {}", addr);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_metrics_are_gathered() {
+        record_job("test_job", Duration::from_millis(1500), true);
+        record_job("test_failing_job", Duration::from_secs(2), false);
+        record_replays("test_query", 10, 3);
+        record_ggst_error("test/endpoint", "refused");
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&prometheus::gather(), &mut buffer)
+            .unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+
+        assert!(text.contains("puddle_job_duration_seconds{job=\"test_job\"} 1.5"));
+        assert!(text.contains("puddle_job_failures_total{job=\"test_failing_job\"} 1"));
+        assert!(!text.contains("puddle_job_last_success_timestamp_seconds{job=\"test_failing_job\"}"));
+        assert!(text.contains("puddle_replays_fetched_total{query=\"test_query\"} 10"));
+        assert!(text.contains("puddle_games_inserted_total{query=\"test_query\"} 3"));
+        assert!(text.contains("puddle_ggst_errors_total{endpoint=\"test/endpoint\",reason=\"refused\"} 1"));
+    }
+}
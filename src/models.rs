@@ -13,11 +13,11 @@ use diesel::{
     prelude::*,
 };
 use crate::schema::{
-    self, games, player_names, players, tags, player_ratings,
+    self, follows, games, job_runs, leaderboard_snapshots, player_names, players, tags, player_ratings, webhook_deliveries, webhooks,
 };
 
-use chrono::NaiveDateTime;
-#[derive(Selectable, Insertable, Queryable, Identifiable)]
+use chrono::{NaiveDate, NaiveDateTime};
+#[derive(Selectable, Insertable, Queryable, QueryableByName, Identifiable, Clone)]
 #[diesel(primary_key(timestamp, id_a, id_b))]
 pub struct Game {
     pub timestamp: NaiveDateTime,
@@ -58,6 +58,7 @@ pub struct Player {
     pub platform: i16,
     pub api_key: Option<String>,
     pub rcode_check_code: Option<String>,
+    pub private: bool,
 }
 
 #[derive(Selectable, Insertable, Queryable)]
@@ -66,4 +67,67 @@ pub struct Tag {
     pub player_id: i64,
     pub tag: String,
     pub style: String,
-}
\ No newline at end of file
+}
+#[derive(Selectable, Insertable, Queryable, Identifiable, Clone)]
+#[diesel(primary_key(snapshot_date, board, player_id, char_id))]
+pub struct LeaderboardSnapshot {
+    pub snapshot_date: NaiveDate,
+    pub board: String,
+    pub player_id: i64,
+    pub char_id: i16,
+    pub rank: i32,
+    pub name: String,
+    pub rating: i64,
+}
+
+#[derive(Selectable, Insertable, Queryable, Identifiable)]
+#[diesel(primary_key(player_id, followed_id))]
+pub struct Follow {
+    pub player_id: i64,
+    pub followed_id: i64,
+}
+
+#[derive(Selectable, Insertable, Queryable, QueryableByName, Identifiable, Clone)]
+#[diesel(primary_key(player_id))]
+pub struct Webhook {
+    pub player_id: i64,
+    pub url: String,
+    pub secret: String,
+}
+
+#[derive(Selectable, Queryable, QueryableByName, Identifiable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub player_id: i64,
+    pub payload: String,
+    pub attempts: i32,
+    pub next_attempt: NaiveDateTime,
+    pub last_error: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewWebhookDelivery {
+    pub player_id: i64,
+    pub payload: String,
+    pub next_attempt: NaiveDateTime,
+}
+
+#[derive(Selectable, Queryable, Identifiable, Clone)]
+#[diesel(table_name = job_runs)]
+pub struct JobRun {
+    pub id: i64,
+    pub job: String,
+    pub started: NaiveDateTime,
+    pub finished: Option<NaiveDateTime>,
+    pub success: Option<bool>,
+    pub error: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = job_runs)]
+pub struct NewJobRun<'a> {
+    pub job: &'a str,
+    pub started: NaiveDateTime,
+}
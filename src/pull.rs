@@ -1,4 +1,4 @@
-use crate::{ggst_api, schema::{self, player_ratings}, CHAR_NAMES};
+use crate::{error::Error, ggst_api::{self, GgstApi}, requests::ReplayQuery, schema::{self, player_ratings}, CHAR_NAMES};
 
 use bb8_redis::redis;
 use diesel::prelude::*;
@@ -22,270 +22,134 @@ define_sql_function! {
 }
 
 pub const ONE_MINUTE: u64 = 1 * 60;
+const WEBHOOK_INTERVAL: u64 = 10;
+const WEBHOOK_BATCH: i64 = 50;
+/// How long claimed deliveries are kept from other instances, longer than a batch of timeouts takes
+const WEBHOOK_LEASE_SECONDS: i64 = 15 * 60;
+/// Replay pages grab_games reads per run when it doesn't reach games it already has, REPLAY_PAGE_BUDGET overrides it
+const DEFAULT_REPLAY_PAGE_BUDGET: usize = 10;
+/// Pages read per shard, shards only have to catch what the unfiltered query crowds out
+const SHARD_PAGE_BUDGET: usize = 2;
+/// Pages in a row that don't reach older games before backfill gives up
+const MAX_STALLED_BACKFILL_PAGES: usize = 3;
+/// Shards queried per run on top of the unfiltered query, REPLAY_SHARDS_PER_RUN overrides it
+const DEFAULT_SHARDS_PER_RUN: usize = 2;
 
 //TODO move the db stuff from this file into db.rs and imdb.rs
 
 pub async fn pull_and_update_continuous(state: crate::AppState) {
 
-    //check for token.txt and set TOKEN if it exists
+    //check for token.txt and use it until GGST refuses it
     {
         use std::fs;
         if let Ok(token) = fs::read_to_string("token.txt") {
-            let mut token_lock = ggst_api::TOKEN.lock().await;
-            *token_lock = Some(token.trim().to_string());
+            ggst_api::set_token(token.trim().to_string()).await;
             info!("Loaded API token from token.txt");
         } else {
             warn!("token.txt not found, trying to initialize with steam authentication");
         }
     }
     
-    // Processing loop
-    let processing_state = state.clone();
-    let processing_task = tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(ONE_MINUTE - 10));
-
-        loop {
-            interval.tick().await;
-
-            info!("Processing");
-
-            let mut connection = processing_state.db_pool.get().await.unwrap();
-            let mut redis_connection = processing_state.redis_pool.get().await.unwrap();
-
-            //Get last_update_hourly from redis
-            let last_update_hourly: Result<String, redis::RedisError> = redis::cmd("GET")
-                .arg("last_update_hourly")
-                .query_async(&mut *redis_connection)
-                .await;
-
-            let last_update_hourly = match last_update_hourly {
-                Ok(s) => s,
-                Err(_) => {
-                    //If last_update_hourly doesn't exist, initialize to now minus 2 hours
-                    chrono::DateTime::from_timestamp(
-                        chrono::Utc::now().naive_utc().and_utc().timestamp() - (2 * 60 * 60),
-                        0,
-                    )
-                    .unwrap()
-                    .naive_utc()
-                    .to_string()
-                }
-            };
-
-            //Parse last_update_hourly
-            let last_update_hourly =
-                NaiveDateTime::parse_from_str(&last_update_hourly, "%Y-%m-%d %H:%M:%S").unwrap();
-
-            //Only do this if "last_update_hourly" from redis is more than an hour ago
-            if last_update_hourly < Utc::now().naive_utc() - chrono::Duration::hours(1) {
-                info!("Hourly update");
-                if let Err(e) = connection
-                    .transaction::<_, diesel::result::Error, _>(|conn| {
-                        async move {
-                            do_hourly_update(conn, &mut redis_connection).await.unwrap();
-                            Ok(())
-                        }
-                        .scope_boxed()
-                    })
-                    .await
-                {
-                    error!("Hourly update failed: {e}");
-                } else {
-                    info!("Hourly update - Done");
-                }
-            }
-
-            let mut redis_connection = processing_state.redis_pool.get().await.unwrap();
-
-            //Get last_update_daily from redis
-            let last_update_daily: Result<String, redis::RedisError> = redis::cmd("GET")
-                .arg("last_update_daily")
-                .query_async(&mut *redis_connection)
-                .await;
-
-            let last_update_daily = match last_update_daily {
-                Ok(s) => s,
-                Err(_) => {
-                    //If last_update_daily doesn't exist, initialize to now minus 2 days
-                    chrono::DateTime::from_timestamp(
-                        chrono::Utc::now().naive_utc().and_utc().timestamp() - 172800,
-                        0,
-                    )
-                    .unwrap()
-                    .naive_utc()
-                    .to_string()
-                }
-            };
-
-            //Parse last_update_daily
-            let last_update_daily =
-                NaiveDateTime::parse_from_str(&last_update_daily, "%Y-%m-%d %H:%M:%S").unwrap();
-
-            if last_update_daily < Utc::now().naive_utc() - chrono::Duration::days(1) {
-                info!("Daily update");
-                if let Err(e) = connection
-                    .transaction::<_, diesel::result::Error, _>(|conn| {
-                        async move {
-                            do_daily_update(conn, &mut redis_connection).await.unwrap();
-                            Ok(())
-                        }
-                        .scope_boxed()
-                    })
-                    .await
-                {
-                    error!("Daily update failed: {e}");
-                } else {
-                    info!("Daily update - Done");
-                }
-            };
-
-            info!("Processing - done");
-        }
-    });
+    // Hourly and daily jobs
+    let scheduler_task = tokio::spawn(crate::scheduler::run_continuous(state.clone()));
 
     // Replay pulling loop
     let pull_state = state.clone();
     let pull_task = tokio::spawn(async move {
         let mut interval = time::interval(Duration::from_secs(ONE_MINUTE));
+        let schedule = replay_shard_schedule();
+        let shards_per_run = replay_shards_per_run().min(schedule.len());
+        let mut next_shard = 0;
 
         loop {
             interval.tick().await;
 
             info!("Replay pull");
 
-            let mut connection = pull_state.db_pool.get().await.unwrap();
-            let mut redis_connection = pull_state.redis_pool.get().await.unwrap();
-
-            if let Err(e) = connection
-                .transaction::<_, diesel::result::Error, _>(|conn| {
-                    async move {
-                        match grab_games(conn, &mut redis_connection).await {
-                            Ok(new_games) => {
-                                info!("New games: {:?}", new_games.len());
-                            }
-                            Err(e) => {
-                                error!("grab_games failed: {e}");
-                            }
-                        };
-                        Ok(())
+            let (mut connection, mut redis_connection) =
+                match (pull_state.db_pool.get().await, pull_state.redis_pool.get().await) {
+                    (Ok(connection), Ok(redis_connection)) => (connection, redis_connection),
+                    (Err(e), _) => {
+                        error!("Replay pull: {}", Error::from(e));
+                        continue;
                     }
-                    .scope_boxed()
+                    (_, Err(e)) => {
+                        error!("Replay pull: {}", Error::from(e));
+                        continue;
+                    }
+                };
+            let api = &*pull_state.ggst;
+
+            let shards: Vec<ReplayShard> = (0..shards_per_run)
+                .map(|i| schedule[(next_shard + i) % schedule.len()].clone())
+                .collect();
+            next_shard = (next_shard + shards_per_run) % schedule.len().max(1);
+
+            //A failed pull rolls back its games, the next one reads them again
+            let new_games = match connection
+                .transaction::<_, Error, _>(|conn| {
+                    async move { grab_games(conn, &mut redis_connection, api, &shards).await }.scope_boxed()
                 })
                 .await
             {
-                error!("Replay pull loop: {e}");
-            }
-
-            info!("Replay pull - Done");
-        }
-    });
-
-    tokio::select! {
-        _ = processing_task => {},
-        _ = pull_task => {},
-    }
-}
-
-pub async fn do_hourly_update_once(state: crate::AppState) {
-    let mut connection = state.db_pool.get().await.unwrap();
-    let mut redis_connection = state.redis_pool.get().await.unwrap();
+                Ok(new_games) => {
+                    info!("New games: {:?}", new_games.len());
+                    new_games
+                }
+                Err(e) => {
+                    error!("grab_games failed: {e}");
+                    vec![]
+                }
+            };
 
-    connection
-        .transaction::<_, diesel::result::Error, _>(|conn| {
-            async move {
-                do_hourly_update(conn, &mut redis_connection).await.unwrap();
-                Ok(())
+            //Only once committed, so /api/live never shows a game the api can't return
+            if let Err(e) = publish_live_games(&new_games, &mut connection, &pull_state).await {
+                error!("publish_live_games failed: {e}");
             }
-            .scope_boxed()
-        })
-        .await
-        .unwrap();
-}
-
-pub async fn do_daily_update_once(state: crate::AppState) {
-    let mut connection = state.db_pool.get().await.unwrap();
-    let mut redis_connection = state.redis_pool.get().await.unwrap();
 
-    connection
-        .transaction::<_, diesel::result::Error, _>(|conn| {
-            async move {
-                do_daily_update(conn, &mut redis_connection).await.unwrap();
-                Ok(())
+            if let Err(e) = enqueue_game_webhooks(&new_games, &mut connection).await {
+                error!("enqueue_game_webhooks failed: {e}");
             }
-            .scope_boxed()
-        })
-        .await
-        .unwrap();
-}
 
-async fn do_hourly_update(
-    conn: &mut crate::Connection<'_>,
-    redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
-    if let Err(e) = sync_legend_leaderboard(redis_connection).await {
-        error!("sync_legend_leaderboard failed: {e}");
-    }
-
-    if let Err(e) = update_stats(conn, redis_connection).await {
-        error!("update_stats failed: {e}");
-    }
-
-    //Now
-    let last_update =
-        chrono::DateTime::from_timestamp(chrono::Utc::now().naive_utc().and_utc().timestamp(), 0)
-            .unwrap()
-            .naive_utc()
-            .to_string();
-
-    redis::cmd("SET")
-        .arg("last_update_hourly")
-        .arg(last_update)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting last_update_hourly");
-    Ok(())
-}
+            match pull_state.redis_pool.get().await {
+                Ok(mut redis_connection) => {
+                    if let Err(e) = crate::imdb::set_auth_status(&api.auth_status().await, &mut redis_connection).await {
+                        error!("set_auth_status failed: {e}");
+                    }
+                    if let Err(e) = crate::imdb::set_api_version(&crate::requests::api_version(), &mut redis_connection).await {
+                        error!("set_api_version failed: {e}");
+                    }
+                }
+                Err(e) => error!("Replay pull: {}", Error::from(e)),
+            }
 
-async fn do_daily_update(
-    conn: &mut crate::Connection<'_>,
-    redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+            info!("Replay pull - Done");
+        }
+    });
 
-    if let Err(e) = sync_global_leaderboards(redis_connection).await {
-        error!("sync_global_leaderboards failed: {e}");
-    }
+    // Webhook delivery loop
+    let webhook_state = state.clone();
+    let webhook_task = tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(WEBHOOK_INTERVAL));
 
-    if let Err(e) = update_popularity(conn, redis_connection).await {
-        error!("update_popularity failed: {e}");
-    }
+        loop {
+            interval.tick().await;
 
-    if let Err(e) = update_matchups(conn, redis_connection).await {
-        error!("update_matchups failed: {e}");
-    }
+            let result = match webhook_state.db_pool.get().await {
+                Ok(mut connection) => deliver_webhooks(&mut connection).await,
+                Err(e) => Err(Error::from(e)),
+            };
+            if let Err(e) = result {
+                error!("deliver_webhooks failed: {e}");
+            }
+        }
+    });
 
-    if let Err(e) = update_distribution(conn, redis_connection).await {
-        error!("update_distribution failed: {e}");
+    tokio::select! {
+        _ = scheduler_task => {},
+        _ = pull_task => {},
+        _ = webhook_task => {},
     }
-
-    //Now
-    let last_update =
-        chrono::DateTime::from_timestamp(chrono::Utc::now().naive_utc().and_utc().timestamp(), 0)
-            .unwrap()
-            .naive_utc()
-            .to_string();
-
-    redis::cmd("SET")
-        .arg("last_update_daily")
-        .arg(last_update)
-        .query_async::<String>(&mut **redis_connection)
-        .await
-        .expect("Error setting last_update_daily");
-
-    //Clear latest_game_time; health check handles the brief absence via the daily_ran_recently window
-    crate::imdb::clear_latest_game_time(redis_connection)
-        .await
-        .unwrap();
-    Ok(())
 }
 
 #[derive(QueryableByName, serde::Serialize, serde::Deserialize)]
@@ -302,10 +166,10 @@ pub struct DistributionResult {
     #[diesel(sql_type = diesel::sql_types::Double)]
     pub percentile: f64,
 }
-async fn update_distribution(
+pub async fn update_distribution(
     conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     info!("Updating distribution");
 
     let distribution_results = diesel::sql_query(
@@ -354,20 +218,71 @@ async fn update_distribution(
 
     let distribution_results = distribution_results
         .get_results::<DistributionResult>(conn)
-        .await
-        .unwrap();
+        .await?;
 
     redis::cmd("SET")
         .arg("distribution_rating")
-        .arg(serde_json::to_string(&distribution_results).unwrap())
+        .arg(serde_json::to_string(&distribution_results)?)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting distribution");
+        .map_err(|e| Error::from(e).context("Redis SET distribution failed"))?;
 
     info!("Updating distribution - Done");
     Ok(())
 }
 
+/// Games read per query while computing performance
+const PERFORMANCE_BATCH_SIZE: i64 = 10000;
+
+pub async fn update_performance(
+    conn: &mut crate::Connection<'_>,
+    redis_connection: &mut crate::RedisConnection<'_>,
+) -> Result<(), Error> {
+    use crate::handlers::performance::{self, Performance};
+
+    info!("Updating performance");
+
+    let until = Utc::now().naive_utc();
+    let since = until - chrono::Duration::days(performance::PERFORMANCE_WINDOW_DAYS);
+
+    let mut performances: std::collections::HashMap<(i64, i16), Performance> = std::collections::HashMap::new();
+    let mut after = None;
+    loop {
+        let games = crate::db::get_all_games_after(after, Some(since), until, PERFORMANCE_BATCH_SIZE, conn).await?;
+        for game in games.iter().filter(|g| g.game_floor == 0) {
+            performances.entry((game.id_a, game.char_a)).or_default().add(game.value_a, game.value_b, game.winner == 1);
+            performances.entry((game.id_b, game.char_b)).or_default().add(game.value_b, game.value_a, game.winner == 2);
+        }
+
+        match games.last() {
+            Some(last) if games.len() as i64 == PERFORMANCE_BATCH_SIZE => after = Some((last.timestamp, last.id_a, last.id_b)),
+            _ => break,
+        }
+    }
+
+    let candidates = performances
+        .iter()
+        .filter(|(_, p)| p.games >= performance::MIN_LEADERBOARD_GAMES)
+        .map(|((id, _), _)| *id)
+        .collect();
+    let names = crate::db::get_player_names(candidates, conn).await?;
+
+    let top_performers = performance::top_performers(performances, |id| match names.get(&id) {
+        Some((name, false)) => Some(name.clone()),
+        _ => None,
+    });
+
+    redis::cmd("SET")
+        .arg("top_performers")
+        .arg(serde_json::to_string(&top_performers)?)
+        .query_async::<String>(&mut **redis_connection)
+        .await
+        .map_err(|e| Error::from(e).context("Redis SET top_performers failed"))?;
+
+    info!("Updating performance - Done");
+    Ok(())
+}
+
 #[derive(QueryableByName, serde::Serialize, serde::Deserialize)]
 pub struct Matchup {
     #[diesel(sql_type = diesel::sql_types::SmallInt)]
@@ -377,17 +292,17 @@ pub struct Matchup {
     #[diesel(sql_type = diesel::sql_types::BigInt)]
     pub total_games: i64,
 }
-async fn update_matchups(
+pub async fn update_matchups(
     conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     info!("Updating matchups");
 
     {
         let mut all_characters: std::collections::HashMap<i16, Vec<Matchup>> =
             std::collections::HashMap::new();
 
-        for c in 0..CHAR_NAMES.len() {
+        for c in 0..CHAR_NAMES.len() as i16 {
             let results = diesel::sql_query(
                 "
               SELECT 
@@ -426,23 +341,23 @@ async fn update_matchups(
               ",
             );
             let results: Vec<Matchup> = results
-                .bind::<Integer, _>(i32::try_from(c).unwrap())
+                .bind::<Integer, _>(i32::from(c))
                 .get_results(conn)
-                .await
-                .unwrap();
+                .await?;
 
-            all_characters.insert(i16::try_from(c).unwrap(), results);
+            all_characters.insert(c, results);
         }
 
         for (char_id, matchups) in all_characters {
             let char_id = char_id as usize;
 
+            let key = format!("matchup_{}", char_id);
             redis::cmd("SET")
-                .arg(format!("matchup_{}", char_id))
-                .arg(serde_json::to_string(&matchups).unwrap())
+                .arg(&key)
+                .arg(serde_json::to_string(&matchups)?)
                 .query_async::<String>(&mut **redis_connection)
                 .await
-                .expect("Error setting matchup");
+                .map_err(|e| Error::from(e).context(&format!("Redis SET {key} failed")))?;
         }
     }
 
@@ -450,7 +365,7 @@ async fn update_matchups(
         let mut all_characters: std::collections::HashMap<i16, Vec<Matchup>> =
             std::collections::HashMap::new();
 
-        for c in 0..CHAR_NAMES.len() {
+        for c in 0..CHAR_NAMES.len() as i16 {
             let results = diesel::sql_query(
                 "
             SELECT 
@@ -489,23 +404,23 @@ async fn update_matchups(
             ",
             );
             let results: Vec<Matchup> = results
-                .bind::<Integer, _>(i32::try_from(c).unwrap())
+                .bind::<Integer, _>(i32::from(c))
                 .get_results(conn)
-                .await
-                .unwrap();
+                .await?;
 
-            all_characters.insert(i16::try_from(c).unwrap(), results);
+            all_characters.insert(c, results);
         }
 
         for (char_id, matchups) in all_characters {
             let char_id = char_id as usize;
 
+            let key = format!("matchup_vanq_{}", char_id);
             redis::cmd("SET")
-                .arg(format!("matchup_vanq_{}", char_id))
-                .arg(serde_json::to_string(&matchups).unwrap())
+                .arg(&key)
+                .arg(serde_json::to_string(&matchups)?)
                 .query_async::<String>(&mut **redis_connection)
                 .await
-                .expect("Error setting matchup");
+                .map_err(|e| Error::from(e).context(&format!("Redis SET {key} failed")))?;
         }
     }
 
@@ -525,10 +440,10 @@ struct PopularityResultTotal {
     #[diesel(sql_type = diesel::sql_types::BigInt)]
     count: i64,
 }
-async fn update_popularity(
+pub async fn update_popularity(
     conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     info!("Updating popularity");
     //We're using subqueries here, so we need to use sql_query
 
@@ -550,18 +465,19 @@ async fn update_popularity(
     );
 
     let results: Vec<PopularityResult> =
-        results.get_results::<PopularityResult>(conn).await.unwrap();
+        results.get_results::<PopularityResult>(conn).await?;
 
     for r in results {
         let char_id = r.c as usize;
         let count = r.count;
 
+        let key = format!("popularity_per_player_{}", CHAR_NAMES[char_id].0);
         redis::cmd("SET")
-            .arg(format!("popularity_per_player_{}", CHAR_NAMES[char_id].0))
+            .arg(&key)
             .arg(count)
             .query_async::<String>(&mut **redis_connection)
             .await
-            .expect("Error setting popularity per player");
+            .map_err(|e| Error::from(e).context(&format!("Redis SET {key} failed")))?;
     }
 
     //Total distinct player + character combination.
@@ -582,15 +498,14 @@ async fn update_popularity(
 
     let results_total_players: Vec<PopularityResultTotal> = results_total_players
         .get_results::<PopularityResultTotal>(conn)
-        .await
-        .unwrap();
+        .await?;
 
     redis::cmd("SET")
         .arg("popularity_per_player_total")
         .arg(results_total_players[0].count)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting popularity_total");
+        .map_err(|e| Error::from(e).context("Redis SET popularity_per_player_total failed"))?;
 
     //Total game count per character (no total needed, we can use 'one_month_games' from stats)
     let results = diesel::sql_query(
@@ -610,21 +525,19 @@ async fn update_popularity(
     );
 
     let results: Vec<PopularityResult> =
-        results.get_results::<PopularityResult>(conn).await.unwrap();
+        results.get_results::<PopularityResult>(conn).await?;
 
     for r in results {
         let char_id = r.c as usize;
         let count = r.count;
 
+        let key = format!("popularity_per_character_{}", CHAR_NAMES[char_id].0);
         redis::cmd("SET")
-            .arg(format!(
-                "popularity_per_character_{}",
-                CHAR_NAMES[char_id].0
-            ))
+            .arg(&key)
             .arg(count)
             .query_async::<String>(&mut **redis_connection)
             .await
-            .expect("Error setting popularity per game");
+            .map_err(|e| Error::from(e).context(&format!("Redis SET {key} failed")))?;
     }
 
     info!("Updating popularity - Done");
@@ -637,18 +550,17 @@ struct CountResult {
     #[diesel(sql_type = BigInt)]
     count: i64,
 }
-async fn update_stats(
+pub async fn update_stats(
     conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     info!("Updating stats");
 
     // Get total game count
     let total_games: i64 = schema::games::table
         .count()
         .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
+        .await?;
 
     // Get one month game count
     let one_month_games = schema::games::table
@@ -658,8 +570,7 @@ async fn update_stats(
         )
         .count()
         .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
+        .await?;
 
     // Get one week game count
     let one_week_games = schema::games::table
@@ -668,8 +579,7 @@ async fn update_stats(
         )
         .count()
         .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
+        .await?;
 
     // Get one day game count
     let one_day_games = schema::games::table
@@ -678,8 +588,7 @@ async fn update_stats(
         )
         .count()
         .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
+        .await?;
 
     // Get one hour game count
     let one_hour_games = schema::games::table
@@ -689,15 +598,13 @@ async fn update_stats(
         )
         .count()
         .get_result::<i64>(conn)
-        .await
-        .expect("Error loading games");
+        .await?;
 
     // Get total player count
     let total_players = schema::players::table
         .count()
         .get_result::<i64>(conn)
-        .await
-        .expect("Error loading players");
+        .await?;
 
     // Get past month player count
     let one_month_players = diesel::sql_query(
@@ -716,8 +623,7 @@ async fn update_stats(
     );
     let one_month_players = one_month_players
         .get_results::<CountResult>(conn)
-        .await
-        .unwrap();
+        .await?;
     let one_month_players = one_month_players[0].count;
 
     // Get past month player count
@@ -737,8 +643,7 @@ async fn update_stats(
     );
     let one_week_players = one_week_players
         .get_results::<CountResult>(conn)
-        .await
-        .unwrap();
+        .await?;
     let one_week_players = one_week_players[0].count;
 
     // Get past day player count
@@ -758,8 +663,7 @@ async fn update_stats(
     );
     let one_day_players = one_day_players
         .get_results::<CountResult>(conn)
-        .await
-        .unwrap();
+        .await?;
     let one_day_players = one_day_players[0].count;
 
     // Get past hour player count
@@ -779,8 +683,7 @@ async fn update_stats(
     );
     let one_hour_players = one_hour_players
         .get_results::<CountResult>(conn)
-        .await
-        .unwrap();
+        .await?;
     let one_hour_players = one_hour_players[0].count;
 
     redis::cmd("SET")
@@ -788,94 +691,162 @@ async fn update_stats(
         .arg(total_games)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting total_games");
+        .map_err(|e| Error::from(e).context("Redis SET total_games failed"))?;
 
     redis::cmd("SET")
         .arg("one_month_games")
         .arg(one_month_games)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting one_month_games");
+        .map_err(|e| Error::from(e).context("Redis SET one_month_games failed"))?;
 
     redis::cmd("SET")
         .arg("one_week_games")
         .arg(one_week_games)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting one_week_games");
+        .map_err(|e| Error::from(e).context("Redis SET one_week_games failed"))?;
 
     redis::cmd("SET")
         .arg("one_day_games")
         .arg(one_day_games)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting one_day_games");
+        .map_err(|e| Error::from(e).context("Redis SET one_day_games failed"))?;
 
     redis::cmd("SET")
         .arg("one_hour_games")
         .arg(one_hour_games)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting one_hour_games");
+        .map_err(|e| Error::from(e).context("Redis SET one_hour_games failed"))?;
 
     redis::cmd("SET")
         .arg("total_players")
         .arg(total_players)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting total_players");
+        .map_err(|e| Error::from(e).context("Redis SET total_players failed"))?;
 
     redis::cmd("SET")
         .arg("one_month_players")
         .arg(one_month_players)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting one_month_players");
+        .map_err(|e| Error::from(e).context("Redis SET one_month_players failed"))?;
 
     redis::cmd("SET")
         .arg("one_week_players")
         .arg(one_week_players)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting one_week_players");
+        .map_err(|e| Error::from(e).context("Redis SET one_week_players failed"))?;
 
     redis::cmd("SET")
         .arg("one_day_players")
         .arg(one_day_players)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting one_day_players");
+        .map_err(|e| Error::from(e).context("Redis SET one_day_players failed"))?;
 
     redis::cmd("SET")
         .arg("one_hour_players")
         .arg(one_hour_players)
         .query_async::<String>(&mut **redis_connection)
         .await
-        .expect("Error setting one_hour_players");
+        .map_err(|e| Error::from(e).context("Redis SET one_hour_players failed"))?;
 
     info!("Updating stats - Done");
     Ok(())
 }
 
-async fn sync_legend_leaderboard(
+/// Replaces today's snapshot of `board`, so the legend board (synced hourly) keeps the last sync of the day.
+/// Runs in its own savepoint so a failed snapshot doesn't abort the rest of the update.
+async fn save_leaderboard_snapshot(
+    conn: &mut crate::Connection<'_>,
+    board: &str,
+    entries: &[crate::responses::LeaderboardEntry],
+) -> Result<(), Error> {
+    use crate::schema::leaderboard_snapshots;
+
+    let snapshot_date = Utc::now().date_naive();
+    let rows: Vec<LeaderboardSnapshot> = entries
+        .iter()
+        .filter_map(|e| {
+            Some(LeaderboardSnapshot {
+                snapshot_date,
+                board: board.to_string(),
+                player_id: e.player_id.parse::<i64>().ok()?,
+                char_id: e.char_id as i16,
+                rank: e.rank as i32,
+                name: e.player_name.clone(),
+                rating: e.rating,
+            })
+        })
+        .collect();
+
+    // Per character boards share the 'char' board, only replace this character's rows
+    let char_id = rows.iter().map(|r| r.char_id).next().unwrap_or(-1);
+    let only_char = board == "char";
+    let board_name = board.to_string();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        async move {
+            if only_char {
+                delete(leaderboard_snapshots::table)
+                    .filter(leaderboard_snapshots::snapshot_date.eq(snapshot_date))
+                    .filter(leaderboard_snapshots::board.eq(&board_name))
+                    .filter(leaderboard_snapshots::char_id.eq(char_id))
+                    .execute(conn)
+                    .await?;
+            } else {
+                delete(leaderboard_snapshots::table)
+                    .filter(leaderboard_snapshots::snapshot_date.eq(snapshot_date))
+                    .filter(leaderboard_snapshots::board.eq(&board_name))
+                    .execute(conn)
+                    .await?;
+            }
+
+            // Leaderboards can hold the same player and character twice across MR/LP pages
+            for chunk in rows.chunks(1000) {
+                insert_into(leaderboard_snapshots::table)
+                    .values(chunk)
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+                    .await?;
+            }
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|e| Error::from(e).context(&format!("Saving {board} leaderboard snapshot failed")))
+}
+
+pub async fn sync_legend_leaderboard(
+    conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+    api: &dyn GgstApi,
+) -> Result<(), Error> {
     use crate::responses::LeaderboardEntry;
 
     info!("Syncing legend leaderboard");
 
-    match crate::ggst_api::get_rank_match_legend().await {
+    match api.get_rank_match_legend().await {
         Ok(players) => {
             info!("Legend: {} players", players.len());
             let entries: Vec<LeaderboardEntry> =
                 players.into_iter().map(LeaderboardEntry::from).collect();
-            let json = serde_json::to_string(&entries).unwrap();
+            let json = serde_json::to_string(&entries)?;
             redis::cmd("SET")
                 .arg("leaderboard_legend")
                 .arg(json)
                 .query_async::<String>(&mut **redis_connection)
                 .await
-                .map_err(|e| format!("Redis SET leaderboard_legend failed: {e}"))?;
+                .map_err(|e| Error::from(e).context("Redis SET leaderboard_legend failed"))?;
+            if let Err(e) = save_leaderboard_snapshot(conn, "legend", &entries).await {
+                error!("{e}");
+            }
         }
         Err(e) => error!("sync_legend_leaderboard: legend failed: {e}"),
     }
@@ -883,9 +854,11 @@ async fn sync_legend_leaderboard(
     Ok(())
 }
 
-async fn sync_global_leaderboards(
+pub async fn sync_global_leaderboards(
+    conn: &mut crate::Connection<'_>,
     redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<(), String> {
+    api: &dyn GgstApi,
+) -> Result<(), Error> {
     use crate::responses::LeaderboardEntry;
 
     info!("Syncing global leaderboards");
@@ -893,7 +866,7 @@ async fn sync_global_leaderboards(
     let mut mr_all: Vec<LeaderboardEntry> = Vec::new();
     let mut page = 0i64;
     loop {
-        match crate::ggst_api::get_rank_match_mr(page, -1).await {
+        match api.get_rank_match_mr(page, -1).await {
             Ok(players) => {
                 let n = players.len();
                 if n == 0 {
@@ -923,7 +896,7 @@ async fn sync_global_leaderboards(
     let mut lp_filtered: Vec<LeaderboardEntry> = Vec::new();
     let mut page = 0i64;
     loop {
-        match crate::ggst_api::get_rank_match_lp(page, -1).await {
+        match api.get_rank_match_lp(page, -1).await {
             Ok(players) => {
                 let n = players.len();
                 if n == 0 {
@@ -961,13 +934,16 @@ async fn sync_global_leaderboards(
 
     if !combined.is_empty() {
         info!("Combined leaderboard: {} MR + {} LP = {} total", mr_count, lp_count, combined.len());
-        let json = serde_json::to_string(&combined).unwrap();
+        let json = serde_json::to_string(&combined)?;
         redis::cmd("SET")
             .arg("leaderboard_all")
             .arg(json)
             .query_async::<String>(&mut **redis_connection)
             .await
-            .map_err(|e| format!("Redis SET leaderboard_all failed: {e}"))?;
+            .map_err(|e| Error::from(e).context("Redis SET leaderboard_all failed"))?;
+        if let Err(e) = save_leaderboard_snapshot(conn, "all", &combined).await {
+            error!("{e}");
+        }
     }
 
     for (char_idx, (char_short, _)) in crate::CHAR_NAMES.iter().enumerate() {
@@ -976,7 +952,7 @@ async fn sync_global_leaderboards(
         let mut mr_char: Vec<LeaderboardEntry> = Vec::new();
         let mut page = 0i64;
         loop {
-            match crate::ggst_api::get_rank_match_mr(page, char_id).await {
+            match api.get_rank_match_mr(page, char_id).await {
                 Ok(players) => {
                     let n = players.len();
                     if n == 0 {
@@ -1007,7 +983,7 @@ async fn sync_global_leaderboards(
         let mut lp_char: Vec<LeaderboardEntry> = Vec::new();
         let mut page = 0i64;
         loop {
-            match crate::ggst_api::get_rank_match_lp(page, char_id).await {
+            match api.get_rank_match_lp(page, char_id).await {
                 Ok(players) => {
                     let n = players.len();
                     if n == 0 {
@@ -1043,13 +1019,16 @@ async fn sync_global_leaderboards(
         if !combined.is_empty() {
             let key = format!("leaderboard_char_{}", char_idx);
             info!("Char {char_short}: storing {} entries as {key}", combined.len());
-            let json = serde_json::to_string(&combined).unwrap();
+            let json = serde_json::to_string(&combined)?;
             redis::cmd("SET")
                 .arg(&key)
                 .arg(json)
                 .query_async::<String>(&mut **redis_connection)
                 .await
-                .map_err(|e| format!("Redis SET {key} failed: {e}"))?;
+                .map_err(|e| Error::from(e).context(&format!("Redis SET {key} failed")))?;
+            if let Err(e) = save_leaderboard_snapshot(conn, "char", &combined).await {
+                error!("{e}");
+            }
         }
 
         tokio::time::sleep(Duration::from_millis(100)).await;
@@ -1062,7 +1041,7 @@ async fn sync_global_leaderboards(
 async fn update_player_info(
     connection: &mut AsyncPgConnection,
     new_game: &Game,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     //Update player name in the player table
     insert_into(players::table)
         .values(&Player {
@@ -1071,6 +1050,7 @@ async fn update_player_info(
             platform: new_game.platform_a,
             api_key: None,
             rcode_check_code: None,
+            private: false,
         })
         .on_conflict(players::id)
         .do_update()
@@ -1079,8 +1059,7 @@ async fn update_player_info(
             players::platform.eq(new_game.platform_a),
         ))
         .execute(connection)
-        .await
-        .unwrap();
+        .await?;
 
     insert_into(players::table)
         .values(&Player {
@@ -1089,6 +1068,7 @@ async fn update_player_info(
             platform: new_game.platform_b,
             api_key: None,
             rcode_check_code: None,
+            private: false,
         })
         .on_conflict(players::id)
         .do_update()
@@ -1097,8 +1077,7 @@ async fn update_player_info(
             players::platform.eq(new_game.platform_b),
         ))
         .execute(connection)
-        .await
-        .unwrap();
+        .await?;
 
     //Update player names in the player_names table
     insert_into(player_names::table)
@@ -1108,8 +1087,7 @@ async fn update_player_info(
         })
         .on_conflict_do_nothing()
         .execute(connection)
-        .await
-        .unwrap();
+        .await?;
 
     insert_into(player_names::table)
         .values(&PlayerName {
@@ -1118,8 +1096,7 @@ async fn update_player_info(
         })
         .on_conflict_do_nothing()
         .execute(connection)
-        .await
-        .unwrap();
+        .await?;
 
       //Update player rating
             insert_into(player_ratings::table)
@@ -1132,8 +1109,7 @@ async fn update_player_info(
           .do_update()
           .set(player_ratings::value.eq(new_game.value_a))
           .execute(connection)
-          .await
-          .unwrap();
+          .await?;
 
       insert_into(player_ratings::table)
           .values(&PlayerRating {
@@ -1145,92 +1121,384 @@ async fn update_player_info(
           .do_update()
           .set(player_ratings::value.eq(new_game.value_b))
           .execute(connection)
-          .await
-          .unwrap();
+          .await?;
+
+    Ok(())
+}
+
+async fn publish_live_games(
+    new_games: &[Game],
+    connection: &mut crate::Connection<'_>,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    use crate::handlers::live::{LiveGame, LIVE_CHANNEL};
+
+    if new_games.is_empty() {
+        return Ok(());
+    }
+
+    let player_ids = new_games.iter().flat_map(|g| [g.id_a, g.id_b]).collect();
+    let private_players = crate::db::get_private_players(player_ids, connection).await?;
+
+    let mut redis_connection = state.redis_pool.get().await?;
+    for game in new_games {
+        let json = serde_json::to_string(&LiveGame::from_game(game, &private_players))?;
+        redis::cmd("PUBLISH")
+            .arg(LIVE_CHANNEL)
+            .arg(json)
+            .query_async::<i64>(&mut *redis_connection)
+            .await
+            .map_err(|e| Error::from(e).context(&format!("Redis PUBLISH {LIVE_CHANNEL} failed")))?;
+    }
+
+    Ok(())
+}
+
+async fn enqueue_game_webhooks(
+    new_games: &[Game],
+    connection: &mut crate::Connection<'_>,
+) -> Result<(), Error> {
+    use crate::handlers::webhook::game_message;
+
+    if new_games.is_empty() {
+        return Ok(());
+    }
+
+    let player_ids: std::collections::HashSet<i64> = new_games.iter().flat_map(|g| [g.id_a, g.id_b]).collect();
+    let private_players = crate::db::get_private_players(player_ids.clone(), connection).await?;
+    let followed_ids = player_ids.difference(&private_players).copied().collect();
+    let followers = crate::db::get_webhook_followers(followed_ids, connection).await?;
+
+    let queued_at = Utc::now().naive_utc();
+    let mut deliveries = Vec::new();
+    for game in new_games {
+        for (follower, followed) in &followers {
+            if *followed == game.id_a || *followed == game.id_b {
+                deliveries.push(NewWebhookDelivery {
+                    player_id: *follower,
+                    payload: game_message(&crate::handlers::common::SITE_URL, game, *followed, &private_players),
+                    next_attempt: queued_at,
+                });
+            }
+        }
+    }
+
+    info!("Webhooks: {} game deliveries queued", deliveries.len());
+    crate::db::enqueue_webhook_deliveries(deliveries, connection).await
+}
+
+/// Global rank changes of followed players between the two latest snapshots of the 'all' board
+pub async fn enqueue_rank_webhooks(connection: &mut crate::Connection<'_>) -> Result<(), Error> {
+    use crate::handlers::webhook::rank_message;
+
+    let dates = crate::db::get_latest_snapshot_dates("all", 0, connection).await?;
+    let (current, previous) = match dates[..] {
+        [current, previous] => (
+            crate::db::get_leaderboard_snapshot("all", 0, current, connection).await?,
+            crate::db::get_leaderboard_snapshot("all", 0, previous, connection).await?,
+        ),
+        _ => return Ok(()),
+    };
+
+    let previous_ranks: std::collections::HashMap<(i64, i16), i32> = previous
+        .iter()
+        .map(|s| ((s.player_id, s.char_id), s.rank))
+        .collect();
+    let changed: Vec<&LeaderboardSnapshot> = current
+        .iter()
+        .filter(|s| previous_ranks.get(&(s.player_id, s.char_id)) != Some(&s.rank))
+        .collect();
+
+    let changed_ids: std::collections::HashSet<i64> = changed.iter().map(|s| s.player_id).collect();
+    let private_players = crate::db::get_private_players(changed_ids.clone(), connection).await?;
+    let followed_ids = changed_ids.difference(&private_players).copied().collect();
+    let followers = crate::db::get_webhook_followers(followed_ids, connection).await?;
+
+    let queued_at = Utc::now().naive_utc();
+    let mut deliveries = Vec::new();
+    for snapshot in changed {
+        for (follower, followed) in &followers {
+            if *followed == snapshot.player_id {
+                deliveries.push(NewWebhookDelivery {
+                    player_id: *follower,
+                    payload: rank_message(
+                        &crate::handlers::common::SITE_URL,
+                        snapshot.player_id,
+                        &snapshot.name,
+                        snapshot.char_id,
+                        snapshot.rank,
+                        previous_ranks.get(&(snapshot.player_id, snapshot.char_id)).copied(),
+                    ),
+                    next_attempt: queued_at,
+                });
+            }
+        }
+    }
+
+    info!("Webhooks: {} rank deliveries queued", deliveries.len());
+    crate::db::enqueue_webhook_deliveries(deliveries, connection).await
+}
+
+async fn deliver_webhooks(connection: &mut crate::Connection<'_>) -> Result<(), Error> {
+    use crate::handlers::webhook::{backoff, deliver, MAX_ATTEMPTS};
+
+    let at = Utc::now().naive_utc();
+    let lease_until = at + chrono::Duration::seconds(WEBHOOK_LEASE_SECONDS);
+    let due = crate::db::claim_due_webhook_deliveries(at, lease_until, WEBHOOK_BATCH, connection).await?;
+
+    for (delivery, webhook) in due {
+        match deliver(&webhook.url, &webhook.secret, &delivery.payload).await {
+            Ok(()) => {
+                crate::db::delete_webhook_delivery(delivery.id, connection).await?;
+            }
+            Err(e) if delivery.attempts + 1 >= MAX_ATTEMPTS => {
+                warn!(
+                    "Webhook delivery {} to player {} dropped after {} attempts: {e}",
+                    delivery.id, delivery.player_id, MAX_ATTEMPTS
+                );
+                crate::db::delete_webhook_delivery(delivery.id, connection).await?;
+            }
+            Err(e) => {
+                debug!("Webhook delivery {} failed: {e}", delivery.id);
+                let attempts = delivery.attempts + 1;
+                crate::db::reschedule_webhook_delivery(
+                    delivery.id,
+                    attempts,
+                    Utc::now().naive_utc() + backoff(attempts),
+                    e,
+                    connection,
+                )
+                .await?;
+            }
+        }
+    }
 
     Ok(())
 }
 
+fn replay_page_budget() -> usize {
+    dotenv::var("REPLAY_PAGE_BUDGET")
+        .ok()
+        .and_then(|b| b.parse().ok())
+        .unwrap_or(DEFAULT_REPLAY_PAGE_BUDGET)
+}
+
+fn replay_shards_per_run() -> usize {
+    dotenv::var("REPLAY_SHARDS_PER_RUN")
+        .ok()
+        .and_then(|b| b.parse().ok())
+        .unwrap_or(DEFAULT_SHARDS_PER_RUN)
+}
+
+/// A filtered replay query, so segments the unfiltered one crowds out still get read
+#[derive(Clone)]
+struct ReplayShard {
+    name: String,
+    query: ReplayQuery,
+}
+
+/// Rank bands, Tower floors and one shard per character, the pull loop goes through them in order
+fn replay_shard_schedule() -> Vec<ReplayShard> {
+    let rank_bands = [(0, 9), (10, 15), (16, 19), (20, 22)]
+        .into_iter()
+        .map(|(min, max)| ReplayShard {
+            name: format!("ranks {min}-{max}"),
+            query: ReplayQuery::ranks(min, max),
+        });
+
+    let floors = [(1, 5), (6, 9), (10, 10), (99, 99)]
+        .into_iter()
+        .map(|(min, max)| ReplayShard {
+            name: format!("floors {min}-{max}"),
+            query: ReplayQuery::floors(min, max),
+        });
+
+    let characters = CHAR_NAMES.iter().enumerate().map(|(char_id, (short, _))| ReplayShard {
+        name: format!("char {short}"),
+        query: ReplayQuery::character(char_id as i64),
+    });
+
+    rank_bands.chain(floors).chain(characters).collect()
+}
+
+fn replay_to_game(r: &crate::responses::Replay) -> Result<Game, Error> {
+    let bad = |field: &str| Error::Internal(format!("Bad {field} in replay: {r:?}"));
+
+    Ok(Game {
+        timestamp: NaiveDateTime::parse_from_str(&r.timestamp, "%Y-%m-%d %H:%M:%S").map_err(|_| bad("timestamp"))?,
+        real_timestamp: None,
+        id_a: r.player1.id.parse::<i64>().map_err(|_| bad("player1 id"))?,
+        name_a: r.player1.name.clone(),
+        char_a: i16::try_from(r.player1_character).map_err(|_| bad("player1_character"))?,
+        platform_a: i16::try_from(r.player1.platform).map_err(|_| bad("player1 platform"))?,
+        id_b: r.player2.id.parse::<i64>().map_err(|_| bad("player2 id"))?,
+        name_b: r.player2.name.clone(),
+        char_b: i16::try_from(r.player2_character).map_err(|_| bad("player2_character"))?,
+        platform_b: i16::try_from(r.player2.platform).map_err(|_| bad("player2 platform"))?,
+        winner: i16::try_from(r.winner).map_err(|_| bad("winner"))?,
+        game_floor: i16::try_from(r.floor).map_err(|_| bad("floor"))?,
+        value_a: r.player1.rating,
+        value_b: r.player2.rating,
+    })
+}
+
+type GameKey = (NaiveDateTime, i64, i64);
+
+fn game_key(game: &Game) -> GameKey {
+    (game.timestamp, game.id_a, game.id_b)
+}
+
+/// How many of `keys` are already in the games table
+async fn count_known_games(connection: &mut AsyncPgConnection, keys: &[GameKey]) -> Result<usize, Error> {
+    let timestamps: Vec<NaiveDateTime> = keys.iter().map(|k| k.0).collect();
+
+    let known: std::collections::HashSet<GameKey> = games::table
+        .select((games::timestamp, games::id_a, games::id_b))
+        .filter(games::timestamp.eq_any(timestamps))
+        .load::<GameKey>(connection)
+        .await?
+        .into_iter()
+        .collect();
+
+    Ok(keys.iter().filter(|k| known.contains(k)).count())
+}
+
+/// Walks replay pages from the newest until one has games we already have, at most `budget` pages.
+/// Returns the games oldest first and whether it reached known games. Games that show up on two pages
+/// because new ones pushed them down while walking are only returned once.
+async fn fetch_new_games(
+    connection: &mut AsyncPgConnection,
+    api: &dyn GgstApi,
+    query: &ReplayQuery,
+    budget: usize,
+) -> Result<(Vec<Game>, bool), Error> {
+    let mut new_games = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+
+    for index in 0..budget {
+        let page = match api.get_replay_page(index, query).await {
+            Ok(page) => page,
+            Err(e) if index == 0 => return Err(e),
+            Err(e) => {
+                error!("Replay page {index} failed, keeping the first {index}: {e}");
+                break;
+            }
+        };
+
+        //Nothing older to read, so nothing was missed either
+        if page.is_empty() {
+            new_games.reverse();
+            return Ok((new_games, true));
+        }
+
+        let page: Vec<Game> = page
+            .iter()
+            .filter_map(|r| replay_to_game(r).inspect_err(|e| error!("{e}")).ok())
+            .filter(|g| seen.insert(game_key(g)))
+            .collect();
+
+        let keys: Vec<GameKey> = page.iter().map(game_key).collect();
+        let known = count_known_games(connection, &keys).await?;
+        debug!("Replay page {index}: {} games, {known} known", page.len());
+
+        new_games.extend(page);
+
+        if known > 0 {
+            new_games.reverse();
+            return Ok((new_games, true));
+        }
+    }
+
+    new_games.reverse();
+    Ok((new_games, false))
+}
+
 async fn grab_games(
     connection: &mut AsyncPgConnection,
     redis_connection: &mut crate::RedisConnection<'_>,
-) -> Result<Vec<Game>, String> {
+    api: &dyn GgstApi,
+    shards: &[ReplayShard],
+) -> Result<Vec<Game>, Error> {
     info!("Grabbing replays");
 
-    let replays = ggst_api::get_replays().await;
-
-    let mut replays = match replays {
-        Ok(replays) => replays,
-        Err(e) => {
-            return Err(e);
-        }
-    };
+    let budget = replay_page_budget();
+    let (replays, overlapped) = fetch_new_games(connection, api, &ReplayQuery::default(), budget).await?;
 
     let num_replays = replays.len();
     info!("Got {num_replays} replays.");
 
-    replays.reverse();
+    //Everything we read was new, there are probably games between these and the ones we have
+    if !overlapped
+        && let Some(oldest) = replays.as_slice().first()
+    {
+        warn!("No known games in {budget} replay pages, games older than {} may be missing", oldest.timestamp);
+        if let Err(e) = crate::imdb::record_replay_gap(oldest.timestamp, redis_connection).await {
+            error!("record_replay_gap failed: {e}");
+        }
+    }
+
+    //Source of every game, 0 for the unfiltered query and i + 1 for shards[i]
+    let mut seen: std::collections::HashSet<GameKey> = replays.iter().map(game_key).collect();
+    let mut replays: Vec<(Game, usize)> = replays.into_iter().map(|g| (g, 0)).collect();
+    let mut fetched = vec![num_replays];
+
+    for (i, shard) in shards.iter().enumerate() {
+        let shard_games = match fetch_new_games(connection, api, &shard.query, SHARD_PAGE_BUDGET).await {
+            Ok((shard_games, _)) => shard_games,
+            Err(e) => {
+                error!("Replay shard {} failed: {e}", shard.name);
+                vec![]
+            }
+        };
+
+        fetched.push(shard_games.len());
+        replays.extend(
+            shard_games
+                .into_iter()
+                .filter(|g| seen.insert(game_key(g)))
+                .map(|g| (g, i + 1)),
+        );
+    }
+
+    //Oldest first across all queries, so player info ends up from the latest game
+    replays.sort_by_key(|(g, _)| g.timestamp);
 
     let mut new_games = Vec::new();
+    let mut inserted = vec![0; fetched.len()];
 
     //Try to keep order if possible
     let mut seconds_offset = 0;
 
-    for r in replays {
-        let game_timestamp =
-            NaiveDateTime::parse_from_str(&r.timestamp, "%Y-%m-%d %H:%M:%S").unwrap();
-
-        let fixed_timestamp;
-
+    for (mut new_game, source) in replays {
         // If the game_timestamp is more than 2 seconds in the future, set it to current time
-        if game_timestamp > Utc::now().naive_utc()
-            && game_timestamp > Utc::now().naive_utc() + chrono::Duration::seconds(2)
+        if new_game.timestamp > Utc::now().naive_utc()
+            && new_game.timestamp > Utc::now().naive_utc() + chrono::Duration::seconds(2)
         {
-            fixed_timestamp = Some(chrono::SubsecRound::round_subsecs(
+            new_game.real_timestamp = Some(chrono::SubsecRound::round_subsecs(
                 Utc::now().naive_utc() + chrono::Duration::seconds(seconds_offset),
                 0,
             ));
-        } else {
-            fixed_timestamp = None;
         }
 
-        let new_game = Game {
-            timestamp: game_timestamp,
-            real_timestamp: fixed_timestamp,
-            id_a: r.player1.id.parse::<i64>().unwrap(),
-            name_a: r.player1.name.clone(),
-            char_a: i16::try_from(r.player1_character).ok().unwrap(),
-            platform_a: i16::try_from(r.player1.platform).ok().unwrap(),
-            id_b: r.player2.id.parse::<i64>().unwrap(),
-            name_b: r.player2.name.clone(),
-            char_b: i16::try_from(r.player2_character).ok().unwrap(),
-            platform_b: i16::try_from(r.player2.platform).ok().unwrap(),
-            winner: i16::try_from(r.winner).ok().unwrap(),
-            game_floor: i16::try_from(r.floor).ok().unwrap(),
-            value_a: r.player1.rating,
-            value_b: r.player2.rating,
-        };
-
-        if let Err(e) = update_player_info(connection, &new_game).await {
-            error!("update_player_info failed: {e}");
-        }
+        update_player_info(connection, &new_game).await?;
 
         let count = insert_into(games::table)
             .values(&new_game)
             .on_conflict_do_nothing()
             .execute(connection)
-            .await
-            .unwrap();
+            .await?;
 
         if count > 0 {
-            if fixed_timestamp.is_some() {
+            inserted[source] += 1;
+
+            if let Some(fixed_timestamp) = new_game.real_timestamp {
                 seconds_offset += 1;
                 debug!(
                     "Fixing timestamp {} -> {} - {} vs {}",
-                    r.timestamp,
-                    fixed_timestamp.unwrap(),
-                    r.player1.name,
-                    r.player2.name
+                    new_game.timestamp,
+                    fixed_timestamp,
+                    new_game.name_a,
+                    new_game.name_b
                 );
             }
 
@@ -1238,15 +1506,414 @@ async fn grab_games(
         }
     }
 
+    info!("Replay query all: {} replays, {} new", fetched[0], inserted[0]);
+    crate::metrics::record_replays("all", fetched[0], inserted[0]);
+    for (i, shard) in shards.iter().enumerate() {
+        info!("Replay shard {}: {} replays, {} new", shard.name, fetched[i + 1], inserted[i + 1]);
+        crate::metrics::record_replays(&shard.name, fetched[i + 1], inserted[i + 1]);
+    }
+
     //Set set_latest_game_time for health check
     if let Some(last_game) = new_games.last() {
         let ts = last_game.real_timestamp.unwrap_or(last_game.timestamp);
 
-        crate::imdb::set_latest_game_time(ts, redis_connection)
-            .await
-            .unwrap();
+        crate::imdb::set_latest_game_time(ts, redis_connection).await?;
     }
 
     info!("Grabbing replays - Done");
     Ok(new_games)
 }
+
+/// Inserts games from the replay pages back to the newest game we had before the last recorded gap,
+/// or every page the API serves without one, at most `max_pages`.
+/// These are old games, so players, names and ratings the puller already has are left alone.
+pub async fn backfill(state: crate::AppState, max_pages: Option<usize>) -> Result<(), Error> {
+    let target = backfill_target(&state).await?;
+    match target {
+        Some(target) => info!("Backfill - Reading back to {target}"),
+        None => info!("Backfill - No replay gap recorded, reading every page"),
+    }
+
+    backfill_until(state, target, max_pages).await
+}
+
+/// The newest game from before the last gap `grab_games` recorded, the missing games are after it
+async fn backfill_target(state: &crate::AppState) -> Result<Option<NaiveDateTime>, Error> {
+    let mut redis = state.redis_pool.get().await?;
+    let gap = match crate::imdb::get_last_replay_gap(&mut redis).await {
+        Ok(gap) => gap,
+        Err(Error::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut connection = state.db_pool.get().await?;
+    Ok(games::table
+        .select(max(games::timestamp))
+        .filter(games::timestamp.lt(gap))
+        .first::<Option<NaiveDateTime>>(&mut connection)
+        .await?)
+}
+
+/// Reads pages until one goes back past `target`. New games push the pages back while this runs, so a
+/// page can repeat games already read; only `MAX_STALLED_BACKFILL_PAGES` in a row that get no further
+/// back end the walk early, which is also how the API serving its last page again looks.
+async fn backfill_until(
+    state: crate::AppState,
+    target: Option<NaiveDateTime>,
+    max_pages: Option<usize>,
+) -> Result<(), Error> {
+    let mut connection = state.db_pool.get().await?;
+    let mut seen = std::collections::HashSet::new();
+    let mut oldest_read: Option<NaiveDateTime> = None;
+    let mut stalled = 0;
+    let mut inserted = 0;
+
+    for index in 0..max_pages.unwrap_or(usize::MAX) {
+        let page = match state.ggst.get_replay_page(index, &ReplayQuery::default()).await {
+            Ok(page) => page,
+            Err(e) => {
+                error!("Replay page {index} failed, stopping: {e}");
+                break;
+            }
+        };
+
+        let page: Vec<Game> = page
+            .iter()
+            .filter_map(|r| replay_to_game(r).inspect_err(|e| error!("{e}")).ok())
+            .collect();
+
+        let Some(oldest) = page.iter().map(|g| g.timestamp).min() else {
+            info!("No more replays after page {index}");
+            break;
+        };
+
+        let new_games: Vec<Game> = page.into_iter().filter(|g| seen.insert(game_key(g))).collect();
+        let count = match connection
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                async move { insert_backfill_games(conn, &new_games).await }.scope_boxed()
+            })
+            .await
+        {
+            Ok(count) => count,
+            Err(e) => {
+                error!("Backfill of page {index} failed, stopping: {e}");
+                break;
+            }
+        };
+
+        inserted += count;
+        info!("Backfill page {index}: {count} new games ({inserted} total), back to {oldest}");
+
+        if target.is_some_and(|target| oldest < target) {
+            info!("Reached games from before the gap on page {index}");
+            break;
+        }
+
+        if oldest_read.is_some_and(|oldest_read| oldest >= oldest_read) {
+            stalled += 1;
+            if stalled >= MAX_STALLED_BACKFILL_PAGES {
+                info!("No older replays in {stalled} pages after page {}", index - stalled);
+                break;
+            }
+        } else {
+            stalled = 0;
+            oldest_read = Some(oldest);
+        }
+    }
+
+    info!("Backfill - Done, {inserted} new games");
+    Ok(())
+}
+
+async fn insert_backfill_games(
+    connection: &mut AsyncPgConnection,
+    new_games: &[Game],
+) -> Result<usize, diesel::result::Error> {
+    let mut count = 0;
+
+    for game in new_games {
+        for (id, name, platform) in [
+            (game.id_a, &game.name_a, game.platform_a),
+            (game.id_b, &game.name_b, game.platform_b),
+        ] {
+            insert_into(players::table)
+                .values(&Player {
+                    id,
+                    name: name.clone(),
+                    platform,
+                    api_key: None,
+                    rcode_check_code: None,
+                    private: false,
+                })
+                .on_conflict_do_nothing()
+                .execute(connection)
+                .await?;
+
+            insert_into(player_names::table)
+                .values(&PlayerName { id, name: name.clone() })
+                .on_conflict_do_nothing()
+                .execute(connection)
+                .await?;
+        }
+
+        count += insert_into(games::table)
+            .values(game)
+            .on_conflict_do_nothing()
+            .execute(connection)
+            .await?;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ggst_api::{fixtures, FixtureApi};
+    use crate::responses::LeaderboardEntry;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn grab_games_walks_pages_until_known_games() {
+        let dir = fixtures::dir("grab-games");
+        let query = ReplayQuery::default();
+        // Fresh players every run, the scratch database keeps what other runs committed
+        let id = 800_000_000_000_000 + rand::random::<u32>() as i64 * 10;
+        let game = |timestamp: &str, a: i64, b: i64| {
+            fixtures::replay(timestamp, 99, (id + a, "A", 0, 10001500), (id + b, "B", 1, 10001400), 1)
+        };
+
+        fixtures::write_replay_page(&dir, 0, &query, vec![game("2026-01-01 12:03:00", 1, 2), game("2026-01-01 12:02:00", 1, 2)]);
+        fixtures::write_replay_page(&dir, 1, &query, vec![game("2026-01-01 12:01:00", 3, 4)]);
+
+        let Some(state) = crate::test_state(Arc::new(FixtureApi::new(dir.clone()))).await else {
+            return;
+        };
+        let mut connection = state.db_pool.get().await.unwrap();
+        connection.begin_test_transaction().await.unwrap();
+        let mut redis = state.redis_pool.get().await.unwrap();
+
+        // Page 2 doesn't exist, so the walk ends without reaching known games
+        let new_games = grab_games(&mut connection, &mut redis, &*state.ggst, &[]).await.unwrap();
+        let timestamps: Vec<String> = new_games.iter().map(|g| g.timestamp.to_string()).collect();
+        assert_eq!(timestamps, vec!["2026-01-01 12:01:00", "2026-01-01 12:02:00", "2026-01-01 12:03:00"]);
+
+        let last_gap: String = redis::cmd("GET").arg("last_replay_gap").query_async(&mut *redis).await.unwrap();
+        assert_eq!(last_gap, "2026-01-01 12:01:00");
+
+        // One new game on top, page 0 now reaches known games and page 1 isn't read again
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        fixtures::write_replay_page(&dir, 0, &query, vec![game("2026-01-01 12:04:00", 1, 2), game("2026-01-01 12:03:00", 1, 2)]);
+
+        let new_games = grab_games(&mut connection, &mut redis, &*state.ggst, &[]).await.unwrap();
+        assert_eq!(new_games.len(), 1);
+        assert_eq!(new_games[0].timestamp.to_string(), "2026-01-01 12:04:00");
+    }
+
+    #[tokio::test]
+    async fn backfill_reads_back_past_the_target() {
+        let dir = fixtures::dir("backfill");
+        let query = ReplayQuery::default();
+        let id = 850_000_000_000_000 + rand::random::<u32>() as i64 * 10;
+        let game = |timestamp: &str, a: i64, b: i64| {
+            fixtures::replay(timestamp, 99, (id + a, "A", 0, 10001500), (id + b, "B", 1, 10001400), 1)
+        };
+
+        fixtures::write_replay_page(&dir, 0, &query, vec![game("2026-01-01 12:05:00", 1, 2), game("2026-01-01 12:04:00", 1, 2)]);
+        // New games pushed page 0 back while backfill ran
+        fixtures::write_replay_page(&dir, 1, &query, vec![game("2026-01-01 12:04:00", 1, 2)]);
+        fixtures::write_replay_page(&dir, 2, &query, vec![game("2026-01-01 12:03:00", 3, 4)]);
+        fixtures::write_replay_page(&dir, 3, &query, vec![game("2026-01-01 12:02:00", 1, 2), game("2026-01-01 12:00:00", 3, 4)]);
+        fixtures::write_replay_page(&dir, 4, &query, vec![game("2026-01-01 11:59:00", 5, 6)]);
+
+        let Some(state) = crate::test_state(Arc::new(FixtureApi::new(dir))).await else {
+            return;
+        };
+        let target = NaiveDateTime::parse_from_str("2026-01-01 12:01:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        backfill_until(state.clone(), Some(target), None).await.unwrap();
+
+        let ids: Vec<i64> = (1..=6).map(|i| id + i).collect();
+        let mut connection = state.db_pool.get().await.unwrap();
+        let timestamps: Vec<NaiveDateTime> = games::table
+            .select(games::timestamp)
+            .filter(games::id_a.eq_any(&ids))
+            .order(games::timestamp)
+            .load(&mut connection)
+            .await
+            .unwrap();
+
+        diesel::delete(games::table.filter(games::id_a.eq_any(&ids))).execute(&mut connection).await.unwrap();
+        diesel::delete(player_names::table.filter(player_names::id.eq_any(&ids))).execute(&mut connection).await.unwrap();
+        diesel::delete(players::table.filter(players::id.eq_any(&ids))).execute(&mut connection).await.unwrap();
+
+        let timestamps: Vec<String> = timestamps.iter().map(|t| t.format("%H:%M").to_string()).collect();
+        assert_eq!(timestamps, vec!["12:00", "12:02", "12:03", "12:04", "12:05"]);
+    }
+
+    #[tokio::test]
+    async fn game_webhooks_hide_private_opponents() {
+        fixtures::init_env();
+        let Some(state) = crate::test_state(Arc::new(FixtureApi::new(fixtures::dir("game-webhooks")))).await else {
+            return;
+        };
+        let mut connection = state.db_pool.get().await.unwrap();
+        connection.begin_test_transaction().await.unwrap();
+
+        // 1 is private and plays 2, whom 3 follows
+        let id = 820_000_000_000_000 + rand::random::<u32>() as i64 * 10;
+        for (offset, name, private) in [(1, "Secret", true), (2, "Followed", false), (3, "Follower", false)] {
+            insert_into(players::table)
+                .values(&Player {
+                    id: id + offset,
+                    name: name.to_string(),
+                    platform: 3,
+                    api_key: None,
+                    rcode_check_code: None,
+                    private,
+                })
+                .execute(&mut connection)
+                .await
+                .unwrap();
+        }
+        insert_into(schema::follows::table)
+            .values(&Follow { player_id: id + 3, followed_id: id + 2 })
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        insert_into(schema::webhooks::table)
+            .values(&Webhook { player_id: id + 3, url: "https://example.com/hook".to_string(), secret: "secret".to_string() })
+            .execute(&mut connection)
+            .await
+            .unwrap();
+
+        let game = Game {
+            timestamp: Utc::now().naive_utc(),
+            id_a: id + 1,
+            name_a: "Secret".to_string(),
+            char_a: 0,
+            platform_a: 3,
+            id_b: id + 2,
+            name_b: "Followed".to_string(),
+            char_b: 1,
+            platform_b: 3,
+            winner: 2,
+            game_floor: 99,
+            value_a: 10001500,
+            value_b: 10001480,
+            real_timestamp: None,
+        };
+        enqueue_game_webhooks(&[game], &mut connection).await.unwrap();
+
+        let payloads: Vec<String> = schema::webhook_deliveries::table
+            .select(schema::webhook_deliveries::payload)
+            .filter(schema::webhook_deliveries::player_id.eq(id + 3))
+            .load(&mut connection)
+            .await
+            .unwrap();
+        assert_eq!(payloads.len(), 1);
+        assert!(payloads[0].contains(crate::handlers::common::PRIVATE_PLAYER_NAME), "{}", payloads[0]);
+        assert!(!payloads[0].contains("Secret"), "{}", payloads[0]);
+    }
+
+    #[tokio::test]
+    async fn claimed_webhook_deliveries_are_skipped_until_the_lease_ends() {
+        let Some(state) = crate::test_state(Arc::new(FixtureApi::new(fixtures::dir("webhook-claims")))).await else {
+            return;
+        };
+        let mut connection = state.db_pool.get().await.unwrap();
+        connection.begin_test_transaction().await.unwrap();
+
+        let id = 840_000_000_000_000 + rand::random::<u32>() as i64;
+        insert_into(players::table)
+            .values(&Player {
+                id,
+                name: "Follower".to_string(),
+                platform: 3,
+                api_key: None,
+                rcode_check_code: None,
+                private: false,
+            })
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        insert_into(schema::webhooks::table)
+            .values(&Webhook { player_id: id, url: "https://example.com/hook".to_string(), secret: "secret".to_string() })
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        // Everything else waiting in the scratch database is due far later than these
+        let at = Utc::now().naive_utc() - chrono::Duration::days(365 * 100);
+        let payloads = ["first", "second"].map(|payload| crate::models::NewWebhookDelivery {
+            player_id: id,
+            payload: payload.to_string(),
+            next_attempt: at - chrono::Duration::minutes(1),
+        });
+        insert_into(schema::webhook_deliveries::table)
+            .values(&payloads[..])
+            .execute(&mut connection)
+            .await
+            .unwrap();
+
+        let lease = chrono::Duration::seconds(WEBHOOK_LEASE_SECONDS);
+        let claimed = |claimed: Vec<(crate::models::WebhookDelivery, Webhook)>| {
+            claimed.into_iter().map(|(delivery, webhook)| (delivery.payload, webhook.url)).collect::<Vec<_>>()
+        };
+        let url = "https://example.com/hook".to_string();
+
+        let first = crate::db::claim_due_webhook_deliveries(at, at + lease, 10, &mut connection).await.unwrap();
+        assert_eq!(claimed(first), vec![("first".to_string(), url.clone()), ("second".to_string(), url)]);
+        let again = crate::db::claim_due_webhook_deliveries(at, at + lease, 10, &mut connection).await.unwrap();
+        assert!(again.is_empty());
+        // Never delivered or rescheduled, due again once the lease is over
+        let later = crate::db::claim_due_webhook_deliveries(at + lease, at + lease * 2, 10, &mut connection)
+            .await
+            .unwrap();
+        assert_eq!(later.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn sync_global_leaderboards_ranks_lp_after_mr() {
+        let dir = fixtures::dir("global-leaderboards");
+        fixtures::write_mr_page(
+            &dir,
+            0,
+            -1,
+            vec![fixtures::mr_player((1, 2000, 0, 1, "A")), fixtures::mr_player((2, 1900, 1, 2, "B"))],
+        );
+        // B on the same character is already on the MR board
+        fixtures::write_lp_page(
+            &dir,
+            0,
+            -1,
+            vec![fixtures::lp_player((1, 90000, 1, 2, "B")), fixtures::lp_player((2, 80000, 2, 3, "C"))],
+        );
+        // No per character pages, those boards fail and are skipped
+
+        let Some(state) = crate::test_state(Arc::new(FixtureApi::new(dir))).await else {
+            return;
+        };
+        let mut connection = state.db_pool.get().await.unwrap();
+        connection.begin_test_transaction().await.unwrap();
+        let mut redis = state.redis_pool.get().await.unwrap();
+
+        sync_global_leaderboards(&mut connection, &mut redis, &*state.ggst).await.unwrap();
+
+        let json: String = redis::cmd("GET").arg("leaderboard_all").query_async(&mut *redis).await.unwrap();
+        let leaderboard: Vec<LeaderboardEntry> = serde_json::from_str(&json).unwrap();
+        let ranks: Vec<(i64, &str, i64)> = leaderboard
+            .iter()
+            .map(|e| (e.rank, e.player_id.as_str(), e.rating))
+            .collect();
+        assert_eq!(ranks, vec![(1, "1", 10002000), (2, "2", 10001900), (3, "3", 80000)]);
+
+        let snapshot: i64 = schema::leaderboard_snapshots::table
+            .filter(schema::leaderboard_snapshots::board.eq("all"))
+            .filter(schema::leaderboard_snapshots::snapshot_date.eq(Utc::now().date_naive()))
+            .count()
+            .get_result(&mut connection)
+            .await
+            .unwrap();
+        assert_eq!(snapshot, 3);
+    }
+}
@@ -0,0 +1,242 @@
+//! Approximation of the in-game rating change for a single ranked match.
+//!
+//! Ratings come in two regimes, the same way they are stored in `games` and `player_ratings`:
+//! - LP (League Points) below Vanquisher, stored as is.
+//! - MR/DR (Master/Dominion Rating) for Vanquisher and up, stored offset by `VANQUISHER_OFFSET`.
+//!
+//! Drift is a per-player multiplier on the size of a rating change. It is always > 1.0 and
+//! decays toward 1.0 as the player keeps playing, so settled players move less per game.
+
+pub const VANQUISHER_OFFSET: f64 = 10_000_000.0;
+
+/// Rating change between two evenly matched MR players is `MR_K / 2` at drift 1.0
+const MR_K: f64 = 20.0;
+/// Rating change between two evenly matched LP players is `LP_K / 2` at drift 1.0
+const LP_K: f64 = 600.0;
+/// LP per point of Elo-like strength, so the top of the LP ladder lines up with a fresh Vanquisher
+const LP_PER_STRENGTH: f64 = 30.0;
+/// Elo logistic scale
+const STRENGTH_SCALE: f64 = 400.0;
+/// Fraction of (drift - 1.0) that is kept after every game
+const DRIFT_DECAY: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Regime {
+    Lp,
+    Mr,
+}
+
+impl Regime {
+    pub fn of(rating: f64) -> Regime {
+        if rating >= VANQUISHER_OFFSET {
+            Regime::Mr
+        } else {
+            Regime::Lp
+        }
+    }
+
+    fn k(self) -> f64 {
+        match self {
+            Regime::Lp => LP_K,
+            Regime::Mr => MR_K,
+        }
+    }
+
+    fn floor(self) -> f64 {
+        match self {
+            Regime::Lp => 0.0,
+            Regime::Mr => VANQUISHER_OFFSET,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RatingChange {
+    pub rating_a_new: f64,
+    pub drift_a_new: f64,
+    pub rating_b_new: f64,
+    pub drift_b_new: f64,
+    pub win_prob: f64,
+}
+
+/// Puts both regimes on one Elo-like scale so cross-regime matches get a sensible win probability.
+pub fn strength(rating: f64) -> f64 {
+    match Regime::of(rating) {
+        Regime::Lp => rating / LP_PER_STRENGTH,
+        Regime::Mr => rating - VANQUISHER_OFFSET,
+    }
+}
+
+/// Probability that a player rated `rating_a` beats a player rated `rating_b`.
+pub fn win_probability(rating_a: f64, rating_b: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((strength(rating_b) - strength(rating_a)) / STRENGTH_SCALE))
+}
+
+pub fn next_drift(drift: f64) -> f64 {
+    1.0 + (drift - 1.0) * DRIFT_DECAY
+}
+
+fn next_rating(rating: f64, drift: f64, score: f64, expected: f64) -> f64 {
+    let regime = Regime::of(rating);
+    let new_rating = (rating + regime.k() * drift * (score - expected)).round();
+
+    // Losing never drops a player out of their regime, demotion is handled by the game.
+    new_rating.max(regime.floor())
+}
+
+fn validate(rating: f64, drift: f64, name: &str) -> Result<(), String> {
+    if !rating.is_finite() || rating < 0.0 {
+        return Err(format!("rating_{} must be a positive number", name));
+    }
+    if !drift.is_finite() || drift <= 1.0 {
+        return Err(format!("drift_{} must be greater than 1", name));
+    }
+    Ok(())
+}
+
+pub fn calc_rating(
+    rating_a: f64,
+    drift_a: f64,
+    rating_b: f64,
+    drift_b: f64,
+    a_wins: bool,
+) -> Result<RatingChange, String> {
+    validate(rating_a, drift_a, "a")?;
+    validate(rating_b, drift_b, "b")?;
+
+    let win_prob = win_probability(rating_a, rating_b);
+    let (score_a, score_b) = if a_wins { (1.0, 0.0) } else { (0.0, 1.0) };
+
+    Ok(RatingChange {
+        rating_a_new: next_rating(rating_a, drift_a, score_a, win_prob),
+        drift_a_new: next_drift(drift_a),
+        rating_b_new: next_rating(rating_b, drift_b, score_b, 1.0 - win_prob),
+        drift_b_new: next_drift(drift_b),
+        win_prob,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (rating before, opponent rating, won, rating after), hand-written to pin the model's behaviour.
+    /// They are not observed games. Replace them with the `pair` column of `scripts/mine_rating_pairs.sql`
+    /// run on a copy of production, with the date of the copy, once one is at hand.
+    const PAIRS: &[(f64, f64, bool, f64)] = &[
+        // MR vs MR
+        (10001512.0, 10001498.0, true, 10001522.0),
+        (10001522.0, 10001601.0, false, 10001515.0),
+        (10001640.0, 10001402.0, true, 10001643.0),
+        (10001640.0, 10001402.0, false, 10001624.0),
+        (10001733.0, 10001790.0, true, 10001745.0),
+        // LP vs LP
+        (24450.0, 23980.0, true, 24720.0),
+        (24720.0, 28100.0, false, 24540.0),
+        (36810.0, 36950.0, false, 36500.0),
+        (8810.0, 11240.0, true, 9170.0),
+        (40900.0, 38200.0, true, 41130.0),
+    ];
+
+    /// How far the model may be from an observed pair, as a fraction of the regime's K
+    const TOLERANCE: f64 = 0.25;
+
+    fn check_pair(before: f64, opponent: f64, won: bool, after: f64) {
+        let change = calc_rating(before, 1.0001, opponent, 1.0001, won).unwrap();
+        let observed = after - before;
+        let predicted = change.rating_a_new - before;
+
+        assert_eq!(
+            observed.signum(),
+            predicted.signum(),
+            "{before} vs {opponent}: observed {observed}, predicted {predicted}"
+        );
+        assert!(
+            (observed - predicted).abs() <= Regime::of(before).k() * TOLERANCE,
+            "{before} vs {opponent}: observed {observed}, predicted {predicted}"
+        );
+    }
+
+    #[test]
+    fn calc_rating_matches_sample_pairs() {
+        for &(before, opponent, won, after) in PAIRS {
+            check_pair(before, opponent, won, after);
+        }
+    }
+
+    #[derive(diesel::QueryableByName)]
+    struct MinedPair {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        before: i64,
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        opponent_value: i64,
+        #[diesel(sql_type = diesel::sql_types::Bool)]
+        won: bool,
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        after: i64,
+    }
+
+    /// Every pair `scripts/mine_rating_pairs.sql` finds in RATING_PAIRS_DATABASE_URL, a copy of production.
+    /// The scratch TEST_DATABASE_URL has no real games, so it isn't used here.
+    #[tokio::test]
+    async fn calc_rating_matches_mined_pairs() {
+        use diesel_async::{AsyncConnection, RunQueryDsl};
+
+        let Ok(database_url) = std::env::var("RATING_PAIRS_DATABASE_URL") else {
+            eprintln!("RATING_PAIRS_DATABASE_URL not set, skipping");
+            return;
+        };
+        let mut db = diesel_async::AsyncPgConnection::establish(&database_url).await.unwrap();
+
+        let pairs = diesel::sql_query(include_str!("../scripts/mine_rating_pairs.sql"))
+            .load::<MinedPair>(&mut db)
+            .await
+            .unwrap();
+        assert!(!pairs.is_empty(), "No rating pairs in RATING_PAIRS_DATABASE_URL, is it a copy of production?");
+
+        for pair in pairs {
+            check_pair(pair.before as f64, pair.opponent_value as f64, pair.won, pair.after as f64);
+        }
+    }
+
+    #[test]
+    fn calc_rating_even_match() {
+        let change = calc_rating(10001500.0, 1.0001, 10001500.0, 1.0001, true).unwrap();
+
+        assert_eq!(change.win_prob, 0.5);
+        assert_eq!(change.rating_a_new, 10001510.0);
+        assert_eq!(change.rating_b_new, 10001490.0);
+    }
+
+    #[test]
+    fn calc_rating_drift_scales_change() {
+        let settled = calc_rating(24000.0, 1.0001, 24000.0, 1.0001, true).unwrap();
+        let drifting = calc_rating(24000.0, 2.0, 24000.0, 1.0001, true).unwrap();
+
+        assert!(drifting.rating_a_new - 24000.0 > settled.rating_a_new - 24000.0);
+        assert!(drifting.drift_a_new > 1.0 && drifting.drift_a_new < 2.0);
+    }
+
+    #[test]
+    fn calc_rating_mr_floor() {
+        let change = calc_rating(10000005.0, 1.5, 10000001.0, 1.5, false).unwrap();
+
+        assert_eq!(change.rating_a_new, VANQUISHER_OFFSET);
+    }
+
+    #[test]
+    fn calc_rating_cross_regime() {
+        let change = calc_rating(10001500.0, 1.0001, 30000.0, 1.0001, true).unwrap();
+
+        assert!(change.win_prob > 0.5);
+        assert_eq!(Regime::of(change.rating_a_new), Regime::Mr);
+        assert_eq!(Regime::of(change.rating_b_new), Regime::Lp);
+    }
+
+    #[test]
+    fn calc_rating_invalid_drift() {
+        assert!(calc_rating(1000.0, 1.0, 1000.0, 1.5, true).is_err());
+        assert!(calc_rating(1000.0, 1.5, 1000.0, 0.5, true).is_err());
+        assert!(calc_rating(1000.0, f64::NAN, 1000.0, 1.5, true).is_err());
+    }
+}
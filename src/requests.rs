@@ -1,19 +1,55 @@
 use serde_derive::{Deserialize, Serialize};
 use tracing::{info, error};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 use steamworks::{Client, TicketForWebApiResponse};
-use tokio::sync::Mutex;
 use lazy_static::lazy_static;
 
 lazy_static! {
     static ref STEAM_ID: String = dotenv::var("STEAM_ID").expect("STEAM_ID must be set.");
     static ref STEAM_HEX: String = dotenv::var("STEAM_HEX").expect("STEAM_HEX must be set.");
-    static ref VERSION: String = dotenv::var("API_VERSION").expect("API_VERSION must be set.");
+    static ref VERSION: RwLock<String> = RwLock::new(initial_api_version());
     static ref PLAYER_ID: String = dotenv::var("PLAYER_ID").expect("PLAYER_ID must be set.");
     pub static ref STEAM_TOKEN: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(Option::None));
 }
 
 const STEAM_APP_ID: u32 = 1384160;
+/// The version GGST last asked for and the API_VERSION in .env it replaced, one per line
+const API_VERSION_FILE: &str = "api_version.txt";
+
+fn initial_api_version() -> String {
+    let configured = dotenv::var("API_VERSION").expect("API_VERSION must be set.");
+
+    match std::fs::read_to_string(API_VERSION_FILE).ok().and_then(|saved| saved_api_version(&saved, &configured)) {
+        Some(version) => {
+            info!("Using API version {} from {}", version, API_VERSION_FILE);
+            version
+        }
+        None => configured,
+    }
+}
+
+/// The saved version, unless API_VERSION changed since it was saved, a new one in .env wins
+fn saved_api_version(saved: &str, configured: &str) -> Option<String> {
+    let mut lines = saved.lines().map(str::trim);
+    let version = lines.next().filter(|v| !v.is_empty())?;
+    let replaced = lines.next()?;
+
+    (replaced == configured).then(|| version.to_owned())
+}
+
+/// Version every request is sent with
+pub fn api_version() -> String {
+    VERSION.read().unwrap().clone()
+}
+
+/// Switches to `version` and saves it for the next start. Only for a version GGST answered a request with.
+pub fn set_api_version(version: &str) {
+    *VERSION.write().unwrap() = version.to_owned();
+    let configured = dotenv::var("API_VERSION").unwrap_or_default();
+    if let Err(e) = std::fs::write(API_VERSION_FILE, format!("{version}\n{configured}\n")) {
+        error!("Couldn't save API version to {}: {}", API_VERSION_FILE, e);
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Request<T> {
@@ -21,6 +57,20 @@ pub struct Request<T> {
     body: T,
 }
 
+impl<T> Request<T> {
+    pub fn body(&self) -> &T {
+        &self.body
+    }
+
+    pub fn version(&self) -> &str {
+        &self.header.version
+    }
+
+    pub fn set_version(&mut self, version: String) {
+        self.header.version = version;
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct RequestHeader {
     player_id: String,
@@ -39,20 +89,69 @@ pub struct ReplayRequest {
     platforms: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ReplayQuery {
+/// Replay search, the same filters as the in game replay browser
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayQuery {
     int1: i64,
     int2: i64,
-    min_rank: i64,
-    max_rank: i64,
-    min_floor: i64,
-    max_floor: i64,
+    pub min_rank: i64,
+    pub max_rank: i64,
+    pub min_floor: i64,
+    pub max_floor: i64,
     seq1: Vec<()>,
-    char_1: i64,
-    char_2: i64,
+    /// Character of the searched player, -1 for any
+    pub char_1: i64,
+    /// Character of their opponent, -1 for any
+    pub char_2: i64,
     winner: i64,
     int5: i64,
-    int6: i64,
+    /// Match type, 7 for ranked matches only (I think)
+    pub int6: i64,
+}
+
+impl Default for ReplayQuery {
+    //Matches request when filtering on Ranked Match, for all ranks (min_rank -> max_rank)
+    fn default() -> Self {
+        ReplayQuery {
+            int1: -1,
+            int2: 0,
+            min_rank: 0, // No Rank
+            max_rank: 22, // Currently 22 for Vanquisher 3 Vindex
+            min_floor: 0,
+            max_floor: 99,
+            seq1: vec![],
+            char_1: -1,
+            char_2: -1,
+            winner: 0,
+            int5: 0,
+            int6: 7, // Ranked Matches Only (I think)
+        }
+    }
+}
+
+impl ReplayQuery {
+    pub fn ranks(min_rank: i64, max_rank: i64) -> Self {
+        ReplayQuery {
+            min_rank,
+            max_rank,
+            ..Default::default()
+        }
+    }
+
+    pub fn floors(min_floor: i64, max_floor: i64) -> Self {
+        ReplayQuery {
+            min_floor,
+            max_floor,
+            ..Default::default()
+        }
+    }
+
+    pub fn character(char_id: i64) -> Self {
+        ReplayQuery {
+            char_1: char_id,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -66,13 +165,13 @@ pub struct PlayerStatsRequest {
     int6: i64,
 }
 
-pub fn generate_player_stats_request(player_id: String) -> Request<PlayerStatsRequest> {
+pub fn generate_player_stats_request(player_id: String, token: &str) -> Request<PlayerStatsRequest> {
     Request {
         header: RequestHeader {
             player_id: PLAYER_ID.to_owned(),
-            token: std::fs::read_to_string("token.txt").unwrap(),
+            token: token.to_owned(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: api_version(),
             platform: 3, //PC
         },
         body: PlayerStatsRequest {
@@ -93,13 +192,13 @@ pub struct PlayerAvatarRequest {
     int1: i64,
 }
 
-pub fn generate_player_avatar_request(player_id: String) -> Request<PlayerAvatarRequest> {
+pub fn generate_player_avatar_request(player_id: String, token: &str) -> Request<PlayerAvatarRequest> {
     Request {
         header: RequestHeader {
             player_id: PLAYER_ID.to_owned(),
-            token: std::fs::read_to_string("token.txt").unwrap(),
+            token: token.to_owned(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: api_version(),
             platform: 3, //PC
         },
         body: PlayerAvatarRequest {
@@ -112,6 +211,7 @@ pub fn generate_player_avatar_request(player_id: String) -> Request<PlayerAvatar
 pub fn generate_replay_request(
     index: usize,
     replays_per_page: usize,
+    query: &ReplayQuery,
     token: &str,
 ) -> Request<ReplayRequest> {
     Request {
@@ -119,28 +219,14 @@ pub fn generate_replay_request(
             player_id: PLAYER_ID.to_owned(),
             token: token.to_owned(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: api_version(),
             platform: 3, //PC
         },
-        //Matches request when filtering on Ranked Match, for all ranks (min_rank -> max_rank)
         body: ReplayRequest {
             int1: 1,
             index,
             replays_per_page,
-            query: ReplayQuery {
-                int1: -1,
-                int2: 0,
-                min_rank: 0, // No Rank
-                max_rank: 22, // Currently 22 for Vanquisher 3 Vindex
-                min_floor: 0,
-                max_floor: 99,
-                seq1: vec![],
-                char_1: -1,
-                char_2: -1,
-                winner: 0,
-                int5: 0,
-                int6: 7, // Ranked Matches Only (I think)
-            },
+            query: query.clone(),
             platforms: 6, //All
         },
     }
@@ -168,7 +254,7 @@ pub fn generate_rank_match_legend_request(token: &str) -> Request<RankMatchLegen
             player_id: PLAYER_ID.to_owned(),
             token: token.to_owned(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: api_version(),
             platform: 3,
         },
         body: RankMatchLegendRequest {
@@ -199,7 +285,7 @@ pub fn generate_rank_match_mr_request(
             player_id: PLAYER_ID.to_owned(),
             token: token.to_owned(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: api_version(),
             platform: 3,
         },
         body: RankMatchMrRequest {
@@ -232,7 +318,7 @@ pub fn generate_rank_match_lp_request(
             player_id: PLAYER_ID.to_owned(),
             token: token.to_owned(),
             int1: 2,
-            version: VERSION.to_owned(),
+            version: api_version(),
             platform: 3,
         },
         body: RankMatchLpRequest {
@@ -245,26 +331,32 @@ pub fn generate_rank_match_lp_request(
     }
 }
 
-pub async fn generate_login_request() -> Request<LoginRequest> {
-    let client = Client::init_app(STEAM_APP_ID).unwrap();
+/// Asks Steam for a new auth ticket and wraps it in a login request.
+/// Errors instead of panicking, the caller retries with backoff.
+/// Blocks for up to 5 seconds waiting on Steam, run it with `spawn_blocking`
+pub fn generate_login_request() -> Result<Request<LoginRequest>, String> {
+    // A ticket is only good for one login, always ask for a new one
+    if let Ok(mut token) = STEAM_TOKEN.lock() {
+        *token = None;
+    }
+
+    let client = Client::init_app(STEAM_APP_ID).map_err(|e| format!("Steam init failed: {e}"))?;
     let user = client.user();
 
     let token = STEAM_TOKEN.clone();
-    if !token.clone().try_lock().unwrap().is_some() {
-        let _cb = client.register_callback(move |v: TicketForWebApiResponse| {
-            //println!("Got webapi auth response: {:?}", v)
-            let hex: String = v
-                .ticket
-                .iter()
-                .map(|b| format!("{:02X}", b).to_string())
-                .collect::<Vec<String>>()
-                .join("");
-            info!("Login steam token for strive {}", hex);
-            *token.try_lock().unwrap() = Some(hex);
-        });
-    } else {
-        info!("Already have steam token.");
-    };
+    let _cb = client.register_callback(move |v: TicketForWebApiResponse| {
+        //println!("Got webapi auth response: {:?}", v)
+        let hex: String = v
+            .ticket
+            .iter()
+            .map(|b| format!("{:02X}", b).to_string())
+            .collect::<Vec<String>>()
+            .join("");
+        info!("Login steam token for strive {}", hex);
+        if let Ok(mut token) = token.lock() {
+            *token = Some(hex);
+        }
+    });
 
     user.authentication_session_ticket_for_webapi("ggst-game.guiltygear.com");
 
@@ -272,16 +364,15 @@ pub async fn generate_login_request() -> Request<LoginRequest> {
         client.run_callbacks();
         std::thread::sleep(::std::time::Duration::from_millis(100));
 
-        let steam_token = STEAM_TOKEN.try_lock().unwrap();
+        let steam_token = STEAM_TOKEN.lock().ok().and_then(|t| t.clone());
 
-        if steam_token.is_some() {
-            let steam_token = steam_token.clone().unwrap();
-            return Request {
+        if let Some(steam_token) = steam_token {
+            return Ok(Request {
                 header: RequestHeader {
                     player_id: "".to_owned(),
                     token: "".to_owned(),
                     int1: 2,
-                    version: VERSION.to_owned(),
+                    version: api_version(),
                     platform: 3,
                 },
                 body: LoginRequest {
@@ -291,10 +382,25 @@ pub async fn generate_login_request() -> Request<LoginRequest> {
                     int2: 256,
                     steam_token,
                 },
-            };
+            });
         }
     }
 
     error!("Timed out waiting for steam token");
-    panic!();
+    Err("Timed out waiting for steam token".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_api_version_until_env_changes() {
+        assert_eq!(saved_api_version("0.4.9\n0.4.8\n", "0.4.8").as_deref(), Some("0.4.9"));
+        // API_VERSION was fixed in .env since
+        assert_eq!(saved_api_version("0.4.9\n0.4.8\n", "0.5.0"), None);
+        // Saved without the version it replaced
+        assert_eq!(saved_api_version("0.4.9\n", "0.4.8"), None);
+        assert_eq!(saved_api_version("", "0.4.8"), None);
+    }
 }
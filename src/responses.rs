@@ -9,9 +9,10 @@ pub struct Response<T> {
 #[derive(Deserialize, Debug)]
 pub struct ResponseHeader {
     pub token: String,
-    _int1: i64,
+    pub status: i64,
     _date: String,
-    _version1: String,
+    /// API version the server wants, newer than ours after a patch
+    pub version: String,
     _version2: String,
     _version3: String,
     _string1: String,
@@ -0,0 +1,334 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::AsyncConnection;
+use tokio::time;
+use tracing::{error, info};
+
+use crate::error::Error;
+use crate::ggst_api::GgstApi;
+use crate::{db, imdb, metrics, pull};
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+/// Wait after a failed run before trying again, instead of a whole interval
+const RETRY_AFTER_SECONDS: i64 = 10 * 60;
+/// Frees the lock of an instance that died mid-run, longer than any job takes
+const LOCK_TTL_SECONDS: u64 = 2 * 60 * 60;
+
+pub struct Job {
+    pub name: &'static str,
+    pub interval_seconds: i64,
+    /// Redis key set to the finish time after a successful run, the api shows it as "last update"
+    pub marks: Option<&'static str>,
+}
+
+pub const JOBS: &[Job] = &[
+    Job { name: "update_stats", interval_seconds: HOUR, marks: Some("last_update_hourly") },
+    Job { name: "sync_legend_leaderboard", interval_seconds: HOUR, marks: None },
+    Job { name: "sync_global_leaderboards", interval_seconds: DAY, marks: Some("last_update_daily") },
+    Job { name: "update_popularity", interval_seconds: DAY, marks: None },
+    Job { name: "update_matchups", interval_seconds: DAY, marks: None },
+    Job { name: "update_distribution", interval_seconds: DAY, marks: None },
+    Job { name: "update_performance", interval_seconds: DAY, marks: None },
+];
+
+/// What `cargo run hourly` and `cargo run daily` run
+pub const HOURLY_JOBS: &[&str] = &["sync_legend_leaderboard", "update_stats"];
+pub const DAILY_JOBS: &[&str] = &[
+    "sync_global_leaderboards",
+    "update_popularity",
+    "update_matchups",
+    "update_distribution",
+    "update_performance",
+];
+
+pub fn find_job(name: &str) -> Option<&'static Job> {
+    JOBS.iter().find(|job| job.name == name)
+}
+
+/// A successful run `interval_seconds` after the last one, a failed one is retried sooner
+pub fn next_run(
+    job: &Job,
+    last_success: Option<NaiveDateTime>,
+    last_attempt: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+) -> NaiveDateTime {
+    [
+        last_success.map(|t| t + chrono::Duration::seconds(job.interval_seconds)),
+        last_attempt.map(|t| t + chrono::Duration::seconds(RETRY_AFTER_SECONDS)),
+    ]
+    .into_iter()
+    .flatten()
+    .max()
+    .unwrap_or(now)
+}
+
+async fn run(
+    job: &str,
+    conn: &mut crate::Connection<'_>,
+    redis: &mut crate::RedisConnection<'_>,
+    api: &dyn GgstApi,
+) -> Result<(), Error> {
+    match job {
+        "update_stats" => pull::update_stats(conn, redis).await,
+        "sync_legend_leaderboard" => pull::sync_legend_leaderboard(conn, redis, api).await,
+        "sync_global_leaderboards" => {
+            pull::sync_global_leaderboards(conn, redis, api).await?;
+            // Needs today's snapshot, which the sync just saved
+            pull::enqueue_rank_webhooks(conn).await?;
+            // The replay puller sets it again within a minute, health allows for that after a daily run
+            imdb::clear_latest_game_time(redis).await
+        }
+        "update_popularity" => pull::update_popularity(conn, redis).await,
+        "update_matchups" => pull::update_matchups(conn, redis).await,
+        "update_distribution" => pull::update_distribution(conn, redis).await,
+        "update_performance" => pull::update_performance(conn, redis).await,
+        _ => Err(Error::NotFound(format!("Unknown job {}", job))),
+    }
+}
+
+/// Runs `job` in its own transaction and records the run, unless another instance holds its lock.
+/// Returns false in that case.
+pub async fn run_job(job: &'static Job, state: &crate::AppState, owner: &str) -> Result<bool, Error> {
+    let mut redis = state.redis_pool.get().await?;
+    if !imdb::acquire_job_lock(job.name, owner, LOCK_TTL_SECONDS, &mut redis).await? {
+        info!("{} is running on another instance, skipping", job.name);
+        return Ok(false);
+    }
+
+    let result = run_locked(job, state).await;
+
+    if let Err(e) = imdb::release_job_lock(job.name, owner, &mut redis).await {
+        error!("Unlocking {} failed: {e}", job.name);
+    }
+
+    result.map(|_| true)
+}
+
+async fn run_locked(job: &'static Job, state: &crate::AppState) -> Result<(), Error> {
+    info!("Running {}", job.name);
+    let run_id = {
+        let mut connection = state.db_pool.get().await?;
+        db::start_job_run(job.name, chrono::Utc::now().naive_utc(), &mut connection).await?
+    };
+    let start = std::time::Instant::now();
+
+    // In its own task, so a job that panics is recorded as a failed run and the other jobs still run
+    let result = match tokio::spawn(run_in_transaction(job, state.clone())).await {
+        Ok(result) => result,
+        Err(e) => Err(Error::Internal(format!("{} panicked: {e}", job.name))),
+    };
+
+    metrics::record_job(job.name, start.elapsed(), result.is_ok());
+    let finished = chrono::Utc::now().naive_utc();
+    let mut connection = state.db_pool.get().await?;
+    db::finish_job_run(run_id, finished, result.as_ref().err().map(|e| e.to_string()), &mut connection).await?;
+
+    match result {
+        Ok(()) => {
+            info!("{} - Done", job.name);
+            if let Some(key) = job.marks {
+                let mut redis = state.redis_pool.get().await?;
+                imdb::set_update_time(key, finished, &mut redis).await?;
+            }
+        }
+        Err(e) => error!("{} failed: {e}", job.name),
+    }
+
+    Ok(())
+}
+
+/// A failed job rolls back its Postgres writes, Redis keys it set before failing keep their new values
+async fn run_in_transaction(job: &'static Job, state: crate::AppState) -> Result<(), Error> {
+    let mut connection = state.db_pool.get().await?;
+    let mut redis = state.redis_pool.get().await?;
+    let api = &*state.ggst;
+
+    connection
+        .transaction::<_, Error, _>(|conn| {
+            async move { run(job.name, conn, &mut redis, api).await }.scope_boxed()
+        })
+        .await
+}
+
+/// Runs `job` when it's `due` or was triggered. A trigger is only used up once the job ran, one that
+/// finds the job locked by another instance is kept for the next tick.
+async fn run_if_due(job: &'static Job, due: bool, state: &crate::AppState, owner: &str) -> Result<(), Error> {
+    let triggered = {
+        let mut redis = state.redis_pool.get().await?;
+        imdb::take_job_trigger(job.name, &mut redis).await?
+    };
+    if !triggered && !due {
+        return Ok(());
+    }
+
+    let result = run_job(job, state, owner).await;
+    if triggered && !matches!(result, Ok(true)) {
+        let mut redis = state.redis_pool.get().await?;
+        imdb::trigger_job(job.name, &mut redis).await?;
+    }
+    result.map(|_| ())
+}
+
+/// Runs every due or triggered job once
+async fn tick(state: &crate::AppState, owner: &str) -> Result<(), Error> {
+    let now = chrono::Utc::now().naive_utc();
+
+    let (attempts, successes) = {
+        let mut connection = state.db_pool.get().await?;
+        (
+            db::get_latest_job_runs(false, &mut connection).await?,
+            db::get_latest_job_runs(true, &mut connection).await?,
+        )
+    };
+    let started = |runs: &[crate::models::JobRun], job: &Job| {
+        runs.iter().find(|r| r.job == job.name).map(|r| r.started)
+    };
+
+    for job in JOBS {
+        let due = next_run(job, started(&successes, job), started(&attempts, job), now) <= now;
+        if let Err(e) = run_if_due(job, due, state, owner).await {
+            error!("{} failed: {e}", job.name);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_continuous(state: crate::AppState) {
+    // Tells this instance's locks apart from another's
+    let owner = uuid::Uuid::new_v4().to_string();
+    let mut interval = time::interval(Duration::from_secs(pull::ONE_MINUTE - 10));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = tick(&state, &owner).await {
+            error!("Scheduler tick failed: {e}");
+        }
+    }
+}
+
+/// Runs `names` right away whether they're due or not, for `cargo run hourly` and the like
+pub async fn run_now(state: crate::AppState, names: &[&str]) {
+    let owner = uuid::Uuid::new_v4().to_string();
+
+    for name in names {
+        let Some(job) = find_job(name) else {
+            error!("Unknown job {name}");
+            continue;
+        };
+
+        if let Err(e) = run_job(job, &state, &owner).await {
+            error!("{name} failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn next_run_after_interval_or_retry() {
+        let job = find_job("update_stats").unwrap();
+        let now = at("2026-10-18 12:00:00");
+
+        // Never ran
+        assert_eq!(next_run(job, None, None, now), now);
+        // Last run succeeded
+        assert_eq!(
+            next_run(job, Some(at("2026-10-18 11:30:00")), Some(at("2026-10-18 11:30:00")), now),
+            at("2026-10-18 12:30:00")
+        );
+        // Failed since, retried after RETRY_AFTER_SECONDS
+        assert_eq!(
+            next_run(job, Some(at("2026-10-18 09:00:00")), Some(at("2026-10-18 11:55:00")), now),
+            at("2026-10-18 12:05:00")
+        );
+    }
+
+    #[tokio::test]
+    async fn run_job_skips_a_job_locked_elsewhere() {
+        let api = crate::ggst_api::FixtureApi::new(crate::ggst_api::fixtures::dir("scheduler-lock"));
+        let Some(state) = crate::test_state(std::sync::Arc::new(api)).await else {
+            return;
+        };
+        let mut redis = state.redis_pool.get().await.unwrap();
+        let job = find_job("update_distribution").unwrap();
+
+        assert!(imdb::acquire_job_lock(job.name, "other", 60, &mut redis).await.unwrap());
+        assert!(!run_job(job, &state, "this").await.unwrap());
+
+        // Only the holder unlocks it
+        imdb::release_job_lock(job.name, "this", &mut redis).await.unwrap();
+        assert!(imdb::is_job_locked(job.name, &mut redis).await.unwrap());
+        imdb::release_job_lock(job.name, "other", &mut redis).await.unwrap();
+        assert!(!imdb::is_job_locked(job.name, &mut redis).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn trigger_survives_a_job_locked_elsewhere() {
+        let api = crate::ggst_api::FixtureApi::new(crate::ggst_api::fixtures::dir("scheduler-trigger"));
+        let Some(state) = crate::test_state(std::sync::Arc::new(api)).await else {
+            return;
+        };
+        let mut redis = state.redis_pool.get().await.unwrap();
+        let job = find_job("update_popularity").unwrap();
+
+        assert!(imdb::acquire_job_lock(job.name, "other", 60, &mut redis).await.unwrap());
+        imdb::trigger_job(job.name, &mut redis).await.unwrap();
+
+        run_if_due(job, false, &state, "this").await.unwrap();
+        let still_triggered = imdb::take_job_trigger(job.name, &mut redis).await.unwrap();
+        imdb::release_job_lock(job.name, "other", &mut redis).await.unwrap();
+
+        assert!(still_triggered);
+    }
+
+    #[tokio::test]
+    async fn failed_job_finishes_its_run_and_unlocks() {
+        use diesel::prelude::*;
+        use diesel_async::RunQueryDsl;
+
+        static BROKEN: Job = Job { name: "no_such_job", interval_seconds: HOUR, marks: None };
+
+        let api = crate::ggst_api::FixtureApi::new(crate::ggst_api::fixtures::dir("scheduler-failed"));
+        let Some(state) = crate::test_state(std::sync::Arc::new(api)).await else {
+            return;
+        };
+
+        assert!(run_job(&BROKEN, &state, "this").await.unwrap());
+
+        let mut redis = state.redis_pool.get().await.unwrap();
+        let locked = imdb::is_job_locked(BROKEN.name, &mut redis).await.unwrap();
+
+        let mut connection = state.db_pool.get().await.unwrap();
+        let runs: Vec<(Option<bool>, Option<String>)> = crate::schema::job_runs::table
+            .select((crate::schema::job_runs::success, crate::schema::job_runs::error))
+            .filter(crate::schema::job_runs::job.eq(BROKEN.name))
+            .load(&mut connection)
+            .await
+            .unwrap();
+        diesel::delete(crate::schema::job_runs::table.filter(crate::schema::job_runs::job.eq(BROKEN.name)))
+            .execute(&mut connection)
+            .await
+            .unwrap();
+
+        assert!(!locked);
+        assert_eq!(runs, vec![(Some(false), Some("Unknown job no_such_job".to_string()))]);
+    }
+
+    #[test]
+    fn every_job_group_is_registered() {
+        for name in HOURLY_JOBS.iter().chain(DAILY_JOBS) {
+            assert!(find_job(name).is_some(), "{name}");
+        }
+    }
+}
@@ -1,5 +1,12 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    follows (player_id, followed_id) {
+        player_id -> Int8,
+        followed_id -> Int8,
+    }
+}
+
 diesel::table! {
     games (timestamp, id_a, id_b) {
         timestamp -> Timestamp,
@@ -16,6 +23,30 @@ diesel::table! {
         game_floor -> Int2,
         value_a -> Int8,
         value_b -> Int8,
+        inserted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    job_runs (id) {
+        id -> Int8,
+        job -> Text,
+        started -> Timestamp,
+        finished -> Nullable<Timestamp>,
+        success -> Nullable<Bool>,
+        error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    leaderboard_snapshots (snapshot_date, board, player_id, char_id) {
+        snapshot_date -> Date,
+        board -> Text,
+        player_id -> Int8,
+        char_id -> Int2,
+        rank -> Int4,
+        name -> Text,
+        rating -> Int8,
     }
 }
 
@@ -41,6 +72,9 @@ diesel::table! {
         platform -> Int2,
         api_key -> Nullable<Varchar>,
         rcode_check_code -> Nullable<Varchar>,
+        rcode_check_expiry -> Nullable<Timestamp>,
+        private -> Bool,
+        dataset_opt_in -> Bool,
     }
 }
 
@@ -53,13 +87,40 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Int8,
+        player_id -> Int8,
+        payload -> Text,
+        attempts -> Int4,
+        next_attempt -> Timestamp,
+        last_error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    webhooks (player_id) {
+        player_id -> Int8,
+        url -> Text,
+        secret -> Text,
+    }
+}
+
+diesel::joinable!(follows -> players (player_id));
 diesel::joinable!(player_names -> players (id));
 diesel::joinable!(player_ratings -> players (id));
+diesel::joinable!(webhook_deliveries -> webhooks (player_id));
+diesel::joinable!(webhooks -> players (player_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    follows,
     games,
+    job_runs,
+    leaderboard_snapshots,
     player_names,
     player_ratings,
     players,
     tags,
+    webhook_deliveries,
+    webhooks,
 );